@@ -1,5 +1,5 @@
 ; cancel terms
 
 (=> (+ a (- a) ..) ..)
-(=> (* a (/ a) ..) ..)
-(=> (* n a (/ (* a d))) (* n (/ d)))
+(=> (* a (/ a) ..) .. (if (nonzero a)))
+(=> (* n a (/ (* a d))) (* n (/ d)) (if (nonzero a)))
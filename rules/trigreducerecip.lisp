@@ -1,8 +1,4 @@
 ; inverse reciprocal identities
-(=> (/ (sin u)) (csc u))
-(=> (/ (cos u)) (sec u))
-(=> (/ (tan u)) (cot u))
+(=> (/ (F u)) ((torecip F) u))
 
-(=> (/ (* (sin u) d)) (* (csc u) (/ d)))
-(=> (/ (* (cos u) d)) (* (sec u) (/ d)))
-(=> (/ (* (tan u) d)) (* (cot u) (/ d)))
+(=> (/ (* (F u) d)) (* ((torecip F) u) (/ d)))
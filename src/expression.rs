@@ -1,66 +1,106 @@
+mod eval;
 mod multiset;
+#[cfg(feature = "serde")]
+mod number;
 
-use multiset::*;
+pub use eval::*;
+use {multiset::*, num_bigint::BigInt, num_rational::BigRational};
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expression {
     Concrete(Concrete),
     Variable(Variable),
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Concrete {
     Variadic(Variadic),
     Unary(Unary),
+    Power(Power),
     Literal(Literal),
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Variadic {
     pub terms: MultiSet<Expression>,
     pub kind: VariadicKind,
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum VariadicKind {
     Addition,
     Multiplication,
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Unary {
     pub argument: Box<Expression>,
     pub kind: UnaryKind,
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum UnaryKind {
     Negation,
     Reciprocal,
     Named { id: FuncId },
+    // stands for a named function in patterns, the relations are applied to the function it
+    // binds to starting from the last one, like recip in ((recip F) u)
+    Variable { id: VarId, relations: Vec<FuncId> },
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Power {
+    pub base: Box<Expression>,
+    pub exponent: Box<Expression>,
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FuncId(String);
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Literal {
     pub value: LiteralValue,
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LiteralValue {
-    Integer(i32),
+    Integer(#[cfg_attr(feature = "serde", serde(with = "number::integer"))] BigInt),
+    // never has a denominator of 1, those are integers
+    Rational(#[cfg_attr(feature = "serde", serde(with = "number::rational"))] BigRational),
     Constant(String),
 }
 
 // ids MUST have no repeats across statements
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Variable {
     pub id: VarId,
+    pub kind: VarKind,
+}
+
+// what a pattern variable is allowed to bind to, bindings are keyed by the untyped variable
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum VarKind {
+    Any,
+    Integer,
+    Literal,
+    Function,
+    Variable,
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VarId(String);
 
 impl From<Variadic> for Expression {
@@ -75,6 +115,12 @@ impl From<Unary> for Expression {
     }
 }
 
+impl From<Power> for Expression {
+    fn from(value: Power) -> Self {
+        Self::Concrete(Concrete::Power(value))
+    }
+}
+
 impl From<Literal> for Expression {
     fn from(value: Literal) -> Self {
         Self::Concrete(Concrete::Literal(value))
@@ -105,6 +151,15 @@ impl Unary {
     }
 }
 
+impl Power {
+    pub fn new(base: Expression, exponent: Expression) -> Self {
+        Self {
+            base: Box::new(base),
+            exponent: Box::new(exponent),
+        }
+    }
+}
+
 impl UnaryKind {
     pub fn named(id: impl ToString) -> Self {
         let id = FuncId::new(id);
@@ -133,10 +188,37 @@ impl Literal {
 
 impl From<i32> for LiteralValue {
     fn from(value: i32) -> Self {
+        Self::Integer(value.into())
+    }
+}
+
+impl From<BigInt> for LiteralValue {
+    fn from(value: BigInt) -> Self {
         Self::Integer(value)
     }
 }
 
+impl From<BigRational> for LiteralValue {
+    fn from(value: BigRational) -> Self {
+        if value.is_integer() {
+            Self::Integer(value.to_integer())
+        } else {
+            Self::Rational(value)
+        }
+    }
+}
+
+impl LiteralValue {
+    // exact value of integers and rationals
+    pub fn number(&self) -> Option<BigRational> {
+        match self {
+            LiteralValue::Integer(i) => Some(BigRational::from_integer(i.clone())),
+            LiteralValue::Rational(r) => Some(r.clone()),
+            LiteralValue::Constant(_) => None,
+        }
+    }
+}
+
 impl From<&str> for LiteralValue {
     fn from(value: &str) -> Self {
         Self::Constant(value.to_string())
@@ -145,8 +227,39 @@ impl From<&str> for LiteralValue {
 
 impl Variable {
     pub fn new(id: impl ToString) -> Self {
+        Self::typed(id, VarKind::Any)
+    }
+
+    pub fn typed(id: impl ToString, kind: VarKind) -> Self {
         let id = VarId::new(id);
-        Self { id }
+        Self { id, kind }
+    }
+
+    pub fn untyped(&self) -> Self {
+        Self::new(self.id.as_ref())
+    }
+}
+
+impl VarKind {
+    pub fn admits(&self, expr: &Expression) -> bool {
+        match self {
+            VarKind::Any => true,
+            VarKind::Integer => matches!(
+                expr,
+                Expression::Concrete(Concrete::Literal(Literal {
+                    value: LiteralValue::Integer(_)
+                }))
+            ),
+            VarKind::Literal => matches!(expr, Expression::Concrete(Concrete::Literal(_))),
+            VarKind::Function => matches!(
+                expr,
+                Expression::Concrete(Concrete::Unary(Unary {
+                    kind: UnaryKind::Named { .. },
+                    ..
+                }))
+            ),
+            VarKind::Variable => matches!(expr, Expression::Variable(_)),
+        }
     }
 }
 
@@ -161,3 +274,56 @@ impl AsRef<String> for VarId {
         &self.0
     }
 }
+
+// number of nodes in the expression tree
+pub fn expression_size(expr: &Expression) -> usize {
+    match expr {
+        Expression::Concrete(Concrete::Variadic(Variadic { terms, .. })) => {
+            1 + terms.iter().map(expression_size).sum::<usize>()
+        }
+        Expression::Concrete(Concrete::Unary(Unary { argument, .. })) => {
+            1 + expression_size(argument)
+        }
+        Expression::Concrete(Concrete::Power(Power { base, exponent })) => {
+            1 + expression_size(base) + expression_size(exponent)
+        }
+        _ => 1,
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde() {
+    use crate::{rewrite::*, text::*};
+
+    let expr = Lisp::parse("(+ (* x x 3/4) ((recip F) ?n:int) -12 PI x)").unwrap();
+    let json = serde_json::to_string(&expr).unwrap();
+    assert_eq!(serde_json::from_str::<Expression>(&json).unwrap(), expr);
+
+    // terms are written in order whatever order they were built in
+    let sum = |names: [&str; 3]| {
+        let sum = Variadic::new(
+            names.map(|n| Variable::new(n).into()),
+            VariadicKind::Addition,
+        );
+        serde_json::to_string(&sum).unwrap()
+    };
+    assert_eq!(sum(["b", "a", "b"]), sum(["a", "b", "b"]));
+
+    assert_eq!(
+        serde_json::to_string(&Literal::new(BigRational::new(6.into(), 8.into()))).unwrap(),
+        r#"{"value":{"Rational":"3/4"}}"#
+    );
+    for rational in ["6/8", "3/1", "1/0", "3/-4"] {
+        let json = format!(r#"{{"value":{{"Rational":"{rational}"}}}}"#);
+        assert!(
+            serde_json::from_str::<Literal>(&json).is_err(),
+            "{rational}"
+        );
+    }
+
+    let rules = Lisp::parse_ruleset("(=> (* a (/ a) ..) .. (if (nonzero a))) (== (sin x) (cos x))")
+        .unwrap();
+    let json = serde_json::to_string(&rules).unwrap();
+    assert!(serde_json::from_str::<Vec<Rule>>(&json).unwrap() == rules);
+}
@@ -0,0 +1,123 @@
+use {super::*, num_traits::ToPrimitive, std::collections::BTreeMap};
+
+// numeric value of an expression, None if it uses a function or constant without a
+// built-in meaning or a variable that isn't assigned
+pub fn evaluate(expr: &Expression, assignment: &BTreeMap<Variable, f64>) -> Option<f64> {
+    match expr {
+        Expression::Concrete(c) => match c {
+            Concrete::Variadic(Variadic { terms, kind }) => {
+                let mut values = terms.iter().map(|t| evaluate(t, assignment));
+
+                match kind {
+                    VariadicKind::Addition => values.try_fold(0.0, |a, b| Some(a + b?)),
+                    VariadicKind::Multiplication => values.try_fold(1.0, |a, b| Some(a * b?)),
+                }
+            }
+            Concrete::Unary(Unary { argument, kind }) => {
+                let x = evaluate(argument, assignment)?;
+
+                match kind {
+                    UnaryKind::Negation => Some(-x),
+                    UnaryKind::Reciprocal => Some(1.0 / x),
+                    UnaryKind::Named { id } => evaluate_function(id, x),
+                    UnaryKind::Variable { .. } => None,
+                }
+            }
+            Concrete::Power(Power { base, exponent }) => {
+                Some(evaluate(base, assignment)?.powf(evaluate(exponent, assignment)?))
+            }
+            Concrete::Literal(Literal { value }) => match value {
+                LiteralValue::Integer(i) => i.to_f64(),
+                LiteralValue::Rational(r) => r.to_f64(),
+                LiteralValue::Constant(c) => match c.as_str() {
+                    "PI" => Some(std::f64::consts::PI),
+                    "E" => Some(std::f64::consts::E),
+                    _ => None,
+                },
+            },
+        },
+        Expression::Variable(v) => assignment.get(v).copied(),
+    }
+}
+
+pub fn evaluate_function(id: &FuncId, x: f64) -> Option<f64> {
+    match id.as_ref().as_str() {
+        "sin" => Some(x.sin()),
+        "cos" => Some(x.cos()),
+        "tan" => Some(x.tan()),
+        "sec" => Some(1.0 / x.cos()),
+        "csc" => Some(1.0 / x.sin()),
+        "cot" => Some(1.0 / x.tan()),
+        _ => None,
+    }
+}
+
+// every variable in the expression, each listed once
+pub fn variables(expr: &Expression) -> Vec<Variable> {
+    fn rec(expr: &Expression, output: &mut Vec<Variable>) {
+        match expr {
+            Expression::Concrete(Concrete::Variadic(Variadic { terms, .. })) => {
+                terms.iter().for_each(|t| rec(t, output))
+            }
+            Expression::Concrete(Concrete::Unary(Unary { argument, .. })) => rec(argument, output),
+            Expression::Concrete(Concrete::Power(Power { base, exponent })) => {
+                rec(base, output);
+                rec(exponent, output)
+            }
+            Expression::Concrete(Concrete::Literal(_)) => {}
+            Expression::Variable(v) => {
+                if !output.contains(v) {
+                    output.push(v.clone())
+                }
+            }
+        }
+    }
+
+    let mut output = Vec::new();
+    rec(expr, &mut output);
+    output
+}
+
+// every function variable in the expression, each listed once
+pub fn function_variables(expr: &Expression) -> Vec<VarId> {
+    fn rec(expr: &Expression, output: &mut Vec<VarId>) {
+        match expr {
+            Expression::Concrete(Concrete::Variadic(Variadic { terms, .. })) => {
+                terms.iter().for_each(|t| rec(t, output))
+            }
+            Expression::Concrete(Concrete::Unary(Unary { argument, kind })) => {
+                if let UnaryKind::Variable { id, .. } = kind {
+                    if !output.contains(id) {
+                        output.push(id.clone())
+                    }
+                }
+                rec(argument, output)
+            }
+            Expression::Concrete(Concrete::Power(Power { base, exponent })) => {
+                rec(base, output);
+                rec(exponent, output)
+            }
+            _ => {}
+        }
+    }
+
+    let mut output = Vec::new();
+    rec(expr, &mut output);
+    output
+}
+
+#[test]
+fn test_evaluate() {
+    use crate::text::*;
+
+    let expr = Lisp::parse("(+ (* (sin x) (sin x)) (^ (cos x) 2) (- (/ y)))").unwrap();
+    let assignment = BTreeMap::from([(Variable::new("x"), 0.7), (Variable::new("y"), 4.0)]);
+
+    assert_eq!(
+        variables(&expr),
+        vec![Variable::new("x"), Variable::new("y")]
+    );
+    assert!((evaluate(&expr, &assignment).unwrap() - 0.75).abs() < 1e-12);
+    assert_eq!(evaluate(&Lisp::parse("(f x)").unwrap(), &assignment), None);
+    assert_eq!(evaluate(&Lisp::parse("z").unwrap(), &assignment), None);
+}
@@ -1,7 +1,7 @@
 use std::collections::BTreeSet;
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Debug, Hash)]
-pub struct MultiSet<T>(BTreeSet<(T, u8)>);
+pub struct MultiSet<T>(BTreeSet<(T, usize)>);
 
 impl<T: Ord + Clone> FromIterator<T> for MultiSet<T> {
     fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
@@ -26,8 +26,19 @@ impl<T: Ord> MultiSet<T> {
     pub fn iter(&self) -> impl Iterator<Item = &T> {
         self.0.iter().map(|(v, _)| v)
     }
+}
+
+// a sorted sequence of the items with repeats, the counts are rebuilt when it's read back
+#[cfg(feature = "serde")]
+impl<T: Ord + serde::Serialize> serde::Serialize for MultiSet<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
 
-    pub fn is_empty(&self) -> bool {
-        self.0.is_empty()
+#[cfg(feature = "serde")]
+impl<'de, T: Ord + Clone + serde::Deserialize<'de>> serde::Deserialize<'de> for MultiSet<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::<T>::deserialize(deserializer).map(Self::from_iter)
     }
 }
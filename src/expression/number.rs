@@ -0,0 +1,56 @@
+use {
+    num_bigint::BigInt,
+    num_rational::BigRational,
+    serde::{de::Error, Deserialize, Deserializer, Serializer},
+};
+
+// exact numbers are serialized as their decimal strings, like "-12" and "3/4", so they read the
+// same in any format and don't depend on how num stores its digits
+
+pub mod integer {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(n: &BigInt, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(n)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BigInt, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse()
+            .map_err(|_| D::Error::custom(format!("invalid integer `{s}`")))
+    }
+}
+
+pub mod rational {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(r: &BigRational, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("{}/{}", r.numer(), r.denom()))
+    }
+
+    // only rationals in lowest terms are accepted, so equal values always have equal forms
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<BigRational, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        let invalid = || D::Error::custom(format!("invalid rational `{s}`"));
+
+        let (n, d) = s.split_once('/').ok_or_else(invalid)?;
+        let (n, d): (BigInt, BigInt) = (
+            n.parse().map_err(|_| invalid())?,
+            d.parse().map_err(|_| invalid())?,
+        );
+
+        // 1 makes an integer, and BigRational::new would panic on 0
+        if d <= BigInt::from(1) {
+            return Err(invalid());
+        }
+
+        let r = BigRational::new(n.clone(), d.clone());
+        if *r.numer() == n && *r.denom() == d {
+            Ok(r)
+        } else {
+            Err(invalid())
+        }
+    }
+}
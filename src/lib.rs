@@ -0,0 +1,27 @@
+//! A small computer algebra system for proving identities by rewriting.
+//!
+//! - [`expression`] is the expression tree, with exact numbers and pattern variables.
+//! - [`rewrite`] matches patterns and applies rules.
+//! - [`text`] reads and writes expressions and rules in lisp and infix syntax, Content MathML
+//!   and JSON trees, and writes them as LaTeX and Presentation MathML.
+//! - [`solve`] loads rulesets and searches for proofs, mainly through [`solve::RulesetLoader`]
+//!   and [`solve::IdentityProver`].
+//!
+//! ```
+//! use identity::{rewrite::*, solve::*, text::*};
+//!
+//! let rulesets = RulesetLoader::new().load().unwrap().rules;
+//!
+//! let expr = Lisp::parse("(* a (/ a))").unwrap();
+//! let rule = &Lisp::parse_ruleset("(=> (* x (/ x)) 1)").unwrap()[0];
+//! assert_eq!(apply_rule(expr, rule), Ok(Lisp::parse("1").unwrap()));
+//!
+//! let identity = Infix::parse_ruleset("tan(x) cos(x) = sin(x)").unwrap().remove(0);
+//! let report = IdentityProver::new(rulesets).prove(&identity.pattern, &identity.replacement);
+//! assert!(matches!(report.verdict, Verdict::Proved(_)));
+//! ```
+
+pub mod expression;
+pub mod rewrite;
+pub mod solve;
+pub mod text;
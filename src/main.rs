@@ -1,150 +1,1298 @@
-mod expression;
-mod rewrite;
-mod solve;
-mod text;
-
 use {
-    crate::{expression::*, rewrite::*, solve::*, text::*},
     clap::Parser,
+    identity::{expression::*, rewrite::*, solve::*, text::*},
     std::{
         collections::BTreeMap,
         io::{prelude::*, stdin, stdout},
+        path::{Path, PathBuf},
     },
 };
 
+// how typed input is named in error messages
+const INPUT: &str = "<input>";
+
+// shell command for applying one rule at one position, followed by the rulesets to pick from
+const STEP_COMMAND: &str = "step";
+
 #[derive(Parser)]
 struct Args {
     #[command(subcommand)]
     mode: Mode,
+    /// directory of rulesets to load on top of the built-in ones, can be repeated, and a
+    /// ruleset replaces any earlier one with the same name
+    #[arg(long, global = true, value_name = "DIR")]
+    rules: Vec<PathBuf>,
+    /// don't load the rulesets built into the binary
+    #[arg(long, global = true)]
+    no_builtin_rules: bool,
 }
 
 #[derive(clap::Subcommand, Clone)]
 enum Mode {
     /// interactive shell for simplifying expressions with commands
-    Shell,
+    Shell {
+        /// syntax of expressions typed in and printed
+        #[arg(long, value_enum, default_value_t = FormatArg::Lisp)]
+        format: FormatArg,
+        #[command(flatten)]
+        limits: LimitArgs,
+    },
     /// interactive shell for verifying identities, like Shell but automatic
-    Auto,
+    Auto {
+        #[command(flatten)]
+        search: SearchArgs,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// numerically check that both sides of every loaded rule agree
+    CheckRules,
+    /// check that rulesets terminate along with simplify and that their critical pairs join
+    Analyze {
+        /// rulesets to analyze, all of them if none are given
+        rulesets: Vec<String>,
+        /// exit non-zero if any ruleset isn't shown to terminate or any pair doesn't join
+        #[arg(long)]
+        strict: bool,
+        #[command(flatten)]
+        limits: LimitArgs,
+    },
+    /// prove every identity in files with one per line, and exit non-zero if any regressed,
+    /// giving each 10 seconds unless --timeout says otherwise
+    Verify {
+        /// files of identities, like (== a b), a trailing comment with `expect disproved` or
+        /// `expect unknown` marks ones that aren't expected to be proved
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        #[command(flatten)]
+        search: SearchArgs,
+        #[command(flatten)]
+        output: OutputArgs,
+        /// write the proof of each identity into this directory
+        #[arg(long, value_name = "DIR")]
+        output_dir: Option<PathBuf>,
+    },
+}
+
+#[derive(clap::Args, Clone)]
+struct SearchArgs {
+    /// backend used to search for a proof
+    #[arg(long, value_enum, default_value_t = EngineArg::Graph)]
+    engine: EngineArg,
+    /// cost function ordering the best-first search
+    #[arg(long, value_enum, default_value_t = HeuristicArg::Size)]
+    heuristic: HeuristicArg,
+    /// how each ruleset rewrites an expression in the best-first search
+    #[arg(long, value_enum, default_value_t = StepArg::Fixpoint)]
+    step: StepArg,
+    /// stop the best-first search after expanding this many expressions
+    #[arg(long)]
+    max_nodes: Option<usize>,
+    /// don't expand expressions further than this many steps from either side
+    #[arg(long)]
+    max_depth: Option<usize>,
+    /// give up on an identity after this many seconds, with any engine
+    #[arg(long)]
+    timeout: Option<f64>,
+    #[command(flatten)]
+    limits: LimitArgs,
+}
+
+#[derive(clap::Args, Clone, Copy)]
+struct LimitArgs {
+    /// give up on rewriting with a ruleset that needs more rewrites than this, as diverged
+    #[arg(long, default_value_t = DEFAULT_MAX_REWRITES)]
+    max_rewrites: usize,
+    /// give up on rewriting with a ruleset once an expression has more nodes than this
+    #[arg(long, default_value_t = DEFAULT_MAX_TERM_SIZE)]
+    max_term_size: usize,
+}
+
+#[derive(clap::Args, Clone, Copy)]
+struct OutputArgs {
+    /// syntax of identities typed in and proofs printed
+    #[arg(long, value_enum, default_value_t = FormatArg::Lisp)]
+    format: FormatArg,
+    /// also print the shortest proof as a LaTeX align* block
+    #[arg(long)]
+    latex: bool,
+    /// also print the shortest proof as a Presentation MathML table
+    #[arg(long)]
+    mathml: bool,
+    /// break the shortest proof down into single rule applications and check them again
+    #[arg(long)]
+    certificate: bool,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+enum EngineArg {
+    /// rewrite whole expressions with each ruleset and keep every intermediate form
+    Graph,
+    /// equality saturation over equivalence classes of subexpressions
+    Egraph,
+    /// best-first search from both sides at once, within a budget
+    BestFirst,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+enum HeuristicArg {
+    /// number of nodes in the expression
+    Size,
+    /// weighs functions and nesting more than plain arithmetic
+    Complexity,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+enum StepArg {
+    /// apply the whole ruleset along with simplify until nothing changes
+    Fixpoint,
+    /// try every single application of each rule, anywhere and with any matching, then simplify
+    OneStep,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+enum FormatArg {
+    /// s-expressions, like (== (+ (^ (sin x) 2) (^ (cos x) 2)) 1)
+    Lisp,
+    /// conventional notation, like sin(x)^2 + cos(x)^2 = 1
+    Infix,
+    /// Content MathML on a single line, identities are applications of <eq/>
+    Mathml,
+    /// read like lisp, print a JSON object per line, with a full report for each identity
+    Json,
+}
+
+impl SearchArgs {
+    fn prover(&self, rulesets: BTreeMap<String, Vec<Rule>>) -> IdentityProver {
+        IdentityProver::new(rulesets)
+            .engine(match self.engine {
+                EngineArg::Graph => Engine::Graph,
+                EngineArg::Egraph => Engine::Egraph,
+                EngineArg::BestFirst => Engine::BestFirst,
+            })
+            .heuristic(match self.heuristic {
+                HeuristicArg::Size => HeuristicKind::Size,
+                HeuristicArg::Complexity => HeuristicKind::Complexity,
+            })
+            .step_mode(match self.step {
+                StepArg::Fixpoint => StepMode::Fixpoint,
+                StepArg::OneStep => StepMode::OneStep,
+            })
+            .budget(SearchBudget {
+                max_nodes: self.max_nodes,
+                max_depth: self.max_depth,
+                max_time: self.timeout.map(std::time::Duration::from_secs_f64),
+            })
+            .rewrite_limits(self.limits.limits())
+    }
+}
+
+impl LimitArgs {
+    fn limits(self) -> RewriteLimits {
+        RewriteLimits {
+            max_rewrites: Some(self.max_rewrites),
+            max_term_size: Some(self.max_term_size),
+        }
+    }
+}
+
+// typed identities aren't rules, so an uppercase function in them is a plain function
+fn name_rule_functions(rules: Vec<Rule>) -> Result<Vec<Rule>, VarId> {
+    rules
+        .into_iter()
+        .map(|rule| {
+            Ok(Rule {
+                pattern: name_functions(rule.pattern)?,
+                replacement: name_functions(rule.replacement)?,
+                ..rule
+            })
+        })
+        .collect()
+}
+
+fn related_function(id: VarId) -> String {
+    format!(
+        "error: `{}` is a plain function outside of rules, so relations don't apply to it",
+        id.as_ref()
+    )
+}
+
+impl FormatArg {
+    // errors are ready to print, with the offending part of the input marked where possible
+    fn parse(self, s: &str) -> Result<Expression, String> {
+        let expr = match self {
+            FormatArg::Lisp | FormatArg::Json => Lisp::parse(s).map_err(|e| e.diagnostic(s, INPUT)),
+            FormatArg::Infix => Infix::parse(s).map_err(|e| e.diagnostic(s, INPUT)),
+            FormatArg::Mathml => {
+                ContentMathml::parse(s).map_err(|e| format!("error parsing expression: {e:?}"))
+            }
+        }?;
+
+        name_functions(expr).map_err(related_function)
+    }
+
+    fn parse_ruleset(self, s: &str) -> Result<Vec<Rule>, String> {
+        let rules = match self {
+            FormatArg::Lisp | FormatArg::Json => {
+                Lisp::parse_ruleset(s).map_err(|e| e.diagnostic(s, INPUT))
+            }
+            FormatArg::Infix => Infix::parse_ruleset(s).map_err(|e| e.diagnostic(s, INPUT)),
+            FormatArg::Mathml => ContentMathml::parse_equation(s)
+                .map(|rule| vec![rule])
+                .map_err(|e| format!("error parsing rule: {e:?}")),
+        }?;
+
+        name_rule_functions(rules).map_err(related_function)
+    }
+
+    // identities on one line of a larger source, with errors shown in that source
+    fn parse_line(
+        self,
+        source: &str,
+        range: std::ops::Range<usize>,
+        line: usize,
+        origin: &str,
+    ) -> Result<Vec<Rule>, String> {
+        let s = &source[range.clone()];
+        let shift = |span: Span| span.shift(range.start, line);
+
+        let rules = match self {
+            FormatArg::Lisp | FormatArg::Json => Lisp::parse_ruleset(s).map_err(|e| {
+                let span = shift(e.span);
+                ParseError { span, ..e }.diagnostic(source, origin)
+            }),
+            FormatArg::Infix => Infix::parse_ruleset(s).map_err(|e| {
+                let span = shift(e.span);
+                ParseError { span, ..e }.diagnostic(source, origin)
+            }),
+            FormatArg::Mathml => ContentMathml::parse_equation(s)
+                .map(|rule| vec![rule])
+                .map_err(|e| format!("error: {e:?}\n --> {origin}:{line}")),
+        }?;
+
+        name_rule_functions(rules)
+            .map_err(|id| format!("{}\n --> {origin}:{line}", related_function(id)))
+    }
+
+    fn comment(self) -> Option<&'static str> {
+        match self {
+            FormatArg::Lisp | FormatArg::Json => Some(Lisp::comment()),
+            FormatArg::Infix => Some(Infix::comment()),
+            FormatArg::Mathml => None,
+        }
+    }
+
+    fn format(self, expr: &Expression) -> String {
+        match self {
+            FormatArg::Lisp => Lisp::format(expr),
+            FormatArg::Infix => Infix::format(expr),
+            FormatArg::Mathml => ContentMathml::format(expr),
+            FormatArg::Json => JsonTree::format(expr),
+        }
+    }
+
+    // json is read by other programs, which don't want prompts
+    fn interactive(self) -> bool {
+        !matches!(self, FormatArg::Json)
+    }
+
+    // errors are printed as json objects too, so every line of output can be read the same way
+    fn print_error(self, e: &str) {
+        match self {
+            FormatArg::Json => println!("{}", serde_json::json!({ "error": e })),
+            _ => println!("{e}"),
+        }
+    }
 }
 
 fn main() {
     let args = Args::parse();
 
-    use crate::solve::*;
+    let loader = args
+        .rules
+        .into_iter()
+        .fold(RulesetLoader::new().env_dirs(), RulesetLoader::dir)
+        .builtin(!args.no_builtin_rules);
 
-    let rulesets = BTreeMap::from_iter(load_rulesets());
+    let LoadedRulesets {
+        rules: rulesets,
+        strategies,
+        origins,
+    } = loader.load().unwrap_or_else(|errors| {
+        for e in &errors {
+            eprintln!("{e}\n");
+        }
+        eprintln!(
+            "error: could not load rulesets due to {} errors",
+            errors.len()
+        );
+        std::process::exit(1)
+    });
 
     match args.mode {
-        Mode::Shell => expr_interactive_2(rulesets),
-        Mode::Auto => identity_interactive(rulesets),
+        Mode::Shell { format, limits } => {
+            expr_interactive_2(rulesets, &strategies, format, limits.limits())
+        }
+        Mode::Auto { search, output } => identity_interactive(rulesets, search, output),
+        Mode::CheckRules => check_rules(rulesets, &origins),
+        Mode::Analyze {
+            rulesets: names,
+            strict,
+            limits,
+        } => analyze(rulesets, &origins, &names, strict, limits.limits()),
+        Mode::Verify {
+            files,
+            search,
+            output,
+            output_dir,
+        } => {
+            let search = SearchArgs {
+                timeout: search.timeout.or(Some(VERIFY_TIMEOUT)),
+                ..search
+            };
+            verify_files(&files, &search.prover(rulesets), output, output_dir)
+        }
     }
 }
 
-fn expr_interactive_2(command_list: BTreeMap<String, Vec<Rule>>) {
-    println!(
-        "enter expression or commands:\n\n{}\n",
-        command_list
-            .keys()
-            .map(String::as_str)
-            .collect::<Vec<_>>()
-            .join(" ")
-    );
+fn expr_interactive_2(
+    command_list: BTreeMap<String, Vec<Rule>>,
+    strategies: &BTreeMap<String, Strategy>,
+    format: FormatArg,
+    limits: RewriteLimits,
+) {
+    if format.interactive() {
+        println!(
+            "enter expression or commands:\n\n{}\n",
+            command_list
+                .keys()
+                .chain(strategies.keys())
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+    }
 
     let mut expr = Literal::new(0).into();
 
     loop {
-        print!("> ");
-        stdout().flush().expect("error flushing output");
+        if format.interactive() {
+            print!("> ");
+            stdout().flush().expect("error flushing output");
+        }
 
         let mut buf = String::new();
-        stdin().read_line(&mut buf).expect("error reading input");
+        if stdin().read_line(&mut buf).expect("error reading input") == 0 {
+            break;
+        }
 
         let commands: Vec<_> = command_list
             .iter()
             .filter_map(|(k, v)| if buf.contains(k) { Some(v) } else { None })
             .collect();
 
-        if commands.is_empty() {
-            match Lisp::parse(&buf) {
+        // strategies written out, or named in a ruleset file
+        let strategy = if Strategy::is_combinator(&buf) {
+            Some(Strategy::parse(&buf).map_err(|e| e.diagnostic(&buf, INPUT)))
+        } else {
+            strategies.get(buf.trim()).cloned().map(Ok)
+        };
+
+        if let Some(strategy) = strategy {
+            let run = |strategy| run_strategy(&strategy, &expr, &command_list, format, limits);
+            match strategy.and_then(run) {
                 Ok(new_expr) => {
                     expr = new_expr;
-                    println!("{expr}");
+                    println!("{}", format.format(&collect_powers(expr.clone())));
                 }
-                Err(e) => {
-                    println!("error parsing expression: {e:?}")
+                Err(e) => format.print_error(&e),
+            }
+        } else if let Some(names) = buf.trim().strip_prefix(STEP_COMMAND) {
+            // every ruleset if none are named
+            let rulesets: Vec<_> = command_list
+                .iter()
+                .filter(|(name, _)| {
+                    names.trim().is_empty() || names.split_whitespace().any(|n| n == *name)
+                })
+                .collect();
+
+            if let Some(new_expr) = step_interactive(&expr, &rulesets, format) {
+                expr = new_expr;
+                println!("{}", format.format(&collect_powers(expr.clone())));
+            }
+        } else if commands.is_empty() {
+            match format.parse(&buf) {
+                Ok(new_expr) => {
+                    expr = new_expr;
+                    println!("{}", format.format(&expr));
                 }
+                Err(e) => format.print_error(&e),
             }
         } else {
-            expr = ruleset_combiner(commands.as_slice())(expr);
-            println!("{expr}");
+            // the expression stays as it was if the rulesets diverge
+            match ruleset_combiner(commands.as_slice(), limits)(expr.clone()) {
+                Ok(new_expr) => {
+                    expr = new_expr;
+                    // rulesets keep small powers expanded, they're collected again for display
+                    println!("{}", format.format(&collect_powers(expr.clone())));
+                }
+                Err(divergence) => format.print_error(&divergence_text(&divergence, format)),
+            }
         }
     }
 }
 
-fn identity_interactive(rulesets: BTreeMap<String, Vec<Rule>>) {
-    use petgraph::{
-        algo::{all_simple_paths, has_path_connecting},
-        dot::Dot,
+// expr after strategy, or why there isn't anything
+fn run_strategy(
+    strategy: &Strategy,
+    expr: &Expression,
+    rulesets: &BTreeMap<String, Vec<Rule>>,
+    format: FormatArg,
+    limits: RewriteLimits,
+) -> Result<Expression, String> {
+    if let Some(name) = strategy.unknown_ruleset(rulesets) {
+        return Err(format!("error: no ruleset named `{name}`"));
+    }
+
+    // the rules are written for expanded powers, like the rulesets keep them
+    strategy
+        .apply(&expand_powers(expr.clone()), rulesets, limits)
+        .map_err(|divergence| divergence_text(&divergence, format))?
+        .ok_or_else(|| "error: the strategy failed, nothing was changed".to_string())
+}
+
+// the divergence with the expressions it ended on, the last one being where it stopped
+fn divergence_text(divergence: &Divergence, format: FormatArg) -> String {
+    let mut output = format!("error: rewriting {divergence}, the last expressions were:");
+
+    for expr in &divergence.last {
+        output.push_str(&format!(
+            "\n  {}",
+            format.format(&collect_powers(expr.clone()))
+        ));
+    }
+
+    output
+}
+
+// applies a single rule once, at a position picked from every one where a rule of rulesets
+// applies, None if there aren't any or nothing was picked
+fn step_interactive(
+    expr: &Expression,
+    rulesets: &[(&String, &Vec<Rule>)],
+    format: FormatArg,
+) -> Option<Expression> {
+    // the rules are written for expanded powers, like the rulesets keep them
+    let expanded = expand_powers(expr.clone());
+    let mut candidates: Vec<_> = rulesets
+        .iter()
+        .flat_map(|(name, rules)| rules.iter().map(move |rule| (name, rule)))
+        .flat_map(|(name, rule)| {
+            match_positions(&expanded, rule)
+                .into_iter()
+                .map(move |position| (name, rule, position))
+        })
+        .collect();
+    candidates.sort_by(|(_, _, a), (_, _, b)| a.cmp(b));
+
+    if candidates.is_empty() {
+        format.print_error("error: no rule applies anywhere in the expression");
+        return None;
+    }
+
+    for (i, (name, rule, position)) in candidates.iter().enumerate() {
+        let sub = subexpression(&expanded, position).unwrap();
+        let replaced = apply_rule(sub.clone(), rule).unwrap();
+
+        println!(
+            "{:>3}) {name} at {position}: {} -> {}",
+            i + 1,
+            format.format(sub),
+            format.format(&replaced)
+        );
+    }
+
+    if format.interactive() {
+        print!("pick one, or nothing to leave the expression as it is: ");
+        stdout().flush().expect("error flushing output");
+    }
+
+    let mut buf = String::new();
+    stdin().read_line(&mut buf).expect("error reading input");
+
+    if buf.trim().is_empty() {
+        return None;
+    }
+
+    let Some((_, rule, position)) = buf
+        .trim()
+        .parse::<usize>()
+        .ok()
+        .and_then(|i| candidates.get(i.checked_sub(1)?))
+    else {
+        format.print_error(&format!("error: no candidate numbered {}", buf.trim()));
+        return None;
     };
 
-    const MAX_SOLUTIONS_SHOW: usize = 5;
-    let max_name_len = rulesets.keys().map(|s| s.len()).max().unwrap();
+    rewrite_at(&expanded, rule, position)
+}
 
-    println!("enter identity in form (== expr1 expr2):");
+fn identity_interactive(
+    rulesets: BTreeMap<String, Vec<Rule>>,
+    search: SearchArgs,
+    output: OutputArgs,
+) {
+    let prover = search.prover(rulesets);
+
+    match output.format {
+        FormatArg::Lisp => println!("enter identity in form (== expr1 expr2):"),
+        FormatArg::Infix => println!("enter identity in form expr1 = expr2:"),
+        FormatArg::Mathml => println!("enter identity in form <apply><eq/>expr1 expr2</apply>:"),
+        FormatArg::Json => {}
+    }
 
     loop {
-        print!("> ");
-        stdout().flush().expect("error flushing output");
+        if output.format.interactive() {
+            print!("> ");
+            stdout().flush().expect("error flushing output");
+        }
 
         let mut buf = String::new();
-        stdin().read_line(&mut buf).expect("error reading input");
+        if stdin().read_line(&mut buf).expect("error reading input") == 0 {
+            break;
+        }
 
-        match Lisp::parse_ruleset(&buf) {
+        match output.format.parse_ruleset(&buf) {
             Ok(mut rule) => match rule.pop() {
                 Some(Rule {
                     pattern,
                     replacement,
-                    kind,
-                }) if kind == RuleKind::Equality => {
-                    let arena = elsa::FrozenIndexSet::new();
-                    let graph =
-                        auto_identity(pattern.clone(), replacement.clone(), &rulesets, &arena);
-
-                    println!("process graph:\n{}", Dot::new(&graph));
-
-                    if has_path_connecting(&graph, &pattern, &replacement, None) {
-                        let mut paths: Vec<_> =
-                            all_simple_paths::<Vec<_>, _>(&graph, &pattern, &replacement, 0, None)
-                                .collect();
-
-                        paths.sort_by_key(|v| v.len());
-
-                        println!(
-                            "{} solutions ({} omitted):",
-                            paths.len(),
-                            paths.len().saturating_sub(MAX_SOLUTIONS_SHOW)
-                        );
-
-                        for path in paths.into_iter().take(MAX_SOLUTIONS_SHOW).rev() {
-                            println!("\n{} steps:", path.len());
-
-                            println!(" {:>max_name_len$} | {pattern}", "start");
-
-                            for pair in path.as_slice().windows(2) {
-                                let [before, after] = pair else {unreachable!()};
-                                println!(
-                                    " {:>max_name_len$} | {after}",
-                                    graph.edge_weight(before, after).unwrap()
-                                );
-                            }
-                        }
-                    } else {
-                        println!("couldn't find any solutions");
+                    kind: RuleKind::Equality,
+                    ..
+                }) => verify_identity(&pattern, &replacement, &prover, output),
+                Some(_) => output.format.print_error("error: wrong type of rule"),
+                None => output.format.print_error("error: empty rule"),
+            },
+            Err(e) => output.format.print_error(&e),
+        }
+    }
+}
+
+fn verify_identity(
+    pattern: &Expression,
+    replacement: &Expression,
+    prover: &IdentityProver,
+    output: OutputArgs,
+) {
+    const MAX_SOLUTIONS_SHOW: usize = 5;
+
+    let format = output.format;
+    let time = std::time::Instant::now();
+    let report = prover.prove(pattern, replacement);
+
+    if let FormatArg::Json = format {
+        let report = json_report(pattern, replacement, &report, time.elapsed());
+        println!("{report}");
+        return;
+    }
+
+    let ProofReport { verdict, stats } = report;
+
+    match &stats {
+        EngineStats::NotRun => {}
+        EngineStats::Graph {
+            dot,
+            proofs,
+            diverged,
+            ..
+        } => {
+            println!("process graph:\n{dot}");
+            print_diverged(diverged, format);
+
+            if !proofs.is_empty() {
+                println!(
+                    "{} solutions ({} omitted):",
+                    proofs.len(),
+                    proofs.len().saturating_sub(MAX_SOLUTIONS_SHOW)
+                );
+
+                for proof in proofs.iter().take(MAX_SOLUTIONS_SHOW).rev() {
+                    print_proof(proof, prover, format);
+                }
+            }
+        }
+        EngineStats::Egraph {
+            nodes,
+            classes,
+            iterations,
+            truncated,
+            stop_reason,
+        } => println!(
+            "e-graph: {nodes} nodes, {classes} classes, {iterations} iterations, \
+             {truncated} matches dropped, stopped: {stop_reason:?}"
+        ),
+        EngineStats::BestFirst {
+            expanded,
+            visited,
+            elapsed,
+            diverged,
+        } => {
+            println!(
+                "search: {expanded} expanded, {visited} visited, {:.3}s",
+                elapsed.as_secs_f64()
+            );
+            print_diverged(diverged, format);
+        }
+    }
+
+    match verdict {
+        Verdict::Proved(proof) => {
+            // the graph engine already printed its solutions
+            if !matches!(stats, EngineStats::Graph { .. }) {
+                print_proof(&proof, prover, format);
+            }
+            if output.latex {
+                println!("\n{}", latex_proof(&proof));
+            }
+            if output.mathml {
+                println!("\n{}", mathml_proof(&proof));
+            }
+            if output.certificate {
+                match certified(&proof, prover) {
+                    Ok(certificate) => println!("\n{}", certificate_text(&certificate, format)),
+                    Err(e) => println!("\n{e}"),
+                }
+            }
+        }
+        Verdict::Equivalent => {
+            println!("sides are equivalent, but the proof couldn't be reconstructed")
+        }
+        Verdict::Refuted {
+            assignment,
+            left,
+            right,
+        } => {
+            let assignment = assignment
+                .iter()
+                .map(|(var, value)| format!("{} = {value}", format.format(&var.clone().into())))
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            println!("identity is false, counterexample: {assignment}");
+            println!(" left side  = {left}");
+            println!(" right side = {right}");
+        }
+        Verdict::Unproved { reason, plausible } => {
+            match (reason, &stats) {
+                (Unproved::BudgetExhausted(kind), _) => {
+                    println!("budget exhausted ({kind:?} limit) before a solution was found")
+                }
+                (Unproved::NoProof, EngineStats::BestFirst { .. }) => {
+                    println!("no solution exists using these rulesets")
+                }
+                (Unproved::NoProof, _) => println!("couldn't find any solutions"),
+            }
+
+            if let Some(samples) = plausible {
+                println!("numerically plausible: both sides agree at {samples} random points")
+            }
+        }
+    }
+}
+
+fn print_diverged(diverged: &[(Expression, String, Divergence)], format: FormatArg) {
+    for (expr, ruleset, divergence) in diverged {
+        println!(
+            "left out {ruleset} on {}: {divergence}",
+            format.format(&collect_powers(expr.clone()))
+        );
+    }
+}
+
+fn print_proof(proof: &Proof, prover: &IdentityProver, format: FormatArg) {
+    println!("\n{}", proof_text(proof, prover, format));
+}
+
+// every step on its own line, labelled with the ruleset used
+fn proof_text(proof: &Proof, prover: &IdentityProver, format: FormatArg) -> String {
+    let max_name_len = prover.rulesets().keys().map(|s| s.len()).max().unwrap_or(0);
+
+    let mut output = format!("{} steps:\n", proof.steps.len() + 1);
+
+    output.push_str(&format!(
+        " {:>max_name_len$} | {}\n",
+        "start",
+        format.format(&proof.start)
+    ));
+
+    for ProofStep { ruleset, after } in &proof.steps {
+        output.push_str(&format!(
+            " {ruleset:>max_name_len$} | {}\n",
+            format.format(&collect_powers(after.clone()))
+        ));
+    }
+
+    output
+}
+
+// a checked certificate for proof, or why there isn't one
+fn certified(proof: &Proof, prover: &IdentityProver) -> Result<Certificate, String> {
+    let certificate = Certificate::from_proof(proof, prover.rulesets())
+        .map_err(|e| format!("error: couldn't build a certificate: {e:?}"))?;
+
+    check_certificate(&certificate, prover.rulesets())
+        .map_err(|e| format!("error: the certificate doesn't check: {e:?}"))?;
+
+    Ok(certificate)
+}
+
+// every rule application on its own line, with where it applied
+fn certificate_text(certificate: &Certificate, format: FormatArg) -> String {
+    let mut output = format!(
+        "certificate, {} checked rewrites:\n   {}\n",
+        certificate.steps.len(),
+        format.format(&certificate.start)
+    );
+
+    for CertificateStep {
+        ruleset,
+        justification,
+        reversed,
+        after,
+    } in &certificate.steps
+    {
+        let (how, position) = match justification {
+            Justification::Rule { rule, position, .. } => (rule.to_string(), position),
+            Justification::FoldConstants { position } => (FOLD_NAME.to_string(), position),
+            Justification::ExpandPowers { position } => (EXPAND_POWERS_NAME.to_string(), position),
+        };
+        // the e-graph labels built in rewrites with their own names
+        let how = if how == *ruleset {
+            how
+        } else {
+            format!("{ruleset} {how}")
+        };
+
+        output.push_str(&format!(
+            " = {}\n     by {how}{} at {position}\n",
+            format.format(after),
+            if *reversed { " backwards" } else { "" },
+        ));
+    }
+
+    output
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Proved,
+    Disproved,
+    Unknown,
+    TimedOut,
+}
+
+const OUTCOMES: [Outcome; 4] = [
+    Outcome::Proved,
+    Outcome::Disproved,
+    Outcome::Unknown,
+    Outcome::TimedOut,
+];
+const EXPECT_KEYWORD: &str = "expect";
+// seconds each identity gets in verify when no timeout is given, so one can't hold up the rest
+const VERIFY_TIMEOUT: f64 = 10.0;
+
+impl Outcome {
+    fn name(self) -> &'static str {
+        match self {
+            Outcome::Proved => "proved",
+            Outcome::Disproved => "disproved",
+            Outcome::Unknown => "unknown",
+            Outcome::TimedOut => "timed out",
+        }
+    }
+
+    // proofs and disproofs have to stay found, anything can become unknown or time out
+    fn regressed(self, expected: Outcome) -> bool {
+        matches!(expected, Outcome::Proved | Outcome::Disproved) && self != expected
+    }
+}
+
+// how each identity in a file came out, with how many of them regressed or couldn't be run
+#[derive(Default)]
+struct VerifySummary {
+    counts: [usize; OUTCOMES.len()],
+    regressions: usize,
+    errors: usize,
+}
+
+// what a trailing comment expects of its identity, which has to be proved without one
+fn expectation(comment: &str) -> Result<Outcome, String> {
+    match comment.split_once(EXPECT_KEYWORD) {
+        Some((_, word)) => OUTCOMES
+            .into_iter()
+            .find(|o| o.name() == word.trim())
+            .ok_or_else(|| format!("unknown expectation `{}`", word.trim())),
+        None => Ok(Outcome::Proved),
+    }
+}
+
+fn outcome(verdict: &Verdict) -> Outcome {
+    match verdict {
+        Verdict::Proved(_) | Verdict::Equivalent => Outcome::Proved,
+        Verdict::Refuted { .. } => Outcome::Disproved,
+        Verdict::Unproved {
+            reason: Unproved::BudgetExhausted(BudgetKind::Time),
+            ..
+        } => Outcome::TimedOut,
+        Verdict::Unproved { .. } => Outcome::Unknown,
+    }
+}
+
+fn verify_files(
+    files: &[PathBuf],
+    prover: &IdentityProver,
+    output: OutputArgs,
+    output_dir: Option<PathBuf>,
+) {
+    let mut table = Vec::new();
+    let mut regressions = 0;
+    let mut errors = 0;
+
+    if let Some(dir) = &output_dir {
+        if let Err(e) = std::fs::create_dir_all(dir) {
+            eprintln!("error: {e}\n --> {}", dir.display());
+            std::process::exit(1);
+        }
+    }
+
+    for file in files {
+        let origin = file.display().to_string();
+
+        let text = match std::fs::read_to_string(file) {
+            Ok(text) => text,
+            Err(e) => {
+                eprintln!("error: {e}\n --> {origin}\n");
+                errors += 1;
+                continue;
+            }
+        };
+
+        let summary = verify_text(&text, file, prover, output, output_dir.as_deref());
+        regressions += summary.regressions;
+        errors += summary.errors;
+        table.push((origin, summary.counts));
+    }
+
+    let total = table
+        .iter()
+        .fold([0; OUTCOMES.len()], |mut total, (_, counts)| {
+            for (t, c) in total.iter_mut().zip(counts) {
+                *t += c;
+            }
+            total
+        });
+    table.push(("total".to_string(), total));
+
+    let width = table.iter().map(|(file, _)| file.len()).max().unwrap_or(0);
+
+    print!("\n{:width$}", "file");
+    for outcome in OUTCOMES {
+        print!("  {}", outcome.name());
+    }
+    println!();
+
+    for (file, counts) in table {
+        print!("{file:width$}");
+        for (outcome, count) in OUTCOMES.iter().zip(counts) {
+            print!("  {count:>w$}", w = outcome.name().len());
+        }
+        println!();
+    }
+
+    println!("\n{regressions} regressions, {errors} errors");
+
+    if regressions > 0 || errors > 0 {
+        std::process::exit(1);
+    }
+}
+
+// proves the identities in the text of one file, printing a line for each
+fn verify_text(
+    text: &str,
+    file: &Path,
+    prover: &IdentityProver,
+    output: OutputArgs,
+    output_dir: Option<&Path>,
+) -> VerifySummary {
+    let format = output.format;
+    let origin = file.display().to_string();
+    let mut summary = VerifySummary::default();
+    let mut start = 0;
+
+    for (i, line) in text.split_inclusive('\n').enumerate() {
+        let line_start = start;
+        start += line.len();
+
+        let line = line.trim_end_matches(['\n', '\r']);
+        let (source, comment) = match format.comment() {
+            Some(c) => line.split_once(c).unwrap_or((line, "")),
+            None => (line, ""),
+        };
+
+        if source.trim().is_empty() {
+            continue;
+        }
+
+        let expected = match expectation(comment) {
+            Ok(expected) => expected,
+            Err(e) => {
+                eprintln!("error: {e}\n --> {origin}:{}\n", i + 1);
+                summary.errors += 1;
+                continue;
+            }
+        };
+
+        let rules =
+            match format.parse_line(text, line_start..line_start + source.len(), i + 1, &origin) {
+                Ok(rules) => rules,
+                Err(e) => {
+                    eprintln!("{e}\n");
+                    summary.errors += 1;
+                    continue;
+                }
+            };
+
+        for (k, rule) in rules.into_iter().enumerate() {
+            let name = format!("{origin}:{}", i + 1);
+
+            if rule.kind != RuleKind::Equality {
+                eprintln!("error: expected an identity\n --> {name}\n");
+                summary.errors += 1;
+                continue;
+            }
+
+            let time = std::time::Instant::now();
+            let report = prover.prove(&rule.pattern, &rule.replacement);
+            let elapsed = time.elapsed();
+            let verdict = &report.verdict;
+
+            let outcome = outcome(verdict);
+            summary.counts[OUTCOMES.iter().position(|o| *o == outcome).unwrap()] += 1;
+
+            let regressed = outcome.regressed(expected);
+            summary.regressions += regressed as usize;
+
+            if let (true, Verdict::Proved(proof)) = (output.certificate, verdict) {
+                if let Err(e) = certified(proof, prover) {
+                    eprintln!("{e}\n --> {name}\n");
+                    summary.errors += 1;
+                }
+            }
+
+            println!(
+                "{name}: {} ({:.3}s){}",
+                outcome.name(),
+                elapsed.as_secs_f64(),
+                if regressed {
+                    format!(", regression, expected {}", expected.name())
+                } else {
+                    String::new()
+                }
+            );
+
+            if let (Some(dir), Verdict::Proved(proof)) = (output_dir, verdict) {
+                let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+                let base = match k {
+                    0 => format!("{stem}-{}", i + 1),
+                    k => format!("{stem}-{}-{k}", i + 1),
+                };
+
+                let mut outputs = vec![match format {
+                    FormatArg::Json => (
+                        "json",
+                        json_report(&rule.pattern, &rule.replacement, &report, elapsed).to_string(),
+                    ),
+                    _ => ("txt", proof_text(proof, prover, format)),
+                }];
+                if output.latex {
+                    outputs.push(("tex", latex_proof(proof)));
+                }
+                if output.mathml {
+                    outputs.push(("mml", mathml_proof(proof)));
+                }
+
+                for (ext, contents) in outputs {
+                    let path = dir.join(format!("{base}.{ext}"));
+                    if let Err(e) = std::fs::write(&path, contents) {
+                        eprintln!("error: {e}\n --> {}\n", path.display());
+                        summary.errors += 1;
                     }
                 }
-                Some(_) => println!("error: wrong type of rule"),
-                None => println!("error: empty rule"),
-            },
-            Err(e) => println!("error parsing rule: {e:?}"),
+            }
+        }
+    }
+
+    summary
+}
+
+fn check_rules(rulesets: BTreeMap<String, Vec<Rule>>, origins: &BTreeMap<String, String>) {
+    let format_vars = |vars: Vec<String>| vars.join(", ");
+
+    let unsound = check_rulesets(&rulesets, SoundnessOptions::default());
+
+    for (name, index, rule, witness) in unsound.iter() {
+        let file = origins.get(*name).map_or(*name, String::as_str);
+
+        println!("{file}: rule {index}: {rule}");
+        println!(
+            " witness: {}",
+            format_vars(
+                witness
+                    .bind
+                    .functions
+                    .iter()
+                    .map(|(var, id)| format!("{} = {}", var.as_ref(), id.as_ref()))
+                    .chain(witness.bind.terms.iter().map(|(var, expr)| {
+                        format!("{} = {expr}", Expression::from(var.clone()))
+                    }))
+                    .collect()
+            )
+        );
+        println!(
+            " at: {}",
+            format_vars(
+                witness
+                    .assignment
+                    .iter()
+                    .map(|(var, value)| format!("{} = {value}", Expression::from(var.clone())))
+                    .collect()
+            )
+        );
+        println!(
+            " pattern = {}, replacement = {}\n",
+            witness.left, witness.right
+        );
+    }
+
+    let total: usize = rulesets.values().map(Vec::len).sum();
+
+    println!("{} of {total} rules failed", unsound.len());
+
+    if !unsound.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+fn analyze(
+    rulesets: BTreeMap<String, Vec<Rule>>,
+    origins: &BTreeMap<String, String>,
+    names: &[String],
+    strict: bool,
+    limits: RewriteLimits,
+) {
+    let options = AnalysisOptions { limits };
+    let file = |name: &str| origins.get(name).map_or(name, String::as_str).to_string();
+    let rule = |r: &RuleRef| {
+        format!(
+            "{}: rule {}: {}",
+            file(&r.ruleset),
+            r.index,
+            rulesets[&r.ruleset][r.index]
+        )
+    };
+
+    let analyses = if names.is_empty() {
+        analyze_rulesets(&rulesets, options)
+    } else {
+        names
+            .iter()
+            .map(|name| {
+                let analysis = analyze_ruleset(name, &rulesets, options).unwrap_or_else(|| {
+                    eprintln!("error: no ruleset named `{name}`");
+                    std::process::exit(1)
+                });
+                (name.as_str(), analysis)
+            })
+            .collect()
+    };
+
+    let mut unjoined_total = 0;
+
+    for (name, analysis) in &analyses {
+        let Termination {
+            weights,
+            precedence,
+            unoriented,
+        } = &analysis.termination;
+        let precedence: Vec<_> = precedence.iter().map(Symbol::to_string).collect();
+
+        println!("{}: ruleset {name}", file(name));
+        for weights in weights {
+            let weights: Vec<_> = weights
+                .iter()
+                .map(|(s, Weight { own, factor })| match (own, factor) {
+                    (_, 1) => format!("{s} = {own}"),
+                    (0, _) => format!("{s} = {factor} times its arguments"),
+                    _ => format!("{s} = {own} + {factor} times its arguments"),
+                })
+                .collect();
+            println!(" weights: {}", weights.join(", "));
+        }
+        println!(" precedence: {}", precedence.join(" > "));
+        if unoriented.is_empty() {
+            println!(" terminates");
+        } else {
+            println!(" not shown to terminate, these rules don't get smaller:");
+            for r in unoriented {
+                println!("  {}", rule(r));
+            }
         }
+
+        let unjoined: Vec<_> = analysis.unjoined().collect();
+        unjoined_total += unjoined.len();
+        println!(
+            " {} of {} critical pairs don't join",
+            unjoined.len(),
+            analysis.critical_pairs.len()
+        );
+
+        for pair in unjoined {
+            println!("  {}", rule(&pair.outer));
+            println!("  {}", rule(&pair.inner));
+            println!("   peak: {}, inner at {}", pair.peak, pair.position);
+            println!("   reducts: {}, {}", pair.reducts[0], pair.reducts[1]);
+            match &pair.join {
+                Join::Apart([left, right]) => println!("   rewrite to: {left}, {right}"),
+                Join::Diverged(divergence) => println!("   {divergence}"),
+                Join::Joined(_) => {}
+            }
+        }
+
+        println!();
+    }
+
+    let terminating = analyses
+        .iter()
+        .filter(|(_, a)| a.termination.unoriented.is_empty())
+        .count();
+    let total = analyses.len();
+    println!(
+        "{terminating} of {total} rulesets shown to terminate, {unjoined_total} critical pairs \
+         don't join"
+    );
+
+    if strict && (terminating < total || unjoined_total > 0) {
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+fn test_output(format: FormatArg) -> OutputArgs {
+    OutputArgs {
+        format,
+        latex: false,
+        mathml: false,
+        certificate: true,
+    }
+}
+
+#[test]
+fn test_expectation() {
+    assert!(expectation("") == Ok(Outcome::Proved));
+    assert!(expectation(" the classic one") == Ok(Outcome::Proved));
+    assert!(expectation(" expect disproved") == Ok(Outcome::Disproved));
+    assert!(expectation(" expect timed out ") == Ok(Outcome::TimedOut));
+    assert_eq!(
+        expectation(" expect maybe").err().as_deref(),
+        Some("unknown expectation `maybe`")
+    );
+
+    // proofs and disproofs have to stay found, anything else can come out either way
+    assert!(Outcome::Unknown.regressed(Outcome::Proved));
+    assert!(Outcome::TimedOut.regressed(Outcome::Proved));
+    assert!(Outcome::Proved.regressed(Outcome::Disproved));
+    assert!(!Outcome::Proved.regressed(Outcome::Proved));
+    assert!(!Outcome::Proved.regressed(Outcome::Unknown));
+    assert!(!Outcome::Disproved.regressed(Outcome::TimedOut));
+}
+
+#[test]
+fn test_verify() {
+    let rulesets = RulesetLoader::new().load().unwrap().rules;
+    let prover = IdentityProver::new(rulesets);
+    let dir = std::env::temp_dir().join(format!("identity-verify-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let text = "\
+; a comment on its own line, then a blank one
+
+(== (+ (^ (sin x) 2) (^ (cos x) 2)) 1)
+(== (sin x) (sin x))
+(== (sin x) 2) ; expect disproved
+(== (sin x) 3)
+(== (sin x) (cos x) ; expect disproved
+(== x y) ; expect maybe
+(=> x y)
+";
+    let summary = verify_text(
+        text,
+        Path::new("identities.lisp"),
+        &prover,
+        test_output(FormatArg::Lisp),
+        Some(&dir),
+    );
+
+    // the last disproof regressed, the last three lines can't be run at all
+    assert_eq!(summary.counts, [2, 2, 0, 0]);
+    assert_eq!(summary.regressions, 1);
+    assert_eq!(summary.errors, 3);
+
+    // proofs are written under the file's name and their line, identities that are already
+    // the same on both sides included
+    for line in [3, 4] {
+        assert!(dir.join(format!("identities-{line}.txt")).exists());
     }
+    assert!(!dir.join("identities-5.txt").exists());
+
+    // out of time is told apart from out of ideas, and fails an identity expected proved
+    let prover = prover.budget(SearchBudget {
+        max_time: Some(std::time::Duration::ZERO),
+        ..Default::default()
+    });
+    let summary = verify_text(
+        "x = y % expect timed out\nsin(x)^2 + cos(x)^2 = 1\n",
+        Path::new("identities.txt"),
+        &prover,
+        test_output(FormatArg::Infix),
+        None,
+    );
+    assert_eq!(summary.counts, [0, 1, 0, 1]);
+    assert_eq!(summary.regressions, 1);
+    assert_eq!(summary.errors, 0);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_plain_functions() {
+    let named = |e: &Expression| {
+        matches!(
+            e,
+            Expression::Concrete(Concrete::Unary(Unary {
+                kind: UnaryKind::Named { .. },
+                ..
+            }))
+        )
+    };
+
+    assert!(named(&FormatArg::Lisp.parse("(F x)").unwrap()));
+    assert!(named(&FormatArg::Infix.parse("F(x)").unwrap()));
+
+    let rules = FormatArg::Lisp.parse_ruleset("(== (F x) (G x))").unwrap();
+    assert!(named(&rules[0].pattern) && named(&rules[0].replacement));
+
+    let source = "(== (sin x) (sin x))\n(== ((recip F) x) (/ (F x)))";
+    let error = FormatArg::Lisp
+        .parse_line(source, 21..source.len(), 2, "identities.lisp")
+        .unwrap_err();
+    assert!(error.contains("`F`") && error.ends_with("--> identities.lisp:2"));
 }
@@ -1,39 +1,131 @@
+mod fold;
+mod guard;
+mod limit;
 mod pattern;
+mod position;
+mod power;
+mod relation;
 mod replace;
 
+pub use {fold::*, guard::*, limit::*, pattern::*, position::*, power::*, relation::*, replace::*};
+
 use {
     crate::{expression::*, text::*},
-    pattern::*,
-    replace::*,
-    std::collections::VecDeque,
+    std::collections::{HashMap, HashSet, VecDeque},
 };
 
 #[derive(PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Rule {
     pub pattern: Expression,
     pub replacement: Expression,
     pub kind: RuleKind,
+    pub guard: Option<Guard>,
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum RuleKind {
     Replacement,
     Equality,
 }
 
 pub fn apply_rule(expr: Expression, rule: &Rule) -> Result<Expression, Expression> {
-    if let Ok(bind) = match_pattern(expr.clone(), rule.pattern.clone()) {
-        replace_variable(rule.replacement.clone(), &bind).map_err(|()| expr)
-    } else {
-        Err(expr)
+    match match_pattern(expr.clone(), rule.pattern.clone()) {
+        Ok(bind) if rule.guard.as_ref().is_none_or(|g| g.holds(&bind)) => {
+            replace_variable(rule.replacement.clone(), &bind).map_err(|()| expr)
+        }
+        _ => Err(expr),
+    }
+}
+
+// every way rule rewrites expr itself, one for each distinct binding that passes the guard
+pub fn apply_rule_all<'a>(
+    expr: &'a Expression,
+    rule: &'a Rule,
+) -> impl Iterator<Item = (Bindings, Expression)> + 'a {
+    match_all(expr.clone(), rule.pattern.clone())
+        .filter(|bind| rule.guard.as_ref().is_none_or(|g| g.holds(bind)))
+        .filter_map(|bind| {
+            let replaced = replace_variable(rule.replacement.clone(), &bind).ok()?;
+            Some((bind, replaced))
+        })
+}
+
+// every rewrite of a single application of rule anywhere in expr, with the position and
+// bindings it used, leaving out the ones that change nothing
+pub fn rewrite_once(expr: &Expression, rule: &Rule) -> Vec<(Position, Bindings, Expression)> {
+    let mut output = Vec::new();
+
+    for position in positions(expr) {
+        let sub = subexpression(expr, &position).unwrap();
+
+        for (bind, replaced) in apply_rule_all(sub, rule) {
+            if replaced == *sub {
+                continue;
+            }
+
+            if let Some(new) = replace_subexpression(expr.clone(), &position, replaced) {
+                output.push((position.clone(), bind, new));
+            }
+        }
     }
+
+    output
+}
+
+// every position in expr where rule applies and changes something, parents before children
+pub fn match_positions(expr: &Expression, rule: &Rule) -> Vec<Position> {
+    positions(expr)
+        .into_iter()
+        .filter(|position| rewrite_at(expr, rule, position).is_some())
+        .collect()
+}
+
+// rule applied once to the subexpression at position and nowhere else, with the bindings
+// apply_rule would use, None if it doesn't apply there or changes nothing
+pub fn rewrite_at(expr: &Expression, rule: &Rule, position: &[usize]) -> Option<Expression> {
+    let sub = subexpression(expr, position)?;
+    let replaced = apply_rule(sub.clone(), rule).ok()?;
+
+    if replaced == *sub {
+        return None;
+    }
+
+    replace_subexpression(expr.clone(), position, replaced)
+}
+
+// the distinct expressions one rule of rules applied once anywhere in expr gives
+pub fn one_step_successors(expr: &Expression, rules: &[Rule]) -> Vec<Expression> {
+    let mut seen = HashSet::new();
+
+    rules
+        .iter()
+        .flat_map(|rule| rewrite_once(expr, rule))
+        .map(|(_, _, new)| new)
+        .filter(|new| seen.insert(new.clone()))
+        .collect()
+}
+
+// for the built in functions, which always settle, panics if func goes in circles, anything
+// else goes through try_transform_recursive
+pub(crate) fn transform_recursive(
+    expr: Expression,
+    func: &mut impl FnMut(Expression) -> Expression,
+) -> Expression {
+    let mut tracker = RewriteTracker::new(RewriteLimits::unlimited());
+    try_transform_recursive(expr, func, &mut tracker).unwrap_or_else(|d| panic!("{d}"))
 }
 
 // no result wrapper in func output since you can't trust it to not do (a => a) and always return Ok
-pub fn transform_recursive(
+pub fn try_transform_recursive(
     mut expr: Expression,
     func: &mut impl FnMut(Expression) -> Expression,
-) -> Expression {
+    tracker: &mut RewriteTracker,
+) -> Result<Expression, Divergence> {
+    // most calls change nothing, so the starting expression is only kept once something does
+    let mut visited = HashMap::new();
+
     loop {
         let previous_expr = expr.clone();
 
@@ -42,12 +134,18 @@ pub fn transform_recursive(
             let mut new_terms = Vec::new();
 
             for old_term in terms.into_iter() {
-                new_terms.push(transform_recursive(old_term, func));
+                new_terms.push(try_transform_recursive(old_term, func, tracker)?);
             }
 
             expr = Variadic::new(new_terms, kind).into();
         } else if let Expression::Concrete(Concrete::Unary(Unary { argument, kind })) = expr {
-            expr = Unary::new(transform_recursive(*argument, func), kind).into();
+            expr = Unary::new(try_transform_recursive(*argument, func, tracker)?, kind).into();
+        } else if let Expression::Concrete(Concrete::Power(Power { base, exponent })) = expr {
+            expr = Power::new(
+                try_transform_recursive(*base, func, tracker)?,
+                try_transform_recursive(*exponent, func, tracker)?,
+            )
+            .into();
         }
 
         // step 2: try applying function on base
@@ -55,23 +153,30 @@ pub fn transform_recursive(
 
         // step 3: if successful on 1 or 2, loop and try again, otherwise return
         if expr == previous_expr {
-            break expr;
+            break Ok(expr);
+        }
+
+        if visited.is_empty() {
+            visited.insert(previous_expr, 0);
         }
+        tracker.step(&expr, &mut visited)?;
     }
 }
 
 pub trait RuleTextFormat<ErrorType>: ExprTextFormat<ErrorType> {
     fn parse_ruleset(s: &str) -> Result<Vec<Rule>, ErrorType> {
+        // comments are blanked out rather than removed so spans still point into s
         let s = s
             .lines()
             .map(|s| {
                 if let Some(i) = s.find(Self::comment()) {
-                    &s[..i]
+                    format!("{}{}", &s[..i], " ".repeat(s.len() - i))
                 } else {
-                    s
+                    s.to_string()
                 }
             })
-            .collect::<String>();
+            .collect::<Vec<_>>()
+            .join("\n");
 
         let mut tokens = Self::tokenize(&s);
         let mut output = Vec::new();
@@ -88,7 +193,7 @@ pub trait RuleTextFormat<ErrorType>: ExprTextFormat<ErrorType> {
         Self::format_tokens(tokens)
     }
 
-    fn parse_tokens_rule(tokens: &mut VecDeque<SourceToken>) -> Result<Rule, ErrorType>;
+    fn parse_tokens_rule(tokens: &mut TokenStream) -> Result<Rule, ErrorType>;
 
     fn format_rule_tokens(rule: &Rule) -> VecDeque<SourceToken>;
 
@@ -127,6 +232,7 @@ fn test_rewrite() {
             pattern,
             replacement,
             kind: RuleKind::Replacement,
+            guard: None,
         },
     );
 
@@ -156,12 +262,14 @@ fn test_rule_parser_2() {
             Rule {
                 pattern: Lisp::parse("(+ 1 2)").unwrap(),
                 replacement: Lisp::parse("3").unwrap(),
-                kind: RuleKind::Replacement
+                kind: RuleKind::Replacement,
+                guard: None
             },
             Rule {
                 pattern: Lisp::parse("(+ a (+ b))").unwrap(),
                 replacement: Lisp::parse("(+ a b)").unwrap(),
-                kind: RuleKind::Replacement
+                kind: RuleKind::Replacement,
+                guard: None
             }
         ]
     );
@@ -173,6 +281,91 @@ fn test_rule_parser_2() {
     }
 }
 
+#[test]
+fn test_rule_guard() {
+    let rule = Lisp::parse_ruleset("(=> (* a (/ a) ..) .. (if (nonzero a) (not (integer a))))")
+        .unwrap()
+        .pop()
+        .unwrap();
+
+    assert_eq!(
+        rule.guard,
+        Some(Guard {
+            predicates: vec![
+                Predicate::Nonzero(Variable::new("a")),
+                Predicate::Not(Box::new(Predicate::Integer(Variable::new("a")))),
+            ]
+        })
+    );
+    assert_eq!(
+        Lisp::parse_ruleset(&rule.to_string()).unwrap(),
+        vec![rule.clone()]
+    );
+
+    let exp = Lisp::parse("(* x (/ x) y)").unwrap();
+    assert_eq!(apply_rule(exp, &rule), Ok(Lisp::parse("y").unwrap()));
+
+    let exp = Lisp::parse("(* 0 (/ 0) y)").unwrap();
+    assert_eq!(apply_rule(exp.clone(), &rule), Err(exp));
+
+    assert_eq!(
+        Lisp::parse_ruleset("(=> a a (if (even a)))").map_err(|e| e.kind),
+        Err(LispParseError::InvalidGuard)
+    );
+}
+
+#[test]
+fn test_one_step_successors() {
+    let rules = Lisp::parse_ruleset("(=> (+ (* a b) (* a c) ..) (+ (* a (+ b c)) ..))").unwrap();
+    let exp = Lisp::parse("(+ (* a b) (* a c) (* d b))").unwrap();
+    let successors = one_step_successors(&exp, &rules);
+
+    // apply_rule only ever factors out one of them
+    for factored in ["(+ (* a (+ b c)) (* d b))", "(+ (* b (+ a d)) (* a c))"] {
+        let factored = Lisp::parse(factored).unwrap();
+        assert!(successors.contains(&factored), "{factored}");
+    }
+    assert!(!successors.contains(&exp));
+
+    // rewrites inside the expression count too, b and c can be bound either way around
+    let exp = Lisp::parse("(sin (+ (* a b) (* a c)))").unwrap();
+    let sub = subexpression(&exp, &[0]).unwrap().clone();
+    let factored = replace_subexpression(exp.clone(), &[0], apply_rule(sub, &rules[0]).unwrap());
+    let rewrites = rewrite_once(&exp, &rules[0]);
+
+    assert_eq!(rewrites.len(), 2);
+    assert!(rewrites
+        .into_iter()
+        .all(|(position, _, new)| *position == [0] && Some(new) == factored));
+}
+
+#[test]
+fn test_rewrite_at() {
+    let rule = Lisp::parse_ruleset("(=> (sin a) (cos a))")
+        .unwrap()
+        .pop()
+        .unwrap();
+    let exp = Lisp::parse("(* (sin (sin x)) (sin y))").unwrap();
+
+    let found = match_positions(&exp, &rule);
+    assert_eq!(
+        found,
+        [vec![0], vec![0, 0], vec![1]].map(Position::from).to_vec()
+    );
+
+    // only the one position is rewritten, even though the rule applies elsewhere
+    assert_eq!(
+        rewrite_at(&exp, &rule, &found[1]),
+        Some(Lisp::parse("(* (sin (cos x)) (sin y))").unwrap())
+    );
+    assert_eq!(
+        rewrite_at(&exp, &rule, &found[0]),
+        Some(Lisp::parse("(* (cos (sin x)) (sin y))").unwrap())
+    );
+    assert_eq!(rewrite_at(&exp, &rule, &[]), None);
+    assert_eq!(rewrite_at(&exp, &rule, &[2]), None);
+}
+
 #[test]
 fn test_recursive_transformer() {
     let exp = Lisp::parse("(* 1 (* 2 (* 3)))").unwrap();
@@ -184,11 +377,29 @@ fn test_recursive_transformer() {
     println!("rule: {rule}");
     println!("before: {exp}");
 
-    let result = transform_recursive(exp, &mut |e| {
-        apply_rule(e, &rule).unwrap_or_else(std::convert::identity)
-    });
+    let mut tracker = RewriteTracker::new(RewriteLimits::default());
+    let result = try_transform_recursive(
+        exp,
+        &mut |e| apply_rule(e, &rule).unwrap_or_else(std::convert::identity),
+        &mut tracker,
+    );
 
-    assert_eq!(result, Lisp::parse("(* 1 2 3)").unwrap());
+    assert_eq!(result, Ok(Lisp::parse("(* 1 2 3)").unwrap()));
 
-    println!("after: {result}");
+    println!("after: {result:?}");
+
+    // rules that undo each other are stopped instead of going around forever
+    let rules = Lisp::parse_ruleset("(=> (sin u) (cos u)) (=> (cos u) (sin u))").unwrap();
+    let mut tracker = RewriteTracker::new(RewriteLimits::default());
+    let divergence = try_transform_recursive(
+        Lisp::parse("(sin x)").unwrap(),
+        &mut |e| {
+            apply_rule(e, &rules[0])
+                .or_else(|e| apply_rule(e, &rules[1]))
+                .unwrap_or_else(std::convert::identity)
+        },
+        &mut tracker,
+    )
+    .unwrap_err();
+    assert_eq!(divergence.kind, DivergenceKind::Cycle { length: 2 });
 }
@@ -0,0 +1,96 @@
+use {
+    crate::{expression::*, rewrite::transform_recursive},
+    num_rational::BigRational,
+    num_traits::{One, Pow, Signed, ToPrimitive, Zero},
+};
+
+// evaluates arithmetic on number literals: literal terms of a sum or product are combined
+// into one, and negations and reciprocals of literals are replaced by their value
+
+pub const FOLD_NAME: &str = "fold";
+// powers of numbers with larger exponents are left alone
+const MAX_FOLDED_EXPONENT: i32 = 1024;
+
+pub fn fold_constants(expr: Expression) -> Expression {
+    transform_recursive(expr, &mut fold_node)
+}
+
+fn fold_node(expr: Expression) -> Expression {
+    let number = |e: &Expression| match e {
+        Expression::Concrete(Concrete::Literal(Literal { value })) => value.number(),
+        _ => None,
+    };
+
+    match expr {
+        Expression::Concrete(Concrete::Variadic(Variadic { terms, kind })) => {
+            let (numbers, mut rest): (Vec<_>, Vec<_>) =
+                terms.into_iter().partition(|t| number(t).is_some());
+            let numbers = numbers.iter().filter_map(number);
+
+            let value = match kind {
+                VariadicKind::Addition => numbers.fold(BigRational::zero(), |a, b| a + b),
+                VariadicKind::Multiplication => numbers.fold(BigRational::one(), |a, b| a * b),
+            };
+            let identity = match kind {
+                VariadicKind::Addition => value.is_zero(),
+                VariadicKind::Multiplication => value.is_one(),
+            };
+
+            if rest.is_empty() || (kind == VariadicKind::Multiplication && value.is_zero()) {
+                Literal::new(value).into()
+            } else {
+                if !identity {
+                    rest.push(Literal::new(value).into());
+                }
+
+                Variadic::new(rest, kind).into()
+            }
+        }
+        Expression::Concrete(Concrete::Unary(Unary { argument, kind })) => {
+            match (number(&argument), &kind) {
+                (Some(n), UnaryKind::Negation) => Literal::new(-n).into(),
+                (Some(n), UnaryKind::Reciprocal) if !n.is_zero() => Literal::new(n.recip()).into(),
+                _ => Expression::Concrete(Concrete::Unary(Unary { argument, kind })),
+            }
+        }
+        Expression::Concrete(Concrete::Power(Power { base, exponent })) => {
+            let exponent_value = number(&exponent)
+                .filter(|e| e.is_integer())
+                .and_then(|e| e.to_integer().to_i32())
+                .filter(|e| e.abs() <= MAX_FOLDED_EXPONENT);
+
+            match (number(&base), exponent_value) {
+                // 0 to a power that isn't positive is undefined
+                (Some(b), Some(e)) if !b.is_zero() || e.is_positive() => {
+                    Literal::new(Pow::pow(b, e)).into()
+                }
+                _ => Power { base, exponent }.into(),
+            }
+        }
+        expr => expr,
+    }
+}
+
+#[test]
+fn test_fold_constants() {
+    use crate::text::*;
+
+    let fold = |s| fold_constants(Lisp::parse(s).unwrap());
+
+    assert_eq!(fold("(+ 1 2 x)"), Lisp::parse("(+ 3 x)").unwrap());
+    assert_eq!(fold("(* 2 (/ 4) (- 3))"), Lisp::parse("-3/2").unwrap());
+    assert_eq!(
+        fold("(+ 1/3 2/3 (sin x))"),
+        Lisp::parse("(+ 1 (sin x))").unwrap()
+    );
+    assert_eq!(fold("(+ 1 (- 1) x)"), Lisp::parse("(+ x)").unwrap());
+    assert_eq!(fold("(* 0 x)"), Lisp::parse("0").unwrap());
+    assert_eq!(fold("(/ 0)"), Lisp::parse("(/ 0)").unwrap());
+    assert_eq!(fold("(^ 2/3 -2)"), Lisp::parse("9/4").unwrap());
+    assert_eq!(fold("(^ 0 -1)"), Lisp::parse("(^ 0 -1)").unwrap());
+    assert_eq!(fold("(^ x (+ 1 1))"), Lisp::parse("(^ x 2)").unwrap());
+    assert_eq!(
+        fold("(* 4294967296 4294967296)"),
+        Lisp::parse("18446744073709551616").unwrap()
+    );
+}
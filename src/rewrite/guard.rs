@@ -0,0 +1,173 @@
+use {
+    crate::{expression::*, rewrite::*},
+    num_traits::{Signed, Zero},
+};
+
+// conditions on the bindings of a rule's pattern variables, every predicate has to hold
+// for the rule to fire
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Guard {
+    pub predicates: Vec<Predicate>,
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Predicate {
+    // bound to a literal
+    Literal(Variable),
+    // bound to an integer literal
+    Integer(Variable),
+    // bound to a positive or negative integer literal
+    Positive(Variable),
+    Negative(Variable),
+    // not provably zero, symbolic expressions are assumed to be nonzero
+    Nonzero(Variable),
+    // the first binding contains the second one, or a variable with that name if unbound
+    Contains(Variable, Variable),
+    Not(Box<Predicate>),
+}
+
+pub const GUARD_KEYWORD: &str = "if";
+
+impl Guard {
+    pub fn holds(&self, bind: &Bindings) -> bool {
+        self.predicates.iter().all(|p| p.holds(bind))
+    }
+}
+
+impl Predicate {
+    pub fn holds(&self, bind: &Bindings) -> bool {
+        let get = |v: &Variable| {
+            bind.terms
+                .get(v)
+                .cloned()
+                .unwrap_or_else(|| v.clone().into())
+        };
+        let integer = |v: &Variable| match get(v) {
+            Expression::Concrete(Concrete::Literal(Literal {
+                value: LiteralValue::Integer(i),
+            })) => Some(i),
+            _ => None,
+        };
+
+        match self {
+            Predicate::Literal(v) => matches!(get(v), Expression::Concrete(Concrete::Literal(_))),
+            Predicate::Integer(v) => integer(v).is_some(),
+            Predicate::Positive(v) => integer(v).is_some_and(|i| i.is_positive()),
+            Predicate::Negative(v) => integer(v).is_some_and(|i| i.is_negative()),
+            Predicate::Nonzero(v) => !is_zero(&get(v)),
+            Predicate::Contains(v, x) => contains(&get(v), &get(x)),
+            Predicate::Not(p) => !p.holds(bind),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Predicate::Literal(_) => "literal",
+            Predicate::Integer(_) => "integer",
+            Predicate::Positive(_) => "positive",
+            Predicate::Negative(_) => "negative",
+            Predicate::Nonzero(_) => "nonzero",
+            Predicate::Contains(_, _) => "contains",
+            Predicate::Not(_) => "not",
+        }
+    }
+
+    // builds a predicate from its name and variable arguments, None if they don't fit
+    pub fn from_name(name: &str, mut args: Vec<Variable>) -> Option<Self> {
+        let one = |args: &mut Vec<Variable>| (args.len() == 1).then(|| args.pop().unwrap());
+
+        match name {
+            "literal" => one(&mut args).map(Predicate::Literal),
+            "integer" => one(&mut args).map(Predicate::Integer),
+            "positive" => one(&mut args).map(Predicate::Positive),
+            "negative" => one(&mut args).map(Predicate::Negative),
+            "nonzero" => one(&mut args).map(Predicate::Nonzero),
+            "contains" if args.len() == 2 => {
+                let x = args.pop().unwrap();
+                Some(Predicate::Contains(args.pop().unwrap(), x))
+            }
+            _ => None,
+        }
+    }
+
+    // variable arguments, empty for predicates made of other predicates
+    pub fn args(&self) -> Vec<&Variable> {
+        match self {
+            Predicate::Literal(v)
+            | Predicate::Integer(v)
+            | Predicate::Positive(v)
+            | Predicate::Negative(v)
+            | Predicate::Nonzero(v) => vec![v],
+            Predicate::Contains(v, x) => vec![v, x],
+            Predicate::Not(_) => Vec::new(),
+        }
+    }
+}
+
+fn is_zero(expr: &Expression) -> bool {
+    match expr {
+        Expression::Concrete(Concrete::Literal(Literal { value })) => {
+            value.number().is_some_and(|n| n.is_zero())
+        }
+        Expression::Concrete(Concrete::Variadic(Variadic {
+            terms,
+            kind: VariadicKind::Addition,
+        })) => terms.iter().all(is_zero),
+        Expression::Concrete(Concrete::Variadic(Variadic {
+            terms,
+            kind: VariadicKind::Multiplication,
+        })) => terms.iter().any(is_zero),
+        Expression::Concrete(Concrete::Unary(Unary {
+            argument,
+            kind: UnaryKind::Negation,
+        })) => is_zero(argument),
+        _ => false,
+    }
+}
+
+fn contains(expr: &Expression, sub: &Expression) -> bool {
+    expr == sub
+        || match expr {
+            Expression::Concrete(Concrete::Variadic(Variadic { terms, .. })) => {
+                terms.iter().any(|t| contains(t, sub))
+            }
+            Expression::Concrete(Concrete::Unary(Unary { argument, .. })) => {
+                contains(argument, sub)
+            }
+            Expression::Concrete(Concrete::Power(Power { base, exponent })) => {
+                contains(base, sub) || contains(exponent, sub)
+            }
+            _ => false,
+        }
+}
+
+#[test]
+fn test_guard() {
+    use {crate::text::*, std::collections::BTreeMap};
+
+    let bind = Bindings {
+        terms: BTreeMap::from([
+            (Variable::new("a"), Lisp::parse("(sin x)").unwrap()),
+            (Variable::new("n"), Lisp::parse("-3").unwrap()),
+            (Variable::new("z"), Lisp::parse("(* 0 y)").unwrap()),
+        ]),
+        ..Default::default()
+    };
+    let [a, n, z, x] = ["a", "n", "z", "x"].map(Variable::new);
+
+    assert!(Predicate::Nonzero(a.clone()).holds(&bind));
+    assert!(!Predicate::Nonzero(z.clone()).holds(&bind));
+    assert!(Predicate::Integer(n.clone()).holds(&bind));
+    assert!(Predicate::Negative(n.clone()).holds(&bind));
+    assert!(!Predicate::Positive(n.clone()).holds(&bind));
+    assert!(!Predicate::Literal(a.clone()).holds(&bind));
+    assert!(Predicate::Contains(a.clone(), x.clone()).holds(&bind));
+    assert!(Predicate::Not(Box::new(Predicate::Contains(z, x))).holds(&bind));
+    assert!(!Guard {
+        predicates: vec![Predicate::Nonzero(a), Predicate::Positive(n)]
+    }
+    .holds(&bind));
+}
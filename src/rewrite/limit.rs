@@ -0,0 +1,165 @@
+use {
+    crate::expression::*,
+    std::collections::{HashMap, VecDeque},
+};
+
+// rewriting to a fixpoint doesn't end for rules that undo each other or keep growing the
+// expression, so loops count their steps and look for expressions they've been through before
+
+// a ruleset normally settles in far fewer steps, and on far smaller expressions, expanding a
+// product of eight binomials gets to about 2500 nodes
+pub const DEFAULT_MAX_REWRITES: usize = 1_000;
+pub const DEFAULT_MAX_TERM_SIZE: usize = 4_000;
+// how many of the last expressions a divergence keeps
+const LAST_STATES: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RewriteLimits {
+    // steps that changed something, over the whole rewrite
+    pub max_rewrites: Option<usize>,
+    // nodes in any expression along the way, see expression_size
+    pub max_term_size: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    pub kind: DivergenceKind,
+    pub steps: usize,
+    // the last few expressions, oldest first, which may be subexpressions of the one rewritten
+    pub last: Vec<Expression>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DivergenceKind {
+    RewriteLimit,
+    TermSizeLimit { size: usize },
+    // the last expression was already reached length steps before
+    Cycle { length: usize },
+}
+
+// keeps count over one whole rewrite, through every fixpoint loop in it
+#[derive(Clone, Debug)]
+pub struct RewriteTracker {
+    limits: RewriteLimits,
+    steps: usize,
+    last: VecDeque<Expression>,
+}
+
+impl Default for RewriteLimits {
+    fn default() -> Self {
+        Self {
+            max_rewrites: Some(DEFAULT_MAX_REWRITES),
+            max_term_size: Some(DEFAULT_MAX_TERM_SIZE),
+        }
+    }
+}
+
+impl RewriteLimits {
+    // cycles are still caught
+    pub fn unlimited() -> Self {
+        Self {
+            max_rewrites: None,
+            max_term_size: None,
+        }
+    }
+}
+
+impl RewriteTracker {
+    pub fn new(limits: RewriteLimits) -> Self {
+        Self {
+            limits,
+            steps: 0,
+            last: VecDeque::new(),
+        }
+    }
+
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    // expr is the next expression of a fixpoint loop, visited has every one before it in that
+    // loop with the step it was reached at, starting from 0
+    pub fn step(
+        &mut self,
+        expr: &Expression,
+        visited: &mut HashMap<Expression, usize>,
+    ) -> Result<(), Divergence> {
+        self.steps += 1;
+        // a loop over a whole expression ends where the last loop inside it did
+        if self.last.back() != Some(expr) {
+            self.last.push_back(expr.clone());
+        }
+        if self.last.len() > LAST_STATES {
+            self.last.pop_front();
+        }
+
+        let index = visited.len();
+        if let Some(earlier) = visited.get(expr) {
+            return Err(self.diverged(DivergenceKind::Cycle {
+                length: index - earlier,
+            }));
+        }
+        visited.insert(expr.clone(), index);
+
+        if let Some(max) = self.limits.max_term_size {
+            let size = expression_size(expr);
+            if size > max {
+                return Err(self.diverged(DivergenceKind::TermSizeLimit { size }));
+            }
+        }
+
+        if self.limits.max_rewrites.is_some_and(|max| self.steps > max) {
+            return Err(self.diverged(DivergenceKind::RewriteLimit));
+        }
+
+        Ok(())
+    }
+
+    fn diverged(&self, kind: DivergenceKind) -> Divergence {
+        Divergence {
+            kind,
+            steps: self.steps,
+            last: self.last.iter().cloned().collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "diverged after {} steps", self.steps)?;
+
+        match self.kind {
+            DivergenceKind::RewriteLimit => write!(f, ", more than allowed"),
+            DivergenceKind::TermSizeLimit { size } => {
+                write!(f, ", an expression grew to {size} nodes")
+            }
+            DivergenceKind::Cycle { length: 1 } => write!(f, ", an expression rewrote to itself"),
+            DivergenceKind::Cycle { length } => {
+                write!(f, ", going around a cycle of {length} expressions")
+            }
+        }
+    }
+}
+
+#[test]
+fn test_rewrite_limit() {
+    use crate::text::*;
+
+    let limits = RewriteLimits {
+        max_rewrites: Some(3),
+        max_term_size: None,
+    };
+    let mut tracker = RewriteTracker::new(limits);
+    let mut visited = HashMap::new();
+
+    // settling on exactly the last step allowed is fine, only the one after it goes over
+    for n in 1..=3 {
+        let expr = Lisp::parse(&n.to_string()).unwrap();
+        assert_eq!(tracker.step(&expr, &mut visited), Ok(()));
+    }
+    let divergence = tracker
+        .step(&Lisp::parse("4").unwrap(), &mut visited)
+        .unwrap_err();
+    assert_eq!(divergence.kind, DivergenceKind::RewriteLimit);
+    assert_eq!(divergence.steps, 4);
+}
@@ -1,145 +1,307 @@
-use {crate::expression::*, std::collections::BTreeMap};
+use {
+    crate::{expression::*, rewrite::unrelate_all},
+    std::collections::{BTreeMap, HashSet},
+};
 
 // pattern variables treated as capture groups
 // all expressions MUST be completely bound to structures, literals, or variables
 
-pub fn match_pattern(
-    expr: Expression,
-    patt: Expression,
-) -> Result<BTreeMap<Variable, Expression>, ()> {
-    match_pattern_rec(vec![MatchGroup::Single { expr, patt }], BTreeMap::new())
+#[derive(PartialEq, Eq, Clone, Debug, Default, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bindings {
+    // keyed by the untyped variable
+    pub terms: BTreeMap<Variable, Expression>,
+    // function variables, what they stand for before any relations are applied
+    pub functions: BTreeMap<VarId, FuncId>,
 }
 
-// ONLY use recursion for backtracking, do something else for tree traversal
-// ONLY escape out of loop with return Err or return recursion()
-
-fn match_pattern_rec(
-    mut stack: Vec<MatchGroup>,
-    mut bind: BTreeMap<Variable, Expression>,
-) -> Result<BTreeMap<Variable, Expression>, ()> {
-    while let Some(match_comb) = stack.pop() {
-        match match_comb {
-            MatchGroup::Single { expr, patt } => match (expr, patt) {
-                (Expression::Concrete(ec), Expression::Concrete(pc)) => match (ec, pc) {
-                    (
-                        Concrete::Variadic(Variadic {
-                            terms: eterms,
-                            kind: ekind,
-                        }),
-                        Concrete::Variadic(Variadic {
-                            terms: pterms,
-                            kind: pkind,
-                        }),
-                    ) if ekind == pkind => {
-                        stack.push(MatchGroup::Multiple {
-                            expr_list: eterms.into_iter().collect(),
-                            patt_list: pterms.into_iter().collect(),
-                            kind: ekind,
-                        });
-                        continue; // operate on next
-                    }
-                    (
-                        Concrete::Unary(Unary {
-                            argument: earg,
-                            kind: ekind,
-                        }),
-                        Concrete::Unary(Unary {
-                            argument: parg,
-                            kind: pkind,
-                        }),
-                    ) if ekind == pkind => {
-                        stack.push(MatchGroup::Single {
-                            expr: *earg,
-                            patt: *parg,
-                        });
-                        continue; // operate on next
+// a failed match has nothing more to say than that it failed
+#[allow(clippy::result_unit_err)]
+pub fn match_pattern(expr: Expression, patt: Expression) -> Result<Bindings, ()> {
+    match_all(expr, patt).next().ok_or(())
+}
+
+// every distinct way patt matches expr, found lazily, the first one is what match_pattern gives
+pub fn match_all(expr: Expression, patt: Expression) -> Matches {
+    Matches {
+        frames: vec![Frame::Search {
+            stack: vec![MatchGroup::Single { expr, patt }],
+            bind: Bindings::default(),
+        }],
+        seen: HashSet::new(),
+    }
+}
+
+// a depth first search over the choices of terms for the patterns in variadics, backtracking
+// from the frame on top
+pub struct Matches {
+    frames: Vec<Frame>,
+    seen: HashSet<Bindings>,
+}
+
+enum Frame {
+    // matching left to do, and what's bound so far
+    Search {
+        stack: Vec<MatchGroup>,
+        bind: Bindings,
+    },
+    // the ways of matching patt to some of expr_list that haven't been tried, from the nth
+    Choices {
+        stack: Vec<MatchGroup>,
+        bind: Bindings,
+        expr_list: Vec<Expression>,
+        patt: Expression,
+        patt_rest: Vec<Expression>,
+        kind: VariadicKind,
+        n: usize,
+    },
+}
+
+impl Iterator for Matches {
+    type Item = Bindings;
+
+    fn next(&mut self) -> Option<Bindings> {
+        while let Some(frame) = self.frames.pop() {
+            let (stack, bind) = match frame {
+                Frame::Search { stack, bind } => (stack, bind),
+                Frame::Choices {
+                    stack,
+                    bind,
+                    expr_list,
+                    patt,
+                    patt_rest,
+                    kind,
+                    n,
+                } => {
+                    // typed variables only bind single terms, so they are tried like subpatterns
+                    let is_var = matches!(
+                        patt,
+                        Expression::Variable(Variable {
+                            kind: VarKind::Any,
+                            ..
+                        })
+                    );
+                    let typed = match &patt {
+                        Expression::Variable(Variable { kind, .. }) => Some(*kind),
+                        _ => None,
                     }
-                    (Concrete::Literal(el), Concrete::Literal(pl)) if el == pl => continue, // good match, operate on next
-                    _ => return Err(()), // mismatching literal
-                },
-                (expr, Expression::Variable(pv)) => {
-                    if *bind.entry(pv).or_insert_with(|| expr.clone()) != expr {
-                        return Err(());
+                    .filter(|kind| *kind != VarKind::Any);
+
+                    let bitmask = if is_var {
+                        all_comb(n, expr_list.len())
                     } else {
-                        continue; // good match, operate on next
-                    }
-                }
+                        set_one(n, expr_list.len())
+                    };
+                    // no combination of matches left
+                    let Some(bitmask) = bitmask else {
+                        continue;
+                    };
 
-                _ => return Err(()), // mismatching expression
-            },
-            MatchGroup::Multiple {
-                expr_list,
-                patt_list: mut patt_rest,
-                kind,
-            } => {
-                if let Some(patt) = patt_rest.pop() {
-                    let esize = match expr_list.len() {
-                        0 => return Err(()), // empty expression list
-                        n => n,
+                    let mut bitmask = bitmask.into_iter();
+                    let (mut expr, expr_rest): (Vec<_>, Vec<_>) = expr_list
+                        .iter()
+                        .cloned()
+                        .partition(|_| bitmask.next().unwrap());
+
+                    let expr = if expr.len() == 1 {
+                        expr.pop().unwrap()
+                    } else {
+                        Variadic::new(expr, kind).into()
                     };
-                    let is_var = matches!(patt, Expression::Variable(_));
 
-                    for bitmask in (0..).map_while(|n| {
-                        if is_var {
-                            all_comb(n, esize)
-                        } else {
-                            set_one(n, esize)
-                        }
-                    }) {
-                        let mut bitmask = bitmask.into_iter();
-                        let (mut expr, expr_rest): (Vec<_>, Vec<_>) = expr_list
+                    // the next combination is tried once everything following from this one has
+                    let search = typed.is_none_or(|kind| kind.admits(&expr)).then(|| {
+                        let stack = stack
                             .iter()
                             .cloned()
-                            .partition(|_| bitmask.next().unwrap());
+                            .chain([
+                                MatchGroup::Multiple {
+                                    expr_list: expr_rest,
+                                    patt_list: patt_rest.clone(),
+                                    kind,
+                                },
+                                MatchGroup::Single {
+                                    expr,
+                                    patt: patt.clone(),
+                                },
+                            ])
+                            .collect();
+                        (stack, bind.clone())
+                    });
+
+                    self.frames.push(Frame::Choices {
+                        stack,
+                        bind,
+                        expr_list,
+                        patt,
+                        patt_rest,
+                        kind,
+                        n: n + 1,
+                    });
+
+                    match search {
+                        Some(search) => search,
+                        None => continue,
+                    }
+                }
+            };
+
+            if let Ok(bind) = self.search(stack, bind) {
+                if self.seen.insert(bind.clone()) {
+                    return Some(bind);
+                }
+            }
+        }
 
-                        let expr = if expr.len() == 1 {
-                            expr.pop().unwrap()
+        None
+    }
+}
+
+impl Matches {
+    // ONLY escape out of loop with return Err, or by reaching a choice, which is pushed as a
+    // frame and also returns Err, the match is then continued from that frame
+
+    fn search(&mut self, mut stack: Vec<MatchGroup>, mut bind: Bindings) -> Result<Bindings, ()> {
+        while let Some(match_comb) = stack.pop() {
+            match match_comb {
+                MatchGroup::Single { expr, patt } => match (expr, patt) {
+                    (Expression::Concrete(ec), Expression::Concrete(pc)) => match (ec, pc) {
+                        (
+                            Concrete::Variadic(Variadic {
+                                terms: eterms,
+                                kind: ekind,
+                            }),
+                            Concrete::Variadic(Variadic {
+                                terms: pterms,
+                                kind: pkind,
+                            }),
+                        ) if ekind == pkind => {
+                            stack.push(MatchGroup::Multiple {
+                                expr_list: eterms.into_iter().collect(),
+                                patt_list: pterms.into_iter().collect(),
+                                kind: ekind,
+                            });
+                            continue; // operate on next
+                        }
+                        (
+                            Concrete::Unary(Unary {
+                                argument: earg,
+                                kind: ekind,
+                            }),
+                            Concrete::Unary(Unary {
+                                argument: parg,
+                                kind: pkind,
+                            }),
+                        ) if ekind == pkind => {
+                            stack.push(MatchGroup::Single {
+                                expr: *earg,
+                                patt: *parg,
+                            });
+                            continue; // operate on next
+                        }
+                        (
+                            Concrete::Unary(Unary {
+                                argument: earg,
+                                kind: UnaryKind::Named { id },
+                            }),
+                            Concrete::Unary(Unary {
+                                argument: parg,
+                                kind: UnaryKind::Variable { id: pv, relations },
+                            }),
+                        ) => {
+                            let Some(id) = unrelate_all(&relations, &id) else {
+                                return Err(());
+                            };
+
+                            if *bind.functions.entry(pv).or_insert_with(|| id.clone()) != id {
+                                return Err(());
+                            }
+
+                            stack.push(MatchGroup::Single {
+                                expr: *earg,
+                                patt: *parg,
+                            });
+                            continue; // operate on next
+                        }
+                        (
+                            Concrete::Power(Power {
+                                base: ebase,
+                                exponent: eexp,
+                            }),
+                            Concrete::Power(Power {
+                                base: pbase,
+                                exponent: pexp,
+                            }),
+                        ) => {
+                            stack.push(MatchGroup::Single {
+                                expr: *eexp,
+                                patt: *pexp,
+                            });
+                            stack.push(MatchGroup::Single {
+                                expr: *ebase,
+                                patt: *pbase,
+                            });
+                            continue; // operate on next
+                        }
+                        (Concrete::Literal(el), Concrete::Literal(pl)) if el == pl => continue, // good match, operate on next
+                        _ => return Err(()), // mismatching literal
+                    },
+                    (expr, Expression::Variable(pv)) => {
+                        if !pv.kind.admits(&expr)
+                            || *bind
+                                .terms
+                                .entry(pv.untyped())
+                                .or_insert_with(|| expr.clone())
+                                != expr
+                        {
+                            return Err(());
                         } else {
-                            Variadic::new(expr, kind).into()
-                        };
-
-                        if let Ok(bind) = match_pattern_rec(
-                            stack
-                                .iter()
-                                .cloned()
-                                .chain([
-                                    MatchGroup::Multiple {
-                                        expr_list: expr_rest,
-                                        patt_list: patt_rest.clone(),
-                                        kind,
-                                    },
-                                    MatchGroup::Single {
-                                        expr,
-                                        patt: patt.clone(),
-                                    },
-                                ])
-                                .collect(),
-                            bind.clone(),
-                        ) {
-                            // should only return if base case is reached
-                            return Ok(bind);
+                            continue; // good match, operate on next
                         }
                     }
 
-                    // no combination of matches work
-                    return Err(());
-                } else if expr_list.is_empty() {
-                    // both empty, operate on next
-                    continue;
-                } else {
-                    // empty pattern list
-                    return Err(());
-                }
+                    _ => return Err(()), // mismatching expression
+                },
+                MatchGroup::Multiple {
+                    expr_list,
+                    patt_list: mut patt_rest,
+                    kind,
+                } => {
+                    // every pattern that isn't a variable needs a term of its own, checking
+                    // that first saves trying every way of splitting the terms between variables
+                    if !terms_for_patterns(&expr_list, &patt_rest) {
+                        return Err(());
+                    }
+
+                    if let Some(patt) = patt_rest.pop() {
+                        if expr_list.is_empty() {
+                            return Err(()); // empty expression list
+                        }
 
-                //unreachable!("must diverge");
+                        self.frames.push(Frame::Choices {
+                            stack,
+                            bind,
+                            expr_list,
+                            patt,
+                            patt_rest,
+                            kind,
+                            n: 0,
+                        });
+                        return Err(());
+                    } else if expr_list.is_empty() {
+                        // both empty, operate on next
+                        continue;
+                    } else {
+                        // empty pattern list
+                        return Err(());
+                    }
+                }
             }
         }
 
-        //unreachable!("must diverge");
+        // base case: stack empty
+        Ok(bind)
     }
-
-    // base case: backtrack stack empty
-    Ok(bind)
 }
 
 #[derive(Clone, Debug)]
@@ -155,6 +317,93 @@ enum MatchGroup {
     },
 }
 
+// whether the patterns that aren't variables can each be given a different term they might
+// match, by finding a matching between them
+fn terms_for_patterns(expr_list: &[Expression], patt_list: &[Expression]) -> bool {
+    // gives patt a term, moving the pattern that had it on to another one if it can, owner
+    // is which pattern each term went to
+    fn assign(
+        patt: usize,
+        candidates: &[Vec<usize>],
+        owner: &mut [Option<usize>],
+        seen: &mut [bool],
+    ) -> bool {
+        for &term in &candidates[patt] {
+            if !seen[term] {
+                seen[term] = true;
+                if owner[term].is_none_or(|other| assign(other, candidates, owner, seen)) {
+                    owner[term] = Some(patt);
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    let candidates: Vec<Vec<usize>> = patt_list
+        .iter()
+        .filter(|patt| !matches!(patt, Expression::Variable(_)))
+        .map(|patt| {
+            (0..expr_list.len())
+                .filter(|&i| may_match(&expr_list[i], patt))
+                .collect()
+        })
+        .collect();
+    let mut owner = vec![None; expr_list.len()];
+
+    (0..candidates.len()).all(|patt| {
+        assign(
+            patt,
+            &candidates,
+            &mut owner,
+            &mut vec![false; expr_list.len()],
+        )
+    })
+}
+
+// false only if patt can't match expr whatever the bindings are, variables in sums and
+// products can take no terms so they're always let through
+fn may_match(expr: &Expression, patt: &Expression) -> bool {
+    match (expr, patt) {
+        (_, Expression::Variable(_)) => true,
+        (Expression::Concrete(ec), Expression::Concrete(pc)) => match (ec, pc) {
+            (
+                Concrete::Variadic(Variadic { kind: ekind, .. }),
+                Concrete::Variadic(Variadic { kind: pkind, .. }),
+            ) => ekind == pkind,
+            (
+                Concrete::Unary(Unary {
+                    argument: earg,
+                    kind: ekind,
+                }),
+                Concrete::Unary(Unary {
+                    argument: parg,
+                    kind: pkind,
+                }),
+            ) => {
+                let kinds = match (ekind, pkind) {
+                    (UnaryKind::Named { .. }, UnaryKind::Variable { .. }) => true,
+                    (ekind, pkind) => ekind == pkind,
+                };
+                kinds && may_match(earg, parg)
+            }
+            (
+                Concrete::Power(Power {
+                    base: ebase,
+                    exponent: eexp,
+                }),
+                Concrete::Power(Power {
+                    base: pbase,
+                    exponent: pexp,
+                }),
+            ) => may_match(ebase, pbase) && may_match(eexp, pexp),
+            (Concrete::Literal(el), Concrete::Literal(pl)) => el == pl,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
 // transforms n in 0..size to bitmask of length size
 fn set_one(n: usize, size: usize) -> Option<Vec<bool>> {
     if n < size {
@@ -181,7 +430,7 @@ fn basic_test() {
     let pat = Lisp::parse("(func2 (/ (- var0)))").unwrap();
     let exp = Lisp::parse("(func2 (/ (- (* 2 2))))").unwrap();
 
-    let results = match_pattern(exp, pat.clone());
+    let results = match_pattern(exp, pat.clone()).map(|b| b.terms);
 
     assert_eq!(
         results,
@@ -195,7 +444,7 @@ fn basic_test() {
 
     let exp = Lisp::parse("(+ 3 1)").unwrap();
 
-    let results = match_pattern(exp, pat);
+    let results = match_pattern(exp, pat).map(|b| b.terms);
 
     assert_eq!(results, Err(()));
 
@@ -214,7 +463,7 @@ fn basic_variadic_test() {
 
     println!("expression: {exp}");
 
-    let results = match_pattern(exp, pat.clone());
+    let results = match_pattern(exp, pat.clone()).map(|b| b.terms);
 
     assert_eq!(
         results,
@@ -228,7 +477,7 @@ fn basic_variadic_test() {
 
     let exp = Lisp::parse("(* (+ 4))").unwrap();
 
-    let results = match_pattern(exp, pat);
+    let results = match_pattern(exp, pat).map(|b| b.terms);
 
     assert_eq!(
         results,
@@ -253,7 +502,7 @@ fn advanced_variadic_test() {
 
     println!("expression: {exp}");
 
-    let results = match_pattern(exp, pat);
+    let results = match_pattern(exp, pat).map(|b| b.terms);
 
     assert_eq!(
         results,
@@ -264,4 +513,108 @@ fn advanced_variadic_test() {
     );
 
     println!("match: {results:?}");
+
+    // two products can't both match the one product there is, however its factors are split
+    let product = format!("(* {})", ["(sec x)", "(sin x)"].repeat(8).join(" "));
+    let exp = Lisp::parse(&format!("(+ {product} (- {product}) 1)")).unwrap();
+    let pat = Lisp::parse("(+ (* a b) (* a c) ..)").unwrap();
+
+    assert_eq!(match_pattern(exp, pat), Err(()));
+}
+
+#[test]
+fn typed_variable_test() {
+    use crate::text::*;
+
+    let pat = Lisp::parse("(* ?n:int ?f:func a)").unwrap();
+    let exp = Lisp::parse("(* x 3 (sin x) y)").unwrap();
+
+    assert_eq!(
+        match_pattern(exp, pat.clone()).map(|b| b.terms),
+        Ok(BTreeMap::from([
+            (Variable::new("n"), Lisp::parse("3").unwrap()),
+            (Variable::new("f"), Lisp::parse("(sin x)").unwrap()),
+            (Variable::new("a"), Lisp::parse("(* x y)").unwrap()),
+        ]))
+    );
+
+    let exp = Lisp::parse("(* x PI (sin x))").unwrap();
+
+    assert_eq!(match_pattern(exp.clone(), pat), Err(()));
+
+    let pat = Lisp::parse("(* ?c:const ?x:var ?x)").unwrap();
+
+    assert_eq!(
+        match_pattern(exp, pat),
+        Err(()),
+        "(sin x) is not a variable"
+    );
+    assert_eq!(
+        match_pattern(
+            Lisp::parse("(* x PI x)").unwrap(),
+            Lisp::parse("(* ?c:const ?x:var ?x)").unwrap()
+        )
+        .map(|b| b.terms),
+        Ok(BTreeMap::from([
+            (Variable::new("c"), Lisp::parse("PI").unwrap()),
+            (Variable::new("x"), Lisp::parse("x").unwrap()),
+        ]))
+    );
+}
+
+#[test]
+fn function_variable_test() {
+    use crate::text::*;
+
+    let pat = Lisp::parse("(* (F u) ((recip F) u))").unwrap();
+    let results = match_pattern(Lisp::parse("(* (sec x) (cos x))").unwrap(), pat.clone());
+
+    // F can be either of the two functions
+    assert_eq!(
+        results.as_ref().map(|b| &b.terms),
+        Ok(&BTreeMap::from([(
+            Variable::new("u"),
+            Lisp::parse("x").unwrap()
+        )]))
+    );
+    assert!(["sec", "cos"]
+        .map(FuncId::new)
+        .contains(&results.as_ref().unwrap().functions[&VarId::new("F")]));
+
+    println!("match: {results:?}");
+
+    assert_eq!(
+        match_pattern(Lisp::parse("(* (sin x) (sec x))").unwrap(), pat.clone()),
+        Err(())
+    );
+    assert_eq!(
+        match_pattern(Lisp::parse("(* (f x) (g x))").unwrap(), pat),
+        Err(())
+    );
+}
+
+#[test]
+fn match_all_test() {
+    use crate::text::*;
+
+    let pat = Lisp::parse("(+ (* a b) (* a c) ..)").unwrap();
+    let exp = Lisp::parse("(+ (* a b) (* a c) (* d b))").unwrap();
+    let matches: Vec<_> = match_all(exp.clone(), pat.clone()).collect();
+
+    // the first match is the one match_pattern finds, and none repeat
+    assert_eq!(Ok(&matches[0]), match_pattern(exp, pat).as_ref());
+    assert!(matches
+        .iter()
+        .enumerate()
+        .all(|(i, m)| !matches[..i].contains(m)));
+
+    // factoring out a or b
+    for common in ["a", "b"] {
+        assert!(matches
+            .iter()
+            .any(|m| m.terms[&Variable::new("a")] == Lisp::parse(common).unwrap()));
+    }
+
+    let exp = Lisp::parse("(* x y)").unwrap();
+    assert_eq!(match_all(exp, Lisp::parse("(+ a b)").unwrap()).count(), 0);
 }
@@ -0,0 +1,133 @@
+use crate::expression::*;
+
+// subexpressions are addressed by the path of child indices leading to them from the root,
+// children are the terms of a sum or product in their sorted order, the argument of a unary,
+// or the base and then the exponent of a power
+
+// the root is the empty path, printed as [] like the certificates do, and [0 1] is the second
+// child of the first child
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Debug, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct Position(pub Vec<usize>);
+
+impl Position {
+    pub fn root() -> Self {
+        Self::default()
+    }
+
+    // the position of the ith child of the subexpression here
+    pub fn child(&self, i: usize) -> Self {
+        Self(self.0.iter().copied().chain([i]).collect())
+    }
+}
+
+impl std::ops::Deref for Position {
+    type Target = [usize];
+
+    fn deref(&self) -> &[usize] {
+        &self.0
+    }
+}
+
+impl From<Vec<usize>> for Position {
+    fn from(path: Vec<usize>) -> Self {
+        Self(path)
+    }
+}
+
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let indices: Vec<_> = self.0.iter().map(usize::to_string).collect();
+        write!(f, "[{}]", indices.join(" "))
+    }
+}
+
+pub fn children(expr: &Expression) -> Vec<&Expression> {
+    match expr {
+        Expression::Concrete(Concrete::Variadic(Variadic { terms, .. })) => terms.iter().collect(),
+        Expression::Concrete(Concrete::Unary(Unary { argument, .. })) => vec![argument],
+        Expression::Concrete(Concrete::Power(Power { base, exponent })) => vec![base, exponent],
+        _ => vec![],
+    }
+}
+
+// None if the path leads out of the expression
+pub fn subexpression<'a>(expr: &'a Expression, position: &[usize]) -> Option<&'a Expression> {
+    match position {
+        [] => Some(expr),
+        [i, rest @ ..] => subexpression(children(expr).get(*i)?, rest),
+    }
+}
+
+// expr with the subexpression at position swapped for new, sums and products are sorted again
+// so the indices of their terms may change
+pub fn replace_subexpression(
+    expr: Expression,
+    position: &[usize],
+    new: Expression,
+) -> Option<Expression> {
+    let [i, rest @ ..] = position else {
+        return Some(new);
+    };
+
+    match expr {
+        Expression::Concrete(Concrete::Variadic(Variadic { terms, kind })) => {
+            let mut terms: Vec<_> = terms.into_iter().collect();
+            let term = terms.get_mut(*i)?;
+            *term = replace_subexpression(term.clone(), rest, new)?;
+            Some(Variadic::new(terms, kind).into())
+        }
+        Expression::Concrete(Concrete::Unary(Unary { argument, kind })) if *i == 0 => {
+            Some(Unary::new(replace_subexpression(*argument, rest, new)?, kind).into())
+        }
+        Expression::Concrete(Concrete::Power(Power { base, exponent })) => match i {
+            0 => Some(Power::new(replace_subexpression(*base, rest, new)?, *exponent).into()),
+            1 => Some(Power::new(*base, replace_subexpression(*exponent, rest, new)?).into()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// every position in expr, parents before their children
+pub fn positions(expr: &Expression) -> Vec<Position> {
+    let mut output = vec![Position::root()];
+
+    for (i, child) in children(expr).into_iter().enumerate() {
+        output.extend(positions(child).into_iter().map(|mut p| {
+            p.0.insert(0, i);
+            p
+        }));
+    }
+
+    output
+}
+
+#[test]
+fn test_positions() {
+    use crate::text::*;
+
+    let expr = Lisp::parse("(+ (^ (sin x) 2) (* a b))").unwrap();
+
+    assert_eq!(
+        subexpression(&expr, &[1, 0, 0]),
+        Some(&Lisp::parse("x").unwrap())
+    );
+    assert_eq!(subexpression(&expr, &[1, 2]), None);
+    assert_eq!(positions(&expr).len(), 8);
+    assert_eq!(Position::root().child(1).child(0).to_string(), "[1 0]");
+
+    // (* a b) sorts before the power, the power it's replaced with sorts after the other one
+    assert_eq!(
+        subexpression(&expr, &[0]),
+        Some(&Lisp::parse("(* a b)").unwrap())
+    );
+    let power = Lisp::parse("(^ z 3)").unwrap();
+    let replaced = replace_subexpression(expr, &[0], power.clone()).unwrap();
+    assert_eq!(replaced, Lisp::parse("(+ (^ (sin x) 2) (^ z 3))").unwrap());
+    assert_eq!(subexpression(&replaced, &[1]), Some(&power));
+}
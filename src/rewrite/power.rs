@@ -0,0 +1,133 @@
+use {
+    crate::{expression::*, rewrite::transform_recursive},
+    num_traits::{Signed, ToPrimitive},
+};
+
+// conversions between repeated factors of a product and powers, expressions are kept with
+// small integer powers expanded so that rules only need to be written for one form
+
+pub const EXPAND_POWERS_NAME: &str = "powers";
+// larger powers are left alone, they would make products too big to match against
+pub const MAX_EXPANDED_POWER: u32 = 8;
+
+// (^ b n) becomes (* b b ...) for integer n up to MAX_EXPANDED_POWER, and its reciprocal
+// for negative n
+pub fn expand_powers(expr: Expression) -> Expression {
+    transform_recursive(expr, &mut |e| match e {
+        Expression::Concrete(Concrete::Power(Power { base, exponent })) => {
+            let n = match exponent.as_ref() {
+                Expression::Concrete(Concrete::Literal(Literal {
+                    value: LiteralValue::Integer(n),
+                })) => n
+                    .abs()
+                    .to_u32()
+                    .filter(|n| (1..=MAX_EXPANDED_POWER).contains(n)),
+                _ => None,
+            };
+
+            let Some(n) = n else {
+                return Power { base, exponent }.into();
+            };
+
+            let expanded = if n == 1 {
+                *base
+            } else {
+                let factors = match *base {
+                    Expression::Concrete(Concrete::Variadic(Variadic {
+                        terms,
+                        kind: VariadicKind::Multiplication,
+                    })) => terms.into_iter().collect(),
+                    base => vec![base],
+                };
+
+                Variadic::new(
+                    (0..n).flat_map(|_| factors.iter().cloned()),
+                    VariadicKind::Multiplication,
+                )
+                .into()
+            };
+
+            if exponent_is_negative(&exponent) {
+                Unary::new(expanded, UnaryKind::Reciprocal).into()
+            } else {
+                expanded
+            }
+        }
+        e => e,
+    })
+}
+
+// repeated factors of a product become a power of that factor, (* x x y) is (* (^ x 2) y)
+pub fn collect_powers(expr: Expression) -> Expression {
+    transform_recursive(expr, &mut |e| match e {
+        Expression::Concrete(Concrete::Variadic(Variadic {
+            terms,
+            kind: VariadicKind::Multiplication,
+        })) => {
+            let mut factors: Vec<(Expression, i32)> = Vec::new();
+
+            for term in terms.into_iter() {
+                match factors.last_mut() {
+                    Some((factor, count)) if *factor == term => *count += 1,
+                    _ => factors.push((term, 1)),
+                }
+            }
+
+            let mut factors: Vec<Expression> = factors
+                .into_iter()
+                .map(|(factor, count)| match count {
+                    1 => factor,
+                    n => Power::new(factor, Literal::new(n).into()).into(),
+                })
+                .collect();
+
+            if factors.len() == 1 {
+                factors.pop().unwrap()
+            } else {
+                Variadic::new(factors, VariadicKind::Multiplication).into()
+            }
+        }
+        e => e,
+    })
+}
+
+fn exponent_is_negative(exponent: &Expression) -> bool {
+    matches!(
+        exponent,
+        Expression::Concrete(Concrete::Literal(Literal {
+            value: LiteralValue::Integer(n),
+        })) if n.is_negative()
+    )
+}
+
+#[test]
+fn test_powers() {
+    use crate::text::*;
+
+    let expand = |s| expand_powers(Lisp::parse(s).unwrap());
+    let collect = |s| collect_powers(Lisp::parse(s).unwrap());
+
+    assert_eq!(
+        expand("(+ (^ (sin x) 2) (^ (cos x) 2))"),
+        Lisp::parse("(+ (* (sin x) (sin x)) (* (cos x) (cos x)))").unwrap()
+    );
+    assert_eq!(
+        expand("(^ (* a b) -2)"),
+        Lisp::parse("(/ (* a a b b))").unwrap()
+    );
+    assert_eq!(expand("(^ x n)"), Lisp::parse("(^ x n)").unwrap());
+    assert_eq!(expand("(^ x 100)"), Lisp::parse("(^ x 100)").unwrap());
+    assert_eq!(
+        collect("(* x y x (sin x) (sin x))"),
+        Lisp::parse("(* (^ x 2) y (^ (sin x) 2))").unwrap()
+    );
+    assert_eq!(collect("(* x x x)"), Lisp::parse("(^ x 3)").unwrap());
+    assert_eq!(
+        collect_powers(expand("(* (^ (tan x) 5) y)")),
+        Lisp::parse("(* (^ (tan x) 5) y)").unwrap()
+    );
+
+    // more copies of a term than fit in a byte
+    let many = format!("(* {})", vec!["x"; 300].join(" "));
+    assert_eq!(collect(&many), Lisp::parse("(^ x 300)").unwrap());
+}
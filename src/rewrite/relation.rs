@@ -0,0 +1,155 @@
+use crate::{expression::*, rewrite::transform_recursive};
+
+// relationships between named functions that function variables can be written against,
+// each pair maps the first function to the second, recip and cofunc are their own inverses so
+// they go both ways, torecip only takes sin, cos and tan to their reciprocals
+const RELATIONS: [(&str, &[(&str, &str)]); 3] = [
+    (
+        "recip",
+        &[
+            ("sin", "csc"),
+            ("cos", "sec"),
+            ("tan", "cot"),
+            ("csc", "sin"),
+            ("sec", "cos"),
+            ("cot", "tan"),
+        ],
+    ),
+    (
+        "cofunc",
+        &[
+            ("sin", "cos"),
+            ("tan", "cot"),
+            ("sec", "csc"),
+            ("cos", "sin"),
+            ("cot", "tan"),
+            ("csc", "sec"),
+        ],
+    ),
+    ("torecip", &[("sin", "csc"), ("cos", "sec"), ("tan", "cot")]),
+];
+
+fn pairs(relation: &FuncId) -> Option<&'static [(&'static str, &'static str)]> {
+    RELATIONS
+        .iter()
+        .find(|(name, _)| name == relation.as_ref())
+        .map(|(_, pairs)| *pairs)
+}
+
+// the function related to id, None if the relation doesn't exist or doesn't cover it
+pub fn relate(relation: &FuncId, id: &FuncId) -> Option<FuncId> {
+    pairs(relation)?
+        .iter()
+        .find(|(from, _)| from == id.as_ref())
+        .map(|(_, to)| FuncId::new(to))
+}
+
+// the function relate maps to id
+pub fn unrelate(relation: &FuncId, id: &FuncId) -> Option<FuncId> {
+    pairs(relation)?
+        .iter()
+        .find(|(_, to)| to == id.as_ref())
+        .map(|(from, _)| FuncId::new(from))
+}
+
+// applies the relations from the last one to the first, like the nesting in (r0 (r1 F))
+pub fn relate_all(relations: &[FuncId], id: &FuncId) -> Option<FuncId> {
+    relations
+        .iter()
+        .rev()
+        .try_fold(id.clone(), |id, relation| relate(relation, &id))
+}
+
+// the function that relate_all maps to id, relations are undone from the first one
+pub fn unrelate_all(relations: &[FuncId], id: &FuncId) -> Option<FuncId> {
+    relations
+        .iter()
+        .try_fold(id.clone(), |id, relation| unrelate(relation, &id))
+}
+
+pub fn is_relation(name: &str) -> bool {
+    RELATIONS.iter().any(|(relation, _)| *relation == name)
+}
+
+// outside of rules an uppercase name like F is just another function, so every function
+// variable becomes the function of the same name, errs with the first one written against
+// a relation since that has no meaning for a plain function
+pub fn name_functions(expr: Expression) -> Result<Expression, VarId> {
+    let mut related = None;
+
+    let named = transform_recursive(expr, &mut |e| match e {
+        Expression::Concrete(Concrete::Unary(Unary {
+            argument,
+            kind: UnaryKind::Variable { id, relations },
+        })) => {
+            if !relations.is_empty() {
+                related.get_or_insert(id.clone());
+            }
+
+            Expression::Concrete(Concrete::Unary(Unary {
+                argument,
+                kind: UnaryKind::Named {
+                    id: FuncId::new(id.as_ref()),
+                },
+            }))
+        }
+        e => e,
+    });
+
+    match related {
+        Some(id) => Err(id),
+        None => Ok(named),
+    }
+}
+
+#[test]
+fn test_relate() {
+    let [sin, cos, csc, sec] = ["sin", "cos", "csc", "sec"].map(FuncId::new);
+    let [recip, cofunc] = ["recip", "cofunc"].map(FuncId::new);
+
+    assert_eq!(relate(&recip, &sin), Some(csc.clone()));
+    assert_eq!(relate(&recip, &csc), Some(sin.clone()));
+    assert_eq!(relate(&cofunc, &sin), Some(cos.clone()));
+    assert_eq!(
+        relate_all(&[recip.clone(), cofunc.clone()], &sin),
+        Some(sec.clone())
+    );
+    assert_eq!(
+        unrelate_all(&[recip.clone(), cofunc.clone()], &sec),
+        Some(sin.clone())
+    );
+    assert_eq!(relate(&recip, &FuncId::new("f")), None);
+
+    // torecip only goes one way
+    let torecip = FuncId::new("torecip");
+    assert_eq!(relate(&torecip, &sin), Some(csc.clone()));
+    assert_eq!(relate(&torecip, &csc), None);
+    assert_eq!(unrelate(&torecip, &csc), Some(sin.clone()));
+    assert_eq!(unrelate(&torecip, &sin), None);
+    assert_eq!(relate(&FuncId::new("inverse"), &sin), None);
+    assert!(is_relation("cofunc") && !is_relation("sin"));
+}
+
+#[test]
+fn test_name_functions() {
+    use crate::{rewrite::match_pattern, text::*};
+
+    let written = Lisp::parse("(+ (F x) (sin (G (F y))))").unwrap();
+    let named = name_functions(written.clone()).unwrap();
+
+    assert_eq!(named.to_string(), written.to_string());
+    assert!(match_pattern(
+        Lisp::parse("(sin x)").unwrap(),
+        Lisp::parse("(F x)").unwrap()
+    )
+    .is_ok());
+    assert!(match_pattern(
+        Lisp::parse("(sin x)").unwrap(),
+        name_functions(Lisp::parse("(F x)").unwrap()).unwrap()
+    )
+    .is_err());
+    assert_eq!(
+        name_functions(Lisp::parse("(* (F u) ((recip F) u))").unwrap()),
+        Err(VarId::new("F"))
+    );
+}
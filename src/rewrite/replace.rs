@@ -1,22 +1,23 @@
-use {crate::expression::*, std::collections::BTreeMap};
+use crate::{expression::*, rewrite::*};
 
-pub fn replace_variable(
-    expr: Expression,
-    bind: &BTreeMap<Variable, Expression>,
-) -> Result<Expression, ()> {
+// fails on an unbound variable, or a relation that doesn't cover the function a variable is bound to
+#[allow(clippy::result_unit_err)]
+pub fn replace_variable(expr: Expression, bind: &Bindings) -> Result<Expression, ()> {
     match expr {
         Expression::Concrete(c) => match c {
             Concrete::Variadic(Variadic { terms, kind }) => {
                 let mut terms_new = Vec::new();
 
                 for term in terms.into_iter() {
+                    // variadics of the same kind are flattened, so an empty one disappears, but
+                    // an empty one of the other kind is kept, (+) in a product is still 0
                     match replace_variable(term, bind)? {
                         Expression::Concrete(Concrete::Variadic(Variadic {
                             terms: inner_terms,
                             kind: inner_kind,
-                        })) if inner_kind == kind || inner_terms.is_empty() => {
+                        })) if inner_kind == kind => {
                             terms_new.extend(inner_terms.into_iter())
-                        } // flatten variadics, empty ones are removed
+                        }
                         term => terms_new.push(term),
                     }
                 }
@@ -24,27 +25,42 @@ pub fn replace_variable(
                 Ok(Variadic::new(terms_new, kind).into())
             }
             Concrete::Unary(Unary { argument, kind }) => {
+                let kind = match kind {
+                    UnaryKind::Variable { id, relations } => UnaryKind::Named {
+                        id: relate_all(&relations, bind.functions.get(&id).ok_or(())?).ok_or(())?,
+                    },
+                    kind => kind,
+                };
+
                 Ok(Unary::new(replace_variable(*argument, bind)?, kind).into())
             }
+            Concrete::Power(Power { base, exponent }) => Ok(Power::new(
+                replace_variable(*base, bind)?,
+                replace_variable(*exponent, bind)?,
+            )
+            .into()),
             Concrete::Literal(Literal { value }) => Ok(Literal::new(value).into()),
         },
-        Expression::Variable(v) => Ok(bind.get(&v).cloned().ok_or(())?),
+        Expression::Variable(v) => Ok(bind.terms.get(&v.untyped()).cloned().ok_or(())?),
     }
 }
 
 #[test]
 fn test_replace() {
-    use crate::text::*;
+    use {crate::text::*, std::collections::BTreeMap};
 
     let exp = Lisp::parse("(+ var9 (* var7 (+ var5 var9)))").unwrap();
 
     println!("expression: {exp}");
 
-    let bind = BTreeMap::from([
-        (Variable::new("var5"), Lisp::parse("42").unwrap()),
-        (Variable::new("var7"), Lisp::parse("65").unwrap()),
-        (Variable::new("var9"), Lisp::parse("3").unwrap()),
-    ]);
+    let bind = Bindings {
+        terms: BTreeMap::from([
+            (Variable::new("var5"), Lisp::parse("42").unwrap()),
+            (Variable::new("var7"), Lisp::parse("65").unwrap()),
+            (Variable::new("var9"), Lisp::parse("3").unwrap()),
+        ]),
+        ..Default::default()
+    };
 
     println!("bindings: {bind:?}");
 
@@ -52,4 +68,33 @@ fn test_replace() {
 
     assert_eq!(result, Ok(Lisp::parse("(+ 3 (* 65 (+ 42 3)))").unwrap()));
     println!("result: {result:?}");
+
+    // an empty sum is only flattened into another sum
+    let bind = Bindings {
+        terms: BTreeMap::from([(Variable::new("a"), Lisp::parse("(+)").unwrap())]),
+        ..Default::default()
+    };
+    assert_eq!(
+        replace_variable(Lisp::parse("(+ 2 (* 3 a) a)").unwrap(), &bind),
+        Ok(Lisp::parse("(+ 2 (* 3 (+)))").unwrap())
+    );
+}
+
+#[test]
+fn test_replace_function() {
+    use {crate::text::*, std::collections::BTreeMap};
+
+    let exp = Lisp::parse("(* (F u) ((recip F) u) ((cofunc (recip F)) u))").unwrap();
+    let mut bind = Bindings {
+        terms: BTreeMap::from([(Variable::new("u"), Lisp::parse("x").unwrap())]),
+        functions: BTreeMap::from([(VarId::new("F"), FuncId::new("sin"))]),
+    };
+
+    assert_eq!(
+        replace_variable(exp.clone(), &bind),
+        Ok(Lisp::parse("(* (sin x) (csc x) (sec x))").unwrap())
+    );
+
+    bind.functions.insert(VarId::new("F"), FuncId::new("f"));
+    assert_eq!(replace_variable(exp, &bind), Err(()));
 }
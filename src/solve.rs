@@ -1,140 +1,563 @@
+pub use {
+    crate::expression::expression_size, analysis::*, certificate::*, egraph::*, numeric::*,
+    prover::*, search::*, soundness::*, strategy::*,
+};
 use {
     crate::{expression::*, rewrite::*, text::*},
     petgraph::prelude::*,
-    std::{collections::BTreeMap, path::PathBuf},
+    std::{
+        collections::{BTreeMap, HashMap},
+        ffi::OsStr,
+        path::PathBuf,
+        time::{Duration, Instant},
+    },
 };
 
-const RULESETS_DIR: &str = "./rules";
+mod analysis;
+mod certificate;
+mod egraph;
+mod numeric;
+mod prover;
+mod search;
+mod soundness;
+mod strategy;
+
 const MACRO_PREFIX: &str = "#";
+// files in a rules directory without it are left alone, like notes or editor swap files
+const RULESET_EXTENSION: &str = "lisp";
+// directories of rulesets to load on top of the built-in ones, separated like PATH
+pub const RULES_ENV: &str = "IDENTITY_RULES";
+// the ruleset applied along with every other one, rulesets work the same without it
+pub const SIMPLIFY_NAME: &str = "simplify";
+
+const BUILTIN_RULESETS: [(&str, &str); 8] = [
+    ("cancel.lisp", include_str!("../rules/cancel.lisp")),
+    ("expand.lisp", include_str!("../rules/expand.lisp")),
+    ("reduce.lisp", include_str!("../rules/reduce.lisp")),
+    ("simplify.lisp", include_str!("../rules/simplify.lisp")),
+    ("trigexpand.lisp", include_str!("../rules/trigexpand.lisp")),
+    ("trigpyth.lisp", include_str!("../rules/trigpyth.lisp")),
+    (
+        "trigreducequot.lisp",
+        include_str!("../rules/trigreducequot.lisp"),
+    ),
+    (
+        "trigreducerecip.lisp",
+        include_str!("../rules/trigreducerecip.lisp"),
+    ),
+];
+
+// a macro and its line number
+type Macro = (usize, String);
+
+// the text of a ruleset file and where it came from
+struct RulesetSource {
+    origin: String,
+    text: String,
+}
+
+/// Loads rulesets from the ones built into the crate and from directories of ruleset files.
+///
+/// Rulesets are merged in order, the built-in ones first and then each directory in the
+/// order they were added. A ruleset replaces any earlier one with the same name, and macros
+/// like `#map` and `#strategy` are applied after merging.
+#[derive(Clone, Debug)]
+pub struct RulesetLoader {
+    dirs: Vec<PathBuf>,
+    builtin: bool,
+}
+
+/// Rulesets by name, the strategies defined with `#strategy`, and the file each one was
+/// loaded from.
+pub struct LoadedRulesets {
+    pub rules: BTreeMap<String, Vec<Rule>>,
+    pub strategies: BTreeMap<String, Strategy>,
+    pub origins: BTreeMap<String, String>,
+}
+
+#[derive(Debug)]
+pub enum RulesetLoadError {
+    Io {
+        path: String,
+        error: std::io::Error,
+    },
+    // the rendered diagnostic, which already names the file
+    Parse(String),
+    EmptyMacro {
+        origin: String,
+        line: usize,
+    },
+    UnknownMacro {
+        origin: String,
+        line: usize,
+        name: String,
+    },
+    UnknownRuleset {
+        origin: String,
+        line: usize,
+        name: String,
+    },
+    // a #map whose rulesets didn't settle on one of the rules
+    MapDiverged {
+        origin: String,
+        line: usize,
+        divergence: Divergence,
+    },
+}
+
+impl std::error::Error for RulesetLoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RulesetLoadError::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for RulesetLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RulesetLoadError::Io { path, error } => write!(f, "error: {error}\n --> {path}"),
+            RulesetLoadError::Parse(diagnostic) => write!(f, "{diagnostic}"),
+            RulesetLoadError::EmptyMacro { origin, line } => {
+                write!(f, "error: empty macro\n --> {origin}:{line}")
+            }
+            RulesetLoadError::UnknownMacro { origin, line, name } => {
+                write!(f, "error: unknown macro `{name}`\n --> {origin}:{line}")
+            }
+            RulesetLoadError::UnknownRuleset { origin, line, name } => {
+                write!(f, "error: no ruleset named `{name}`\n --> {origin}:{line}")
+            }
+            RulesetLoadError::MapDiverged {
+                origin,
+                line,
+                divergence,
+            } => {
+                write!(f, "error: map {divergence}\n --> {origin}:{line}")
+            }
+        }
+    }
+}
+
+impl Default for RulesetLoader {
+    fn default() -> Self {
+        Self {
+            dirs: Vec::new(),
+            builtin: true,
+        }
+    }
+}
+
+impl RulesetLoader {
+    /// A loader for just the built-in rulesets.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether to start from the built-in rulesets, true by default.
+    pub fn builtin(mut self, builtin: bool) -> Self {
+        self.builtin = builtin;
+        self
+    }
+
+    /// Adds a directory of ruleset files.
+    pub fn dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dirs.push(dir.into());
+        self
+    }
 
+    /// Adds the directories named in the `IDENTITY_RULES` environment variable.
+    pub fn env_dirs(mut self) -> Self {
+        if let Some(dirs) = std::env::var_os(RULES_ENV) {
+            self.dirs.extend(std::env::split_paths(&dirs));
+        }
+        self
+    }
+
+    /// Loads every ruleset, or returns every error found rather than stopping at the first.
+    pub fn load(&self) -> Result<LoadedRulesets, Vec<RulesetLoadError>> {
+        load_rulesets_from(&self.dirs, self.builtin)
+    }
+}
+
+fn load_rulesets_from(
+    dirs: &[PathBuf],
+    builtin: bool,
+) -> Result<LoadedRulesets, Vec<RulesetLoadError>> {
+    let mut errors = Vec::new();
+    let sources = ruleset_sources(dirs, builtin, &mut errors);
+    let (rules, strategies) = parse_rulesets(&sources, &mut errors);
+
+    if errors.is_empty() {
+        Ok(LoadedRulesets {
+            rules,
+            strategies,
+            origins: sources
+                .into_iter()
+                .map(|(name, source)| (name, source.origin))
+                .collect(),
+        })
+    } else {
+        Err(errors)
+    }
+}
+
+fn ruleset_sources(
+    dirs: &[PathBuf],
+    builtin: bool,
+    errors: &mut Vec<RulesetLoadError>,
+) -> BTreeMap<String, RulesetSource> {
+    let mut sources = BTreeMap::new();
+
+    if builtin {
+        for (file, text) in BUILTIN_RULESETS {
+            sources.insert(
+                file.split_once('.').unwrap().0.to_string(),
+                RulesetSource {
+                    origin: format!("<builtin>/{file}"),
+                    text: text.to_string(),
+                },
+            );
+        }
+    }
+
+    let io_error = |path: &PathBuf, error| RulesetLoadError::Io {
+        path: path.display().to_string(),
+        error,
+    };
+
+    for dir in dirs {
+        let mut files: Vec<_> = match dir.read_dir() {
+            Ok(entries) => entries
+                .filter_map(|d| d.map_err(|e| errors.push(io_error(dir, e))).ok())
+                .map(|d| d.path())
+                .filter(|path| {
+                    path.is_file() && path.extension() == Some(OsStr::new(RULESET_EXTENSION))
+                })
+                .collect(),
+            Err(e) => {
+                errors.push(io_error(dir, e));
+                continue;
+            }
+        };
+        files.sort();
+
+        for file in files {
+            let text = match std::fs::read_to_string(&file) {
+                Ok(text) => text,
+                Err(e) => {
+                    errors.push(io_error(&file, e));
+                    continue;
+                }
+            };
+            let name = file.file_stem().unwrap().to_string_lossy().to_string();
+
+            sources.insert(
+                name,
+                RulesetSource {
+                    origin: file.display().to_string(),
+                    text,
+                },
+            );
+        }
+    }
+
+    sources
+}
+
+// just the built-in rulesets
+#[cfg(test)]
 pub fn load_rulesets() -> Vec<(String, Vec<Rule>)> {
-    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(RULESETS_DIR);
+    RulesetLoader::new()
+        .load()
+        .unwrap_or_else(|errors| panic!("{}", errors[0]))
+        .rules
+        .into_iter()
+        .collect()
+}
 
-    let (mut rules, macros): (BTreeMap<_, _>, BTreeMap<_, _>) = extract_rules_macros(
-        path.read_dir()
-            .unwrap_or_else(|e| panic!("error loading ruleset directory: {path:?}, {e}"))
-            .map(|d| d.unwrap().path()),
-    )
-    .map(|(f, r, m)| ((f.clone(), r), (f, m)))
-    .unzip();
-
-    for (file, macros) in macros.into_iter() {
-        for m in macros {
-            if let [command, args @ ..] = m
-                .trim()
-                .strip_prefix(MACRO_PREFIX)
-                .unwrap()
-                .split_whitespace()
-                .collect::<Vec<_>>()
-                .as_slice()
-            {
-                match *command {
-                    "map" => {
-                        let f = ruleset_combiner(
-                            &args
-                                .iter()
-                                .map(|s| {
-                                    rules
-                                        .get(*s)
-                                        .unwrap_or_else(|| panic!("nonexistent ruleset: {s}"))
-                                })
-                                .collect::<Vec<_>>(),
-                        );
-
-                        let (file, ruleset) = rules.remove_entry(&file).unwrap();
-                        let ruleset = ruleset
-                            .into_iter()
-                            .map(
-                                |Rule {
-                                     pattern,
-                                     replacement,
-                                     kind,
-                                 }| Rule {
-                                    pattern: f(pattern),
-                                    replacement: f(replacement),
-                                    kind,
-                                },
-                            )
-                            .collect();
-
-                        rules.insert(file, ruleset);
+// macros are expanded after merging, so they refer to whichever ruleset won its name
+fn parse_rulesets(
+    sources: &BTreeMap<String, RulesetSource>,
+    errors: &mut Vec<RulesetLoadError>,
+) -> (BTreeMap<String, Vec<Rule>>, BTreeMap<String, Strategy>) {
+    let mut rules = BTreeMap::new();
+    let mut strategies = BTreeMap::new();
+    let mut macros = Vec::new();
+
+    for (name, source) in sources {
+        let (ruleset, ruleset_macros) = extract_rules_macros(source);
+
+        match ruleset {
+            Ok(ruleset) => {
+                rules.insert(name.clone(), ruleset);
+            }
+            Err(e) => errors.push(e),
+        }
+
+        // macros of a ruleset that failed are still checked
+        macros.extend(
+            ruleset_macros
+                .into_iter()
+                .map(|(line, m)| (name, source, line, m)),
+        );
+    }
+
+    for (name, source, line, m) in macros {
+        let origin = &source.origin;
+        let words: Vec<_> = m
+            .trim()
+            .strip_prefix(MACRO_PREFIX)
+            .unwrap()
+            .split_whitespace()
+            .collect();
+        let [command, args @ ..] = words.as_slice() else {
+            errors.push(RulesetLoadError::EmptyMacro {
+                origin: origin.clone(),
+                line,
+            });
+            continue;
+        };
+
+        match *command {
+            "map" => {
+                let mut mapped = Vec::new();
+
+                for &arg in args {
+                    match rules.get(arg) {
+                        Some(ruleset) => mapped.push(ruleset),
+                        // a ruleset that failed to parse has already been reported
+                        None if sources.contains_key(arg) => {}
+                        None => errors.push(RulesetLoadError::UnknownRuleset {
+                            origin: origin.clone(),
+                            line,
+                            name: arg.to_string(),
+                        }),
+                    }
+                }
+
+                if mapped.len() < args.len() {
+                    continue;
+                }
+
+                let f = ruleset_combiner(&mapped, RewriteLimits::default());
+
+                let Some(ruleset) = rules.get(name) else {
+                    continue;
+                };
+                let ruleset = ruleset
+                    .iter()
+                    .cloned()
+                    .map(
+                        |Rule {
+                             pattern,
+                             replacement,
+                             kind,
+                             guard,
+                         }| {
+                            Ok(Rule {
+                                pattern: f(pattern)?,
+                                replacement: f(replacement)?,
+                                kind,
+                                guard,
+                            })
+                        },
+                    )
+                    .collect();
+
+                match ruleset {
+                    Ok(ruleset) => {
+                        rules.insert(name.clone(), ruleset);
+                    }
+                    Err(divergence) => errors.push(RulesetLoadError::MapDiverged {
+                        origin: origin.clone(),
+                        line,
+                        divergence,
+                    }),
+                }
+            }
+            "strategy" => {
+                // errors point into the file, past the macro name
+                let line_start: usize = source
+                    .text
+                    .split_inclusive('\n')
+                    .take(line - 1)
+                    .map(str::len)
+                    .sum();
+                let m = m.trim_end();
+                let offset = m.find(command).unwrap() + command.len();
+
+                match Strategy::parse(&m[offset..]) {
+                    Ok(strategy) => {
+                        let unknown = strategy.rulesets().into_iter().filter(|name| {
+                            !rules.contains_key(*name)
+                                && !sources.contains_key(*name)
+                                && ![FOLD_NAME, EXPAND_POWERS_NAME].contains(name)
+                        });
+                        errors.extend(unknown.map(|arg| RulesetLoadError::UnknownRuleset {
+                            origin: origin.clone(),
+                            line,
+                            name: arg.to_string(),
+                        }));
+
+                        strategies.insert(name.clone(), strategy);
+                    }
+                    Err(mut e) => {
+                        let column = e.span.column + m[..offset].chars().count();
+                        e.span = Span {
+                            column,
+                            ..e.span.shift(line_start + offset, line)
+                        };
+                        errors.push(RulesetLoadError::Parse(e.diagnostic(&source.text, origin)));
                     }
-                    u => panic!("unknown macro: {u}"),
                 }
-            } else {
-                panic!("empty macro in: {file}");
             }
+            u => errors.push(RulesetLoadError::UnknownMacro {
+                origin: origin.clone(),
+                line,
+                name: u.to_string(),
+            }),
+        }
+    }
+
+    // a file with nothing but a strategy isn't also a ruleset
+    for name in strategies.keys() {
+        if rules.get(name).is_some_and(Vec::is_empty) {
+            rules.remove(name);
         }
     }
 
-    rules.into_iter().collect()
+    (rules, strategies)
 }
 
+// rules and the macros with their line numbers
 fn extract_rules_macros(
-    iter: impl Iterator<Item = PathBuf>,
-) -> impl Iterator<Item = (String, Vec<Rule>, Vec<String>)> {
-    iter.map(|file| {
-        let stem = file.file_stem().unwrap().to_str().unwrap().to_string();
-
-        let ext = file
-            .extension()
-            .unwrap_or_else(|| panic!("file missing extension: {file:?}"))
-            .to_str()
-            .unwrap()
-            .to_owned();
-        let name = file.file_name().unwrap().to_owned();
-
-        let text = std::fs::read_to_string(file).unwrap();
-        let (macros, text): (Vec<_>, Vec<_>) =
-            text.lines().partition(|l| l.starts_with(MACRO_PREFIX));
-        let macros: Vec<_> = macros.into_iter().map(|s| s.to_string()).collect();
-        let text = text.into_iter().collect::<Vec<_>>().as_slice().join("\n");
-
-        let rules = match ext.as_str() {
-            "lisp" => Lisp::parse_ruleset(&text),
-            u => panic!("unknown file extension: {u:?}"),
-        }
-        .unwrap_or_else(|e| panic!("error parsing file: {name:?}, {e:?}"));
+    RulesetSource { origin, text }: &RulesetSource,
+) -> (Result<Vec<Rule>, RulesetLoadError>, Vec<Macro>) {
+    let macros = text
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| l.starts_with(MACRO_PREFIX))
+        .map(|(i, l)| (i + 1, l.to_string()))
+        .collect();
+    // macro lines are left empty so line numbers in errors match the file
+    let text = text
+        .lines()
+        .map(|l| if l.starts_with(MACRO_PREFIX) { "" } else { l })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let rules = Lisp::parse_ruleset(&text)
+        .map_err(|e| RulesetLoadError::Parse(e.diagnostic(&text, origin)))
+        .map(|rules| {
+            rules
+                .into_iter()
+                // rules are matched against expressions with small powers expanded
+                .map(|rule| Rule {
+                    pattern: expand_powers(rule.pattern),
+                    replacement: expand_powers(rule.replacement),
+                    ..rule
+                })
+                .collect()
+        });
 
-        (stem, rules, macros)
-    })
+    (rules, macros)
 }
 
-pub fn ruleset_combiner(rule_sources: &[&Vec<Rule>]) -> impl Fn(Expression) -> Expression {
+pub fn ruleset_combiner(
+    rule_sources: &[&Vec<Rule>],
+    limits: RewriteLimits,
+) -> impl Fn(Expression) -> Result<Expression, Divergence> {
     let rules: Vec<_> = rule_sources.iter().copied().flatten().cloned().collect();
-    move |expr| apply_ruleset(expr, &rules)
+    move |expr| apply_ruleset(expr, &rules, &mut RewriteTracker::new(limits))
 }
 
-fn apply_ruleset(mut expr: Expression, ruleset: &[Rule]) -> Expression {
+fn apply_ruleset(
+    mut expr: Expression,
+    ruleset: &[Rule],
+    tracker: &mut RewriteTracker,
+) -> Result<Expression, Divergence> {
+    let mut visited = HashMap::from([(expr.clone(), 0)]);
+
     loop {
         let previous_expr = expr.clone();
 
         for rule in ruleset.iter() {
-            expr = transform_recursive(expr, &mut |e| {
-                apply_rule(e, rule).unwrap_or_else(std::convert::identity)
-            });
+            expr = try_transform_recursive(
+                expr,
+                &mut |e| apply_rule(e, rule).unwrap_or_else(std::convert::identity),
+                tracker,
+            )?;
         }
 
+        expr = expand_powers(fold_constants(expr));
+
         if expr == previous_expr {
-            break expr;
+            break Ok(expr);
         }
+
+        // rules can also undo each other across passes, each one settling on its own
+        tracker.step(&expr, &mut visited)?;
     }
 }
 
+// every expression reachable from left and right, with an edge for each ruleset rewriting one
+// into another, and the rulesets that diverged on an expression so that it has no edge for them
+#[allow(clippy::type_complexity)]
 pub fn auto_identity<'a>(
     left: Expression,
     right: Expression,
     rulesets: &BTreeMap<String, Vec<Rule>>,
     arena: &'a elsa::index_set::FrozenIndexSet<Box<Expression>>,
-) -> GraphMap<&'a Expression, String, Directed> {
+    limits: RewriteLimits,
+    max_time: Option<Duration>,
+) -> (
+    GraphMap<&'a Expression, String, Directed>,
+    Vec<(Expression, String, Divergence)>,
+) {
+    let start = Instant::now();
     let mut graph: GraphMap<&Expression, String, Directed> = GraphMap::new();
+
+    // expanding powers is an equality, so it also connects a side back from its expanded form
+    for expr in [&left, &right] {
+        let expanded = expand_powers(expr.clone());
+
+        if expanded != *expr {
+            let expr = arena.insert(Box::new(expr.clone()));
+            let expanded = arena.insert(Box::new(expanded));
+            graph.add_edge(expr, expanded, EXPAND_POWERS_NAME.to_string());
+            graph.add_edge(expanded, expr, EXPAND_POWERS_NAME.to_string());
+        }
+    }
+
     let mut unprocessed = vec![left, right];
+    let mut diverged = Vec::new();
+
+    let empty = Vec::new();
+    let always_apply = rulesets.get(SIMPLIFY_NAME).unwrap_or(&empty);
 
-    let always_apply = rulesets.get("simplify").unwrap();
+    'search: while let Some(expr) = unprocessed.pop() {
+        // every ruleset ends by folding constants and expanding powers, when that is all it
+        // changed the edge is named after the built in one instead
+        let built_in = [
+            (FOLD_NAME, fold_constants(expr.clone())),
+            (EXPAND_POWERS_NAME, expand_powers(expr.clone())),
+        ];
 
-    while let Some(expr) = unprocessed.pop() {
         for (name, ruleset) in rulesets.iter() {
-            let expr_new = ruleset_combiner(&[ruleset, always_apply])(expr.clone());
+            // out of time, what is left stays unexplored
+            if max_time.is_some_and(|t| start.elapsed() >= t) {
+                break 'search;
+            }
+
+            // nothing connects an expression to where a diverging ruleset would take it
+            let expr_new = match ruleset_combiner(&[ruleset, always_apply], limits)(expr.clone()) {
+                Ok(expr_new) => expr_new,
+                Err(divergence) => {
+                    diverged.push((expr.clone(), name.clone(), divergence));
+                    continue;
+                }
+            };
 
             if expr_new != expr {
                 let new_ref = arena.insert(Box::new(expr_new.clone()));
@@ -143,16 +566,21 @@ pub fn auto_identity<'a>(
                     unprocessed.push(expr_new);
                 }
 
-                graph.add_edge(
-                    arena.insert(Box::new(expr.clone())),
-                    new_ref,
-                    name.to_string(),
-                );
+                let expr_ref = arena.insert(Box::new(expr.clone()));
+                let name = built_in
+                    .iter()
+                    .find(|(_, built)| built == new_ref)
+                    .map_or(name.as_str(), |(built_name, _)| built_name);
+
+                // the first ruleset to connect two expressions keeps the edge
+                if !graph.contains_edge(expr_ref, new_ref) {
+                    graph.add_edge(expr_ref, new_ref, name.to_string());
+                }
             }
         }
     }
 
-    graph
+    (graph, diverged)
 }
 
 #[test]
@@ -165,3 +593,194 @@ fn test_ruleset_loading() {
         }
     }
 }
+
+#[test]
+fn test_reciprocal_rules() {
+    let rulesets = RulesetLoader::new().load().unwrap().rules;
+    let reduce = ruleset_combiner(&[&rulesets["trigreducerecip"]], RewriteLimits::default());
+    let expr = |s: &str| Lisp::parse(s).unwrap();
+
+    // sin, cos and tan become their reciprocals, which aren't taken back
+    assert_eq!(
+        reduce(expr("(* (/ (sin x)) (/ (* 2 (tan x))))")),
+        Ok(expr("(* (* (cot x) 1/2) (csc x))"))
+    );
+    let reciprocals = expr("(* (/ (csc x)) (/ (* 2 (cot x))))");
+    assert_eq!(reduce(reciprocals.clone()), Ok(reciprocals));
+}
+
+#[test]
+fn test_ruleset_divergence() {
+    // each pass goes from sin to tan or back, neither is ever a fixpoint
+    let rules =
+        Lisp::parse_ruleset("(=> (sin a) (cos a)) (=> (tan a) (sin a)) (=> (cos a) (tan a))")
+            .unwrap();
+    let expr = Lisp::parse("(sin x)").unwrap();
+
+    let divergence = ruleset_combiner(&[&rules], RewriteLimits::default())(expr).unwrap_err();
+    assert_eq!(divergence.kind, DivergenceKind::Cycle { length: 2 });
+    assert_eq!(
+        divergence.last,
+        ["(cos x)", "(tan x)", "(sin x)"].map(|s| Lisp::parse(s).unwrap())
+    );
+
+    // doubling the argument every step
+    let rules = Lisp::parse_ruleset("(=> (sin a) (sin (+ a a)))").unwrap();
+    let expr = Lisp::parse("(sin x)").unwrap();
+
+    let limits = RewriteLimits {
+        max_term_size: Some(1_000),
+        ..Default::default()
+    };
+    let divergence = ruleset_combiner(&[&rules], limits)(expr.clone());
+    assert!(matches!(
+        divergence.map_err(|d| d.kind),
+        Err(DivergenceKind::TermSizeLimit { size }) if size > 1_000
+    ));
+
+    let limits = RewriteLimits {
+        max_rewrites: Some(5),
+        max_term_size: None,
+    };
+    let divergence = ruleset_combiner(&[&rules], limits)(expr).unwrap_err();
+    assert_eq!(divergence.kind, DivergenceKind::RewriteLimit);
+    assert_eq!(divergence.steps, 6);
+    assert_eq!(divergence.last.len(), 4);
+
+    // expanding products of a few binomials ends within the default limits
+    let rules = RulesetLoader::new().load().unwrap().rules;
+    let expr = Lisp::parse("(* (+ a b) (+ c d) (+ e f) (+ g h) (+ i j) (+ k l) (+ m n))").unwrap();
+    let expanded = ruleset_combiner(&[&rules["expand"]], RewriteLimits::default())(expr).unwrap();
+    assert!(expression_size(&expanded) > 1_000);
+}
+
+#[test]
+fn test_auto_identity_labels() {
+    let rulesets = RulesetLoader::new().load().unwrap().rules;
+    let arena = elsa::index_set::FrozenIndexSet::new();
+    let label = |graph: &GraphMap<&Expression, String, Directed>, from: &str, to: &str| {
+        let (from, to) = (Lisp::parse(from).unwrap(), Lisp::parse(to).unwrap());
+        graph.edge_weight(&from, &to).cloned()
+    };
+
+    // every ruleset expands the powers, but the edge is named after the expansion
+    let (graph, _) = auto_identity(
+        Lisp::parse("(+ (^ (sin x) 2) (^ (cos x) 2))").unwrap(),
+        Lisp::parse("1").unwrap(),
+        &rulesets,
+        &arena,
+        RewriteLimits::default(),
+        None,
+    );
+    let expanded = "(+ (* (sin x) (sin x)) (* (cos x) (cos x)))";
+    assert_eq!(
+        label(&graph, "(+ (^ (sin x) 2) (^ (cos x) 2))", expanded).as_deref(),
+        Some(EXPAND_POWERS_NAME)
+    );
+
+    // and folding constants the same
+    let (graph, _) = auto_identity(
+        Lisp::parse("(+ (+ 1 1) (tan x))").unwrap(),
+        Lisp::parse("(+ 2 (tan x))").unwrap(),
+        &rulesets,
+        &arena,
+        RewriteLimits::default(),
+        None,
+    );
+    assert_eq!(
+        label(&graph, "(+ (+ 1 1) (tan x))", "(+ 2 (tan x))").as_deref(),
+        Some(FOLD_NAME)
+    );
+}
+
+#[test]
+fn test_ruleset_directories() {
+    let dir = std::env::temp_dir().join(format!("identity-rules-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("cancel.lisp"), "(=> (+ a (- a)) 0)").unwrap();
+    std::fs::write(dir.join("extra.lisp"), "#map cancel\n(=> (+ x (- x) y) y)").unwrap();
+    std::fs::write(
+        dir.join("tidy.lisp"),
+        "; strategies can use rulesets from anywhere\n#strategy (repeat (once (choice extra cancel)))",
+    )
+    .unwrap();
+
+    let loaded = RulesetLoader::new().dir(&dir).load();
+
+    std::fs::write(dir.join("bad.lisp"), "#map nothing\n#frobnicate\n(=> x").unwrap();
+    std::fs::write(dir.join("worse.lisp"), "#map bad\n#\n").unwrap();
+    // only .lisp files are rulesets, everything else in the directory is skipped
+    std::fs::write(dir.join("README.md"), "# rules").unwrap();
+    std::fs::write(dir.join(".cancel.lisp.swp"), "\0\0").unwrap();
+    std::fs::write(dir.join("noext"), "(=> x").unwrap();
+    std::fs::create_dir_all(dir.join("old.lisp")).unwrap();
+    std::fs::write(
+        dir.join("xstrategy.lisp"),
+        "#strategy (every cancel)\n#strategy (seq nothing)",
+    )
+    .unwrap();
+
+    let errors = RulesetLoader::new().dir(&dir).load().err().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // the directory replaces the built-in cancel and adds extra next to the rest
+    let LoadedRulesets {
+        rules,
+        strategies,
+        origins,
+    } = loaded.unwrap();
+    assert_eq!(
+        origins["cancel"],
+        dir.join("cancel.lisp").display().to_string()
+    );
+    assert_eq!(origins["simplify"], "<builtin>/simplify.lisp");
+    assert_eq!(
+        rules["cancel"],
+        Lisp::parse_ruleset("(=> (+ a (- a)) 0)").unwrap()
+    );
+    assert_eq!(
+        rules["extra"],
+        Lisp::parse_ruleset("(=> (+ x (- x) y) y)").unwrap()
+    );
+    assert!(rules.contains_key("trigpyth"));
+
+    // a file with just a strategy only adds the strategy
+    assert_eq!(
+        strategies["tidy"],
+        Strategy::parse("(repeat (once (choice extra cancel)))").unwrap()
+    );
+    assert!(!rules.contains_key("tidy"));
+
+    // every problem is reported, except a map of bad which is already broken
+    assert!(
+        matches!(
+            &errors[..],
+            [
+                RulesetLoadError::Parse(_),
+                RulesetLoadError::UnknownRuleset { line: 1, .. },
+                RulesetLoadError::UnknownMacro { line: 2, .. },
+                RulesetLoadError::EmptyMacro { line: 2, .. },
+                RulesetLoadError::Parse(_),
+                RulesetLoadError::UnknownRuleset { line: 2, .. },
+            ]
+        ),
+        "{errors:?}"
+    );
+
+    // the strategy is reported where it is in the file
+    let RulesetLoadError::Parse(diagnostic) = &errors[4] else {
+        unreachable!()
+    };
+    assert!(
+        diagnostic.starts_with("error: unknown combinator\n --> ")
+            && diagnostic.contains("xstrategy.lisp:1:12\n"),
+        "{diagnostic}"
+    );
+
+    assert!(RulesetLoader::new()
+        .builtin(false)
+        .load()
+        .unwrap()
+        .rules
+        .is_empty());
+}
@@ -0,0 +1,1217 @@
+use {
+    crate::{expression::*, rewrite::*, solve::*},
+    std::collections::{BTreeMap, BTreeSet},
+};
+
+// termination and local confluence of each ruleset together with simplify, the way
+// ruleset_combiner applies them, termination by weights and then an AC path ordering, and
+// confluence by rewriting both sides of each critical pair
+
+// a symbol counting its arguments more times than this is taken as the weights going in circles,
+// before they'd get too large to add up
+const MAX_FACTOR: usize = 16;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AnalysisOptions {
+    // limits on rewriting each side of a critical pair until it settles
+    pub limits: RewriteLimits,
+}
+
+// a rule by the ruleset it's from and its index there
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RuleRef {
+    pub ruleset: String,
+    pub index: usize,
+}
+
+// what the precedence orders, the top of an expression that isn't a variable
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Symbol {
+    Variadic(VariadicKind),
+    Unary(UnaryKind),
+    Power,
+    Literal(LiteralValue),
+}
+
+// what each symbol that isn't a sum or product weighs, an expression weighs what its top does
+// and what its arguments do that many times, with sums and products weighing what their terms
+// do, so the order and nesting of terms doesn't change it
+pub type Weights = BTreeMap<Symbol, Weight>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Weight {
+    pub own: usize,
+    // how many times the arguments count, at least once so a rule that makes an argument
+    // lighter makes the whole expression lighter
+    pub factor: usize,
+}
+
+impl Default for Weight {
+    fn default() -> Self {
+        Self { own: 0, factor: 1 }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Termination {
+    // the weights that rules were taken away with, in the order they were, before the ordering
+    // was tried on the rest
+    pub weights: Vec<Weights>,
+    // greatest first, literals are below all of them and ordered by value
+    pub precedence: Vec<Symbol>,
+    // rules neither the weights nor the ordering make smaller, termination is shown if there are
+    // none
+    pub unoriented: Vec<RuleRef>,
+}
+
+// two ways of rewriting the peak, the outer rule at the top and the inner one at position
+#[derive(Clone, Debug)]
+pub struct CriticalPair {
+    pub outer: RuleRef,
+    pub inner: RuleRef,
+    pub peak: Expression,
+    pub position: Position,
+    pub reducts: [Expression; 2],
+    pub join: Join,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Join {
+    // both reducts rewrite to this
+    Joined(Expression),
+    // what each reduct rewrites to
+    Apart([Expression; 2]),
+    Diverged(Divergence),
+}
+
+#[derive(Clone, Debug)]
+pub struct RulesetAnalysis {
+    pub termination: Termination,
+    pub critical_pairs: Vec<CriticalPair>,
+}
+
+impl RulesetAnalysis {
+    pub fn unjoined(&self) -> impl Iterator<Item = &CriticalPair> {
+        self.critical_pairs
+            .iter()
+            .filter(|pair| !matches!(pair.join, Join::Joined(_)))
+    }
+
+    // termination is shown and every critical pair found joins, which isn't quite convergence
+    // since some overlaps are missed
+    pub fn passes(&self) -> bool {
+        self.termination.unoriented.is_empty() && self.unjoined().next().is_none()
+    }
+}
+
+impl std::fmt::Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Symbol::Variadic(VariadicKind::Addition) => write!(f, "+"),
+            Symbol::Variadic(VariadicKind::Multiplication) => write!(f, "*"),
+            Symbol::Unary(UnaryKind::Negation) => write!(f, "-"),
+            Symbol::Unary(UnaryKind::Reciprocal) => write!(f, "/"),
+            Symbol::Unary(UnaryKind::Named { id }) => write!(f, "{}", id.as_ref()),
+            Symbol::Unary(UnaryKind::Variable { id, .. }) => write!(f, "{}", id.as_ref()),
+            Symbol::Power => write!(f, "^"),
+            Symbol::Literal(value) => {
+                write!(f, "{}", Expression::from(Literal::new(value.clone())))
+            }
+        }
+    }
+}
+
+// None if there's no ruleset with the name
+pub fn analyze_ruleset(
+    name: &str,
+    rulesets: &BTreeMap<String, Vec<Rule>>,
+    options: AnalysisOptions,
+) -> Option<RulesetAnalysis> {
+    let mut sources = vec![(name, rulesets.get(name)?)];
+    if let (false, Some(simplify)) = (name == SIMPLIFY_NAME, rulesets.get(SIMPLIFY_NAME)) {
+        sources.push((SIMPLIFY_NAME, simplify));
+    }
+
+    let rules: Vec<_> = sources
+        .iter()
+        .flat_map(|(ruleset, rules)| {
+            rules.iter().enumerate().map(|(index, rule)| {
+                let ruleset = ruleset.to_string();
+                (RuleRef { ruleset, index }, rule)
+            })
+        })
+        .collect();
+
+    let normalize = ruleset_combiner(
+        &sources.iter().map(|(_, rules)| *rules).collect::<Vec<_>>(),
+        options.limits,
+    );
+
+    Some(RulesetAnalysis {
+        termination: termination(&rules),
+        critical_pairs: critical_pairs(name, &rules, &normalize),
+    })
+}
+
+pub fn analyze_rulesets(
+    rulesets: &BTreeMap<String, Vec<Rule>>,
+    options: AnalysisOptions,
+) -> Vec<(&str, RulesetAnalysis)> {
+    rulesets
+        .keys()
+        .filter_map(|name| Some((name.as_str(), analyze_ruleset(name, rulesets, options)?)))
+        .collect()
+}
+
+fn termination(rules: &[(RuleRef, &Rule)]) -> Termination {
+    // every way a rule can match is its own rule here, so that each can be taken away on its own
+    let mut remaining: Vec<_> = rules
+        .iter()
+        .flat_map(|(rule_ref, rule)| {
+            function_instances(rule)
+                .into_iter()
+                .flat_map(move |(pattern, replacement)| {
+                    erasures(&pattern)
+                        .into_iter()
+                        .map(|erased| (erase(&pattern, &erased), erase(&replacement, &erased)))
+                        .collect::<Vec<_>>()
+                })
+                .map(move |sides| (rule_ref, sides))
+        })
+        .collect();
+
+    // rules that get lighter while none gets heavier can only be applied so many times, so only
+    // the rest are left for the path ordering
+    let mut weights = Vec::new();
+    while let Some(found) = lighter(remaining.iter().map(|(_, sides)| sides)) {
+        remaining.retain(|(_, (pattern, replacement))| !lighter_side(pattern, replacement, &found));
+        weights.push(found);
+    }
+
+    let precedence = guess_precedence(remaining.iter().map(|(_, sides)| sides));
+    let order = PathOrdering {
+        rank: precedence
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(rank, symbol)| (symbol.clone(), rank))
+            .collect(),
+    };
+
+    let mut unoriented: Vec<RuleRef> = remaining
+        .iter()
+        .filter(|(_, (pattern, replacement))| !order.decreasing(pattern, replacement))
+        .map(|(rule_ref, _)| (*rule_ref).clone())
+        .collect();
+    unoriented.dedup();
+
+    Termination {
+        weights,
+        precedence,
+        unoriented,
+    }
+}
+
+fn critical_pairs(
+    name: &str,
+    rules: &[(RuleRef, &Rule)],
+    normalize: &impl Fn(Expression) -> Result<Expression, Divergence>,
+) -> Vec<CriticalPair> {
+    let mut pairs = Vec::new();
+    let mut seen = BTreeSet::new();
+
+    for (outer, outer_rule) in rules {
+        for (pattern, replacement) in function_instances(outer_rule) {
+            for (inner, inner_rule) in rules {
+                // pairs within simplify are for its own analysis
+                if name != SIMPLIFY_NAME && outer.ruleset != name && inner.ruleset != name {
+                    continue;
+                }
+
+                for (inner_pattern, _) in function_instances(inner_rule) {
+                    let inner_pattern = rename_apart(&inner_pattern, &pattern);
+
+                    for position in positions(&pattern) {
+                        // a variable of the outer pattern is the same on both sides
+                        let sub = subexpression(&pattern, &position).unwrap();
+                        if matches!(sub, Expression::Variable(_)) {
+                            continue;
+                        }
+
+                        for unifier in unify(sub, &inner_pattern, Unifier::new()) {
+                            let peak = substitute(&pattern, &unifier);
+                            let target = substitute(sub, &unifier);
+                            let top = substitute(&replacement, &unifier);
+
+                            // the rules have to apply to the peak as they're written, guards
+                            // included
+                            if !apply_rule_all(&peak, outer_rule).any(|(_, new)| new == top) {
+                                continue;
+                            }
+
+                            for (position, _, reduct) in rewrite_once(&peak, inner_rule) {
+                                // a rule matching itself the same way at the top is no pair
+                                if subexpression(&peak, &position) != Some(&target)
+                                    || (position.is_empty() && reduct == top)
+                                    || !seen.insert((
+                                        outer.clone(),
+                                        inner.clone(),
+                                        peak.clone(),
+                                        [top.clone(), reduct.clone()],
+                                    ))
+                                {
+                                    continue;
+                                }
+
+                                let join = match (normalize(top.clone()), normalize(reduct.clone()))
+                                {
+                                    (Ok(left), Ok(right)) if left == right => Join::Joined(left),
+                                    (Ok(left), Ok(right)) => Join::Apart([left, right]),
+                                    (Err(divergence), _) | (_, Err(divergence)) => {
+                                        Join::Diverged(divergence)
+                                    }
+                                };
+
+                                pairs.push(CriticalPair {
+                                    outer: outer.clone(),
+                                    inner: inner.clone(),
+                                    peak: peak.clone(),
+                                    position,
+                                    reducts: [top.clone(), reduct],
+                                    join,
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pairs
+}
+
+// what variables of both patterns of an overlap stand for, keyed by the untyped variable, with
+// nothing bound in what they stand for
+type Unifier = BTreeMap<Variable, Expression>;
+
+// every most general unifier of s and t, where sums and products unify the way patterns match
+// them, with every term going with a single term on the other side or taken by an untyped
+// variable there along with any number of others. Two variables never split terms between
+// them, so the overlaps that would need a fresh variable for what they share aren't found
+fn unify(s: &Expression, t: &Expression, unifier: Unifier) -> Vec<Unifier> {
+    let (s, t) = (substitute(s, &unifier), substitute(t, &unifier));
+    if s == t {
+        return vec![unifier];
+    }
+
+    match (s, t) {
+        (expr, Expression::Variable(var)) | (Expression::Variable(var), expr) => {
+            bind(var, expr, unifier).into_iter().collect()
+        }
+        (
+            Expression::Concrete(Concrete::Variadic(Variadic { terms: s, kind })),
+            Expression::Concrete(Concrete::Variadic(Variadic {
+                terms: t,
+                kind: t_kind,
+            })),
+        ) if kind == t_kind => unify_terms(
+            s.into_iter().collect(),
+            t.into_iter().collect(),
+            kind,
+            unifier,
+        ),
+        (
+            Expression::Concrete(Concrete::Unary(Unary { argument: s, kind })),
+            Expression::Concrete(Concrete::Unary(Unary {
+                argument: t,
+                kind: t_kind,
+            })),
+        ) if kind == t_kind => unify(&s, &t, unifier),
+        (
+            Expression::Concrete(Concrete::Power(Power { base, exponent })),
+            Expression::Concrete(Concrete::Power(Power {
+                base: t_base,
+                exponent: t_exponent,
+            })),
+        ) => unify(&base, &t_base, unifier)
+            .into_iter()
+            .flat_map(|unifier| unify(&exponent, &t_exponent, unifier))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn unify_terms(
+    mut s: Vec<Expression>,
+    mut t: Vec<Expression>,
+    kind: VariadicKind,
+    unifier: Unifier,
+) -> Vec<Unifier> {
+    s = substitute_terms(&s, kind, &unifier);
+    t = substitute_terms(&t, kind, &unifier);
+
+    // terms that are already the same go together
+    s.retain(|term| match t.iter().position(|other| other == term) {
+        Some(i) => {
+            t.remove(i);
+            false
+        }
+        None => true,
+    });
+
+    // the side to take a term from, which goes first if it isn't an untyped variable since that
+    // leaves it fewer ways to go
+    let untyped = |term: &Expression| {
+        matches!(
+            term,
+            Expression::Variable(Variable {
+                kind: VarKind::Any,
+                ..
+            })
+        )
+    };
+    if s.is_empty() || (s.iter().all(untyped) && !t.iter().all(untyped)) {
+        std::mem::swap(&mut s, &mut t);
+    }
+    if s.is_empty() {
+        return vec![unifier];
+    }
+    let first = s.remove(s.iter().position(|term| !untyped(term)).unwrap_or(0));
+
+    let mut output = Vec::new();
+
+    for i in 0..t.len() {
+        let mut rest = t.clone();
+        let other = rest.remove(i);
+        for unifier in unify(&first, &other, unifier.clone()) {
+            output.extend(unify_terms(s.clone(), rest.clone(), kind, unifier));
+        }
+    }
+
+    // an untyped variable takes no terms, or several
+    if let Expression::Variable(
+        var @ Variable {
+            kind: VarKind::Any, ..
+        },
+    ) = &first
+    {
+        for (taken, rest) in splits(&t).filter(|(taken, _)| taken.len() != 1) {
+            if let Some(unifier) = bind(
+                var.clone(),
+                Variadic::new(taken, kind).into(),
+                unifier.clone(),
+            ) {
+                output.extend(unify_terms(s.clone(), rest, kind, unifier));
+            }
+        }
+    }
+
+    // or one on the other side takes it along with others
+    for i in 0..t.len() {
+        let Expression::Variable(var) = &t[i] else {
+            continue;
+        };
+        if var.kind != VarKind::Any {
+            continue;
+        }
+        let mut rest_t = t.clone();
+        rest_t.remove(i);
+
+        for (taken, rest) in splits(&s).filter(|(taken, _)| !taken.is_empty()) {
+            let taken = Variadic::new([first.clone()].into_iter().chain(taken), kind);
+            if let Some(unifier) = bind(var.clone(), taken.into(), unifier.clone()) {
+                output.extend(unify_terms(rest, rest_t.clone(), kind, unifier));
+            }
+        }
+    }
+
+    output.sort();
+    output.dedup();
+    output
+}
+
+// the unifier with var standing for expr too, None if var can't stand for it
+fn bind(var: Variable, expr: Expression, unifier: Unifier) -> Option<Unifier> {
+    let admits = match &expr {
+        // a typed variable is kept over an untyped one, and otherwise they have to be the same
+        Expression::Variable(other) if var.kind != VarKind::Any && other.kind == VarKind::Any => {
+            return bind(other.clone(), var.into(), unifier);
+        }
+        Expression::Variable(other) => var.kind == VarKind::Any || other.kind == var.kind,
+        expr => var.kind.admits(expr),
+    };
+    if !admits || variables(&expr).iter().any(|v| v.id == var.id) {
+        return None;
+    }
+
+    let single = Unifier::from([(var.untyped(), expr)]);
+    let mut unifier: Unifier = unifier
+        .into_iter()
+        .map(|(var, expr)| (var, substitute(&expr, &single)))
+        .collect();
+    unifier.extend(single);
+    Some(unifier)
+}
+
+// expr with the variables the unifier binds replaced, flattened the way replace_variable does
+fn substitute(expr: &Expression, unifier: &Unifier) -> Expression {
+    match expr {
+        Expression::Concrete(Concrete::Variadic(Variadic { terms, kind })) => {
+            let terms: Vec<_> = terms.iter().cloned().collect();
+            Variadic::new(substitute_terms(&terms, *kind, unifier), *kind).into()
+        }
+        Expression::Concrete(Concrete::Unary(Unary { argument, kind })) => {
+            Unary::new(substitute(argument, unifier), kind.clone()).into()
+        }
+        Expression::Concrete(Concrete::Power(Power { base, exponent })) => {
+            Power::new(substitute(base, unifier), substitute(exponent, unifier)).into()
+        }
+        Expression::Variable(var) => unifier.get(&var.untyped()).unwrap_or(expr).clone(),
+        Expression::Concrete(Concrete::Literal(_)) => expr.clone(),
+    }
+}
+
+fn substitute_terms(
+    terms: &[Expression],
+    kind: VariadicKind,
+    unifier: &Unifier,
+) -> Vec<Expression> {
+    terms
+        .iter()
+        .flat_map(|term| match substitute(term, unifier) {
+            Expression::Concrete(Concrete::Variadic(Variadic {
+                terms: inner,
+                kind: inner_kind,
+            })) if inner_kind == kind => inner.into_iter().collect(),
+            term => vec![term],
+        })
+        .collect()
+}
+
+// every way of splitting terms into the ones taken and the rest
+fn splits(terms: &[Expression]) -> impl Iterator<Item = (Vec<Expression>, Vec<Expression>)> + '_ {
+    (0..1usize << terms.len()).map(|mask| {
+        let (mut taken, mut rest) = (Vec::new(), Vec::new());
+        for (i, term) in terms.iter().enumerate() {
+            if mask >> i & 1 == 1 {
+                taken.push(term.clone());
+            } else {
+                rest.push(term.clone());
+            }
+        }
+        (taken, rest)
+    })
+}
+
+// the inner pattern with the variables it shares with the outer one renamed
+fn rename_apart(inner: &Expression, outer: &Expression) -> Expression {
+    let outer_vars = variables(outer);
+    let mut taken: BTreeSet<_> = outer_vars
+        .iter()
+        .chain(&variables(inner))
+        .map(|v| v.id.clone())
+        .collect();
+
+    let mut renames = Unifier::new();
+    for var in variables(inner) {
+        if outer_vars.iter().any(|v| v.id == var.id) {
+            let id = (1..)
+                .map(|n| VarId::new(format!("{}{n}", var.id.as_ref())))
+                .find(|id| !taken.contains(id))
+                .unwrap();
+            taken.insert(id.clone());
+            renames.insert(var.untyped(), Variable { id, kind: var.kind }.into());
+        }
+    }
+
+    substitute(inner, &renames)
+}
+
+// both sides of the rule for every function its function variables could stand for, as far as
+// their relations cover them, or just the rule if it has none
+fn function_instances(rule: &Rule) -> Vec<(Expression, Expression)> {
+    let vars = function_variables(&rule.pattern);
+    let mut assignments = vec![BTreeMap::new()];
+
+    for var in vars {
+        assignments = assignments
+            .into_iter()
+            .flat_map(|assignment: BTreeMap<VarId, FuncId>| {
+                let var = var.clone();
+                FUNCTIONS.iter().map(move |func| {
+                    let mut assignment = assignment.clone();
+                    assignment.insert(var.clone(), FuncId::new(func));
+                    assignment
+                })
+            })
+            .collect();
+    }
+
+    assignments
+        .iter()
+        .filter_map(|assignment| {
+            Some((
+                instantiate_functions(&rule.pattern, assignment)?,
+                instantiate_functions(&rule.replacement, assignment)?,
+            ))
+        })
+        .collect()
+}
+
+// function variables replaced with what they're assigned, None if a relation doesn't cover it
+fn instantiate_functions(
+    expr: &Expression,
+    assignment: &BTreeMap<VarId, FuncId>,
+) -> Option<Expression> {
+    Some(match expr {
+        Expression::Concrete(Concrete::Variadic(Variadic { terms, kind })) => Variadic::new(
+            terms
+                .iter()
+                .map(|t| instantiate_functions(t, assignment))
+                .collect::<Option<Vec<_>>>()?,
+            *kind,
+        )
+        .into(),
+        Expression::Concrete(Concrete::Unary(Unary { argument, kind })) => {
+            let kind = match kind {
+                UnaryKind::Variable { id, relations } => UnaryKind::Named {
+                    id: relate_all(relations, assignment.get(id)?)?,
+                },
+                kind => kind.clone(),
+            };
+            Unary::new(instantiate_functions(argument, assignment)?, kind).into()
+        }
+        Expression::Concrete(Concrete::Power(Power { base, exponent })) => Power::new(
+            instantiate_functions(base, assignment)?,
+            instantiate_functions(exponent, assignment)?,
+        )
+        .into(),
+        expr => expr.clone(),
+    })
+}
+
+// every combination of the pattern's variables that can match no terms, which are the untyped
+// ones among the terms of a sum or product, with the kind they're a term of. A sum or product in
+// a pattern only matches ones with terms, so they can't all be left out of the same one
+fn erasures(pattern: &Expression) -> Vec<BTreeMap<Variable, VariadicKind>> {
+    fn rec(expr: &Expression, output: &mut BTreeMap<Variable, VariadicKind>) {
+        if let Expression::Concrete(Concrete::Variadic(Variadic { terms, kind })) = expr {
+            for term in terms.iter() {
+                if let Expression::Variable(
+                    v @ Variable {
+                        kind: VarKind::Any, ..
+                    },
+                ) = term
+                {
+                    output.entry(v.clone()).or_insert(*kind);
+                }
+            }
+        }
+        children(expr).into_iter().for_each(|c| rec(c, output));
+    }
+
+    let mut erasable = BTreeMap::new();
+    rec(pattern, &mut erasable);
+
+    erasable
+        .into_iter()
+        .fold(vec![BTreeMap::new()], |combinations, (var, kind)| {
+            combinations
+                .into_iter()
+                .flat_map(|without| {
+                    let mut with = without.clone();
+                    with.insert(var.clone(), kind);
+                    [without, with]
+                })
+                .collect()
+        })
+        .into_iter()
+        .filter(|erased| !empties(pattern, erased))
+        .collect()
+}
+
+// whether some sum or product in expr has nothing left once the erased variables are left out
+fn empties(expr: &Expression, erased: &BTreeMap<Variable, VariadicKind>) -> bool {
+    let empty = match expr {
+        Expression::Concrete(Concrete::Variadic(Variadic { terms, kind })) => {
+            terms.iter().next().is_some()
+                && terms
+                    .iter()
+                    .all(|t| matches!(t, Expression::Variable(v) if erased.get(v) == Some(kind)))
+        }
+        _ => false,
+    };
+
+    empty || children(expr).into_iter().any(|c| empties(c, erased))
+}
+
+// expr with the erased variables matching no terms, so they're left out of the sums or products
+// they're terms of, and empty ones of those everywhere else
+fn erase(expr: &Expression, erased: &BTreeMap<Variable, VariadicKind>) -> Expression {
+    match expr {
+        Expression::Concrete(Concrete::Variadic(Variadic { terms, kind })) => Variadic::new(
+            terms
+                .iter()
+                .filter(|t| !matches!(t, Expression::Variable(v) if erased.get(v) == Some(kind)))
+                .map(|t| erase(t, erased)),
+            *kind,
+        )
+        .into(),
+        Expression::Concrete(Concrete::Unary(Unary { argument, kind })) => {
+            Unary::new(erase(argument, erased), kind.clone()).into()
+        }
+        Expression::Concrete(Concrete::Power(Power { base, exponent })) => {
+            Power::new(erase(base, erased), erase(exponent, erased)).into()
+        }
+        Expression::Variable(v) => match erased.get(v) {
+            Some(kind) => Variadic::new([], *kind).into(),
+            None => expr.clone(),
+        },
+        Expression::Concrete(Concrete::Literal(_)) => expr.clone(),
+    }
+}
+
+fn head(expr: &Expression) -> Option<Symbol> {
+    match expr {
+        Expression::Concrete(Concrete::Variadic(Variadic { kind, .. })) => {
+            Some(Symbol::Variadic(*kind))
+        }
+        Expression::Concrete(Concrete::Unary(Unary { kind, .. })) => {
+            Some(Symbol::Unary(kind.clone()))
+        }
+        Expression::Concrete(Concrete::Power(_)) => Some(Symbol::Power),
+        Expression::Concrete(Concrete::Literal(Literal { value })) => {
+            Some(Symbol::Literal(value.clone()))
+        }
+        Expression::Variable(_) => None,
+    }
+}
+
+// every symbol in expr that isn't a literal
+fn symbols(expr: &Expression, output: &mut BTreeSet<Symbol>) {
+    if let Some(symbol) = head(expr).filter(|s| !matches!(s, Symbol::Literal(_))) {
+        output.insert(symbol);
+    }
+    children(expr).into_iter().for_each(|c| symbols(c, output));
+}
+
+// the top of each pattern above the top of its replacement and every symbol the replacement
+// brings in, and whatever the rule takes away above those too, leaving out whatever would go
+// against an earlier rule, then sorted so that every symbol comes after the ones above it
+fn guess_precedence<'a>(sides: impl Iterator<Item = &'a (Expression, Expression)>) -> Vec<Symbol> {
+    let mut all = BTreeSet::new();
+    let mut above: BTreeMap<Symbol, BTreeSet<Symbol>> = BTreeMap::new();
+
+    let reaches = |above: &BTreeMap<Symbol, BTreeSet<Symbol>>, from: &Symbol, to: &Symbol| {
+        let mut stack = vec![from];
+        let mut seen = BTreeSet::new();
+        while let Some(symbol) = stack.pop() {
+            if symbol == to {
+                return true;
+            }
+            if seen.insert(symbol) {
+                stack.extend(above.get(symbol).into_iter().flatten());
+            }
+        }
+        false
+    };
+
+    for (pattern, replacement) in sides {
+        let (mut left, mut right) = (BTreeSet::new(), BTreeSet::new());
+        symbols(pattern, &mut left);
+        symbols(replacement, &mut right);
+        all.extend(left.iter().chain(&right).cloned());
+
+        let brought: Vec<_> = right.difference(&left).collect();
+        let taken = left.difference(&right);
+        let top = head(pattern).filter(|s| left.contains(s));
+        let below = head(replacement).filter(|s| right.contains(s));
+
+        let edges = top
+            .iter()
+            .flat_map(|top| {
+                below
+                    .iter()
+                    .chain(brought.iter().copied())
+                    .map(move |s| (top, s))
+            })
+            .chain(taken.flat_map(|t| brought.iter().map(move |s| (t, *s))));
+
+        for (greater, less) in edges {
+            if greater != less && !reaches(&above, less, greater) {
+                above
+                    .entry(greater.clone())
+                    .or_default()
+                    .insert(less.clone());
+            }
+        }
+    }
+
+    let mut precedence = Vec::new();
+    while !all.is_empty() {
+        let next = all
+            .iter()
+            .find(|s| {
+                !all.iter()
+                    .any(|t| above.get(t).is_some_and(|below| below.contains(s)))
+            })
+            .unwrap()
+            .clone();
+        all.remove(&next);
+        precedence.push(next);
+    }
+
+    precedence
+}
+
+// what expr weighs given what its variables do, as what it weighs with them all weighing
+// nothing and how many times each one counts
+fn weigh(expr: &Expression, weights: &Weights) -> (usize, BTreeMap<Variable, usize>) {
+    if let Expression::Variable(v) = expr {
+        return (0, BTreeMap::from([(v.untyped(), 1)]));
+    }
+
+    let Weight { own, factor } = head(expr)
+        .and_then(|s| weights.get(&s).copied())
+        .unwrap_or_default();
+    let (mut constant, mut variables) = (own, BTreeMap::new());
+    for child in children(expr) {
+        let (child_constant, child_variables) = weigh(child, weights);
+        constant += factor * child_constant;
+        for (v, n) in child_variables {
+            *variables.entry(v).or_default() += factor * n;
+        }
+    }
+
+    (constant, variables)
+}
+
+// whether the pattern weighs at least what the replacement does whatever its variables weigh
+fn at_least(pattern: &Expression, replacement: &Expression, weights: &Weights) -> bool {
+    let ((constant_p, variables_p), (constant_r, variables_r)) =
+        (weigh(pattern, weights), weigh(replacement, weights));
+
+    constant_p >= constant_r
+        && variables_r
+            .iter()
+            .all(|(v, n)| variables_p.get(v).copied().unwrap_or(0) >= *n)
+}
+
+fn lighter_side(pattern: &Expression, replacement: &Expression, weights: &Weights) -> bool {
+    at_least(pattern, replacement, weights)
+        && weigh(pattern, weights).0 > weigh(replacement, weights).0
+}
+
+// weights under which none of the rules gets heavier and some get lighter, None if there are
+// none to be found. Each symbol is tried weighing 1 with the others weighing nothing, and the
+// top of a pattern is made heavier, or to count its arguments more times, for as long as its
+// replacement would weigh more
+fn lighter<'a>(
+    sides: impl Iterator<Item = &'a (Expression, Expression)> + Clone,
+) -> Option<Weights> {
+    let mut candidates = BTreeSet::new();
+    for (pattern, _) in sides.clone() {
+        heads(pattern, &mut candidates);
+    }
+
+    candidates.into_iter().find_map(|symbol| {
+        let mut weights = Weights::from([(symbol, Weight { own: 1, factor: 1 })]);
+
+        for _ in 0..=sides.clone().count() {
+            let heavier = sides
+                .clone()
+                .find(|(pattern, replacement)| !at_least(pattern, replacement, &weights));
+            let Some((pattern, replacement)) = heavier else {
+                return sides
+                    .clone()
+                    .any(|(pattern, replacement)| lighter_side(pattern, replacement, &weights))
+                    .then_some(weights);
+            };
+
+            let top = head(pattern).filter(|s| !matches!(s, Symbol::Variadic(_)))?;
+            let ((constant_p, variables_p), (constant_r, variables_r)) =
+                (weigh(pattern, &weights), weigh(replacement, &weights));
+
+            // a variable that counts more times in the replacement has to count as many under
+            // the top of the pattern, if it's there at all
+            let mut times = 1;
+            for (v, n) in &variables_r {
+                let m = variables_p.get(v).copied().unwrap_or(0);
+                if m == 0 {
+                    return None;
+                }
+                times = times.max(n.div_ceil(m));
+            }
+
+            let weight = weights.entry(top).or_default();
+            weight.factor *= times;
+            weight.own += constant_r.saturating_sub(constant_p);
+            if weight.factor > MAX_FACTOR {
+                return None;
+            }
+        }
+
+        None
+    })
+}
+
+// every symbol in expr that isn't a sum or product, literals included
+fn heads(expr: &Expression, output: &mut BTreeSet<Symbol>) {
+    if let Some(symbol) = head(expr).filter(|s| !matches!(s, Symbol::Variadic(_))) {
+        output.insert(symbol);
+    }
+    children(expr).into_iter().for_each(|c| heads(c, output));
+}
+
+// sums and products in sums and products of the same kind, which is what flattening removes
+fn nesting(expr: &Expression) -> usize {
+    let nested = match expr {
+        Expression::Concrete(Concrete::Variadic(Variadic { terms, kind })) => terms
+            .iter()
+            .filter(|t| matches!(head(t), Some(Symbol::Variadic(k)) if k == *kind))
+            .count(),
+        _ => 0,
+    };
+
+    nested + children(expr).into_iter().map(nesting).sum::<usize>()
+}
+
+fn flatten(expr: &Expression) -> Expression {
+    match expr {
+        Expression::Concrete(Concrete::Variadic(Variadic { terms, kind })) => Variadic::new(
+            terms.iter().flat_map(|t| match flatten(t) {
+                Expression::Concrete(Concrete::Variadic(Variadic {
+                    terms: inner,
+                    kind: inner_kind,
+                })) if inner_kind == *kind => inner.into_iter().collect(),
+                t => vec![t],
+            }),
+            *kind,
+        )
+        .into(),
+        Expression::Concrete(Concrete::Unary(Unary { argument, kind })) => {
+            Unary::new(flatten(argument), kind.clone()).into()
+        }
+        Expression::Concrete(Concrete::Power(Power { base, exponent })) => {
+            Power::new(flatten(base), flatten(exponent)).into()
+        }
+        expr => expr.clone(),
+    }
+}
+
+// AC-RPO on flattened expressions, with a total precedence
+struct PathOrdering {
+    // higher is greater
+    rank: BTreeMap<Symbol, usize>,
+}
+
+impl PathOrdering {
+    // whether a rule with these sides gets smaller every time it's applied
+    fn decreasing(&self, pattern: &Expression, replacement: &Expression) -> bool {
+        let (left, right) = (flatten(pattern), flatten(replacement));
+
+        if left == right {
+            nesting(pattern) > nesting(replacement)
+        } else {
+            self.greater(&left, &right)
+        }
+    }
+
+    fn precedes(&self, f: &Symbol, g: &Symbol) -> bool {
+        match (f, g) {
+            (Symbol::Literal(a), Symbol::Literal(b)) => a > b,
+            (Symbol::Literal(_), _) => false,
+            (_, Symbol::Literal(_)) => true,
+            _ => self.rank.get(f) > self.rank.get(g),
+        }
+    }
+
+    fn greater_equal(&self, s: &Expression, t: &Expression) -> bool {
+        s == t || self.greater(s, t)
+    }
+
+    fn greater(&self, s: &Expression, t: &Expression) -> bool {
+        let Some(f) = head(s) else {
+            return false;
+        };
+        let Some(g) = head(t) else {
+            return s != t
+                && variables(s)
+                    .iter()
+                    .any(|v| Expression::from(v.clone()) == *t);
+        };
+
+        let (args_s, args_t) = (children(s), children(t));
+
+        if args_s.iter().any(|arg| self.greater_equal(arg, t)) {
+            return true;
+        }
+        if self.precedes(&f, &g) {
+            return args_t.iter().all(|arg| self.greater(s, arg));
+        }
+        if f != g {
+            return false;
+        }
+
+        match f {
+            Symbol::Variadic(kind) => self.greater_ac(s, t, kind),
+            Symbol::Unary(_) => self.greater(args_s[0], args_t[0]),
+            Symbol::Power => {
+                let (&[base_s, exp_s], &[base_t, exp_t]) = (&args_s[..], &args_t[..]) else {
+                    return false;
+                };
+                (self.greater(base_s, base_t) || (base_s == base_t && self.greater(exp_s, exp_t)))
+                    && self.greater(s, base_t)
+                    && self.greater(s, exp_t)
+            }
+            Symbol::Literal(_) => false,
+        }
+    }
+
+    fn greater_ac<'a>(&self, s: &'a Expression, t: &'a Expression, kind: VariadicKind) -> bool {
+        let f = Symbol::Variadic(kind);
+
+        if self
+            .embed_small(s, kind)
+            .iter()
+            .any(|embedded| self.greater_equal(embedded, t))
+        {
+            return true;
+        }
+        if !self
+            .embed_small(t, kind)
+            .iter()
+            .all(|embedded| self.greater(s, embedded))
+        {
+            return false;
+        }
+
+        let (args_s, args_t) = (children(s), children(t));
+        let no_small_head = |args: &[&'a Expression]| -> Vec<&'a Expression> {
+            args.iter()
+                .copied()
+                .filter(|a| !head(a).is_some_and(|h| self.precedes(&f, &h)))
+                .collect()
+        };
+        let big_head = |args: &[&'a Expression]| -> Vec<&'a Expression> {
+            args.iter()
+                .copied()
+                .filter(|a| head(a).is_some_and(|h| self.precedes(&h, &f)))
+                .collect()
+        };
+
+        self.multiset_greater_equal(no_small_head(&args_s), no_small_head(&args_t))
+            && (self.multiset_greater(big_head(&args_s), big_head(&args_t))
+                || count_greater(&args_s, &args_t, true)
+                || (count_greater(&args_s, &args_t, false)
+                    && self.multiset_greater(args_s, args_t)))
+    }
+
+    // expr with one of its terms whose top precedes the sum or product replaced by its own
+    // children, for each such term
+    fn embed_small(&self, expr: &Expression, kind: VariadicKind) -> Vec<Expression> {
+        let f = Symbol::Variadic(kind);
+        let args = children(expr);
+
+        (0..args.len())
+            .filter(|&i| head(args[i]).is_some_and(|h| self.precedes(&f, &h)))
+            .map(|i| {
+                let terms = args
+                    .iter()
+                    .enumerate()
+                    .filter(|&(j, _)| j != i)
+                    .map(|(_, arg)| (*arg).clone())
+                    .chain(children(args[i]).into_iter().cloned());
+                flatten(&Variadic::new(terms, kind).into())
+            })
+            .collect()
+    }
+
+    fn multiset_greater(&self, mut m: Vec<&Expression>, n: Vec<&Expression>) -> bool {
+        let mut n_rest = Vec::new();
+        for x in n {
+            match m.iter().position(|y| *y == x) {
+                Some(i) => {
+                    m.remove(i);
+                }
+                None => n_rest.push(x),
+            }
+        }
+
+        !m.is_empty() && n_rest.iter().all(|x| m.iter().any(|y| self.greater(y, x)))
+    }
+
+    fn multiset_greater_equal(&self, m: Vec<&Expression>, n: Vec<&Expression>) -> bool {
+        let (mut sorted_m, mut sorted_n) = (m.clone(), n.clone());
+        sorted_m.sort();
+        sorted_n.sort();
+
+        sorted_m == sorted_n || self.multiset_greater(m, n)
+    }
+}
+
+// compares the number of terms, where a variable stands for at least one and possibly many, so
+// s has more if it has at least as many of each variable and more when they're all one
+fn count_greater(s: &[&Expression], t: &[&Expression], strict: bool) -> bool {
+    fn count<'a>(args: &[&'a Expression]) -> (i64, BTreeMap<&'a Variable, i64>) {
+        let mut vars: BTreeMap<&Variable, i64> = BTreeMap::new();
+        let mut rest = 0;
+        for arg in args {
+            match arg {
+                Expression::Variable(v) => *vars.entry(v).or_default() += 1,
+                _ => rest += 1,
+            }
+        }
+        (rest, vars)
+    }
+
+    let ((rest_s, vars_s), (rest_t, vars_t)) = (count(s), count(t));
+    let each_variable = vars_t
+        .iter()
+        .all(|(v, n)| vars_s.get(v).copied().unwrap_or(0) >= *n);
+    let difference = rest_s - rest_t + vars_s.values().sum::<i64>() - vars_t.values().sum::<i64>();
+
+    each_variable
+        && if strict {
+            difference > 0
+        } else {
+            difference >= 0
+        }
+}
+
+#[test]
+fn test_analysis() {
+    use crate::text::*;
+
+    let rulesets = |rules: &str| {
+        BTreeMap::from([
+            (SIMPLIFY_NAME.to_string(), Vec::new()),
+            ("test".to_string(), Lisp::parse_ruleset(rules).unwrap()),
+        ])
+    };
+    let analyze = |rules: &str| analyze_ruleset("test", &rulesets(rules), Default::default());
+
+    // distributing needs products above sums, and flattening is decreasing too
+    let analysis = analyze(
+        "(=> (* a (+ b c)) (+ (* a b) (* a c))) (=> (* (* a) b) (* a b)) (=> (+ a 0 ..) (+ a ..))",
+    )
+    .unwrap();
+    assert!(analysis.termination.unoriented.is_empty());
+    assert_eq!(
+        analysis.termination.precedence,
+        [
+            Symbol::Variadic(VariadicKind::Multiplication),
+            Symbol::Variadic(VariadicKind::Addition)
+        ]
+    );
+
+    // with factoring too, one of them can't be oriented
+    let analysis =
+        analyze("(=> (* a (+ b c)) (+ (* a b) (* a c))) (=> (+ (* a b) (* a c)) (* a (+ b c)))")
+            .unwrap();
+    assert_eq!(analysis.termination.unoriented.len(), 1);
+
+    // function variables are tried with every function they can stand for
+    let analysis = analyze("(=> (/ (F u)) ((recip F) u)) (=> (csc u) (/ (sin u)))").unwrap();
+    assert_eq!(analysis.termination.unoriented.len(), 1);
+
+    // combining reciprocals and flipping a quotient need products and reciprocals above each
+    // other both ways, but both take a reciprocal away
+    let quotients = "(=> (* (/ m1) (/ m2) n) (* (/ (* m1 m2)) n)) (=> (/ (* d (/ n))) (* n (/ d)))";
+    let analysis = analyze(quotients).unwrap();
+    assert!(analysis.termination.unoriented.is_empty());
+    assert_eq!(
+        analysis.termination.weights,
+        [Weights::from([(
+            Symbol::Unary(UnaryKind::Reciprocal),
+            Weight { own: 1, factor: 1 }
+        )])]
+    );
+
+    // a rule copying its variable is lighter only if its top counts the copies
+    let analysis = analyze(&format!("{quotients} (=> (tan u) (* (sin u) (/ (cos u))))")).unwrap();
+    assert!(analysis.termination.unoriented.is_empty());
+    assert!(analysis.termination.weights.iter().any(|weights| weights
+        .iter()
+        .any(|(symbol, weight)| symbol.to_string() == "tan" && weight.factor == 2)));
+
+    // cos inside sin can be rewritten before sin is, which never gets back to x
+    let analysis = analyze("(=> (sin (cos a)) a) (=> (cos a) (tan a))").unwrap();
+    let unjoined: Vec<_> = analysis.unjoined().collect();
+    assert_eq!(unjoined.len(), 1);
+    assert_eq!(unjoined[0].peak, Lisp::parse("(sin (cos a))").unwrap());
+    assert_eq!(unjoined[0].position, Position::from(vec![0]));
+    assert_eq!(
+        unjoined[0].join,
+        Join::Apart(["a", "(sin (tan a))"].map(|s| Lisp::parse(s).unwrap()))
+    );
+    assert!(!analysis.passes());
+
+    // unless there's a rule for what it rewrites to
+    let analysis =
+        analyze("(=> (sin (cos a)) a) (=> (cos a) (tan a)) (=> (sin (tan a)) a)").unwrap();
+    assert_eq!(analysis.critical_pairs.len(), 1);
+    assert!(analysis.passes());
+
+    // the sum in the outer pattern only overlaps once its variables are made more specific
+    let analysis = analyze("(=> (sin (+ a b)) (cos (* a b))) (=> (+ x 0) x)").unwrap();
+    let pair = analysis
+        .unjoined()
+        .find(|pair| pair.peak == Lisp::parse("(sin (+ 0 x))").unwrap())
+        .unwrap();
+    assert_eq!(
+        pair.join,
+        Join::Apart(["(cos 0)", "(sin x)"].map(|s| Lisp::parse(s).unwrap()))
+    );
+
+    // and guards have to hold for the overlap
+    let analysis =
+        analyze("(=> (sin (+ a b)) (cos (* a b)) (if (nonzero a) (nonzero b))) (=> (+ x 0) x)")
+            .unwrap();
+    assert!(analysis
+        .critical_pairs
+        .iter()
+        .all(|pair| pair.peak != Lisp::parse("(sin (+ 0 x))").unwrap()));
+}
+
+#[test]
+fn test_loaded_rulesets() {
+    use crate::text::*;
+
+    let rulesets = BTreeMap::from_iter(load_rulesets());
+    let analyses = analyze_rulesets(&rulesets, Default::default());
+
+    // every built in ruleset is shown to terminate, except expand, whose distributing copies
+    // reciprocals that combining them in simplify takes away, and reduce, whose factoring turns
+    // a sum of the same product twice into a product, which neither weighs nor orders less
+    for (name, analysis) in &analyses {
+        let unoriented: Vec<_> = analysis
+            .termination
+            .unoriented
+            .iter()
+            .map(|r| (r.ruleset.as_str(), r.index))
+            .collect();
+        match *name {
+            "expand" => assert_eq!(unoriented, [(SIMPLIFY_NAME, 12)]),
+            "reduce" => assert_eq!(unoriented, [("reduce", 0)]),
+            _ => assert!(unoriented.is_empty(), "{name} doesn't terminate"),
+        }
+    }
+
+    // every pair settles, though not every one joins, like a quotient whose denominator is a
+    // product that (* 0 a) only overlaps with once its term is 0
+    for (_, analysis) in &analyses {
+        assert!(analysis
+            .critical_pairs
+            .iter()
+            .all(|pair| !matches!(pair.join, Join::Diverged(_))));
+    }
+    let (_, simplify) = analyses
+        .iter()
+        .find(|(name, _)| *name == SIMPLIFY_NAME)
+        .unwrap();
+    assert!(simplify
+        .unjoined()
+        .any(|pair| pair.peak == Lisp::parse("(/ (* (/ n) 0))").unwrap()));
+}
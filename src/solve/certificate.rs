@@ -0,0 +1,579 @@
+use {
+    crate::{rewrite::*, solve::*},
+    std::collections::{BTreeMap, HashMap},
+};
+
+// proofs broken down to single rule applications, which can be checked with nothing but the
+// matcher, so they can be stored and verified again without searching
+
+/// How a step of a [`Certificate`] follows from the expression before it.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Justification {
+    /// `rule` rewrote the subexpression at `position` once, with its variables bound to
+    /// `bindings`. See [`subexpression`] for how positions are numbered.
+    Rule {
+        rule: Box<Rule>,
+        position: Position,
+        bindings: Bindings,
+    },
+    /// Constants were folded in the subexpression at `position`.
+    FoldConstants { position: Position },
+    /// Small powers were expanded in the subexpression at `position`.
+    ExpandPowers { position: Position },
+}
+
+/// One rewrite in a [`Certificate`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct CertificateStep {
+    /// The ruleset of the [`ProofStep`] this step is part of.
+    pub ruleset: String,
+    pub justification: Justification,
+    /// The justification rewrites `after` into the expression before it, rather than the other
+    /// way around, which is just as good for an equality.
+    pub reversed: bool,
+    pub after: Expression,
+}
+
+/// A [`Proof`] with every ruleset step broken down into single rule applications.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Certificate {
+    pub start: Expression,
+    pub steps: Vec<CertificateStep>,
+}
+
+/// Why a [`Proof`] couldn't be turned into a [`Certificate`], `step` counts from 0.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CertificateError {
+    UnknownRuleset {
+        step: usize,
+        name: String,
+    },
+    /// No sequence of rule applications from the ruleset was found for the step.
+    Unexplained {
+        step: usize,
+    },
+}
+
+/// Why [`check_certificate`] rejected a step, `step` counts from 0.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CheckError {
+    /// The rule isn't in the step's ruleset, nor in `simplify` which is applied along with it.
+    UnknownRule { step: usize },
+    /// The position doesn't exist in the expression.
+    InvalidPosition { step: usize },
+    /// The rule's pattern doesn't match the subexpression with the recorded bindings.
+    NoMatch { step: usize },
+    /// The rule's guard doesn't hold for the bindings.
+    GuardFailed { step: usize },
+    /// The rule's replacement has variables that aren't bound.
+    Unbound { step: usize },
+    /// Rewriting gives something other than the next expression.
+    WrongResult { step: usize },
+}
+
+impl Certificate {
+    /// Breaks every step of `proof` down into the rule applications that make it up, using the
+    /// rulesets it was found with. Steps can be in either direction, and either rewrite to a
+    /// fixpoint like the graph and best-first engines, apply one rule like the e-graph, or apply
+    /// one rule between simplifications like [`StepMode::OneStep`]. Rewriting to a fixpoint is
+    /// retraced within the default [`RewriteLimits`].
+    pub fn from_proof(
+        proof: &Proof,
+        rulesets: &BTreeMap<String, Vec<Rule>>,
+    ) -> Result<Certificate, CertificateError> {
+        let mut steps = Vec::new();
+        let mut before = &proof.start;
+
+        for (step, ProofStep { ruleset, after }) in proof.steps.iter().enumerate() {
+            let traced = match ruleset.as_str() {
+                FOLD_NAME | EXPAND_POWERS_NAME => single_step(before, after, ruleset, &[]),
+                _ => {
+                    let rules =
+                        rulesets
+                            .get(ruleset)
+                            .ok_or_else(|| CertificateError::UnknownRuleset {
+                                step,
+                                name: ruleset.clone(),
+                            })?;
+                    let simplify = rulesets.get(SIMPLIFY_NAME).unwrap_or(rules);
+                    let combined: Vec<_> = rules.iter().chain(simplify).cloned().collect();
+
+                    fixpoint_steps(before, after, &combined)
+                        .or_else(|| single_step(before, after, ruleset, rules))
+                        .or_else(|| simplified_step(before, after, rules, simplify))
+                }
+            };
+
+            let traced = traced.ok_or(CertificateError::Unexplained { step })?;
+            steps.extend(traced.into_iter().map(|(justification, reversed, after)| {
+                CertificateStep {
+                    ruleset: ruleset.clone(),
+                    justification,
+                    reversed,
+                    after,
+                }
+            }));
+            before = after;
+        }
+
+        Ok(Certificate {
+            start: proof.start.clone(),
+            steps,
+        })
+    }
+
+    /// The expression the certificate ends at.
+    pub fn end(&self) -> &Expression {
+        self.steps.last().map_or(&self.start, |step| &step.after)
+    }
+}
+
+/// Replays every step of `certificate` with [`match_pattern`] and [`replace_variable`],
+/// independently of how it was found. Every rule has to be one of `rulesets`, in the ruleset its
+/// step names, so a certificate can't bring rules of its own.
+///
+/// ```
+/// use identity::{solve::*, text::*};
+///
+/// let rulesets = RulesetLoader::new().load().unwrap().rules;
+/// let left = Lisp::parse("(+ (^ (sin x) 2) (^ (cos x) 2))").unwrap();
+/// let report = IdentityProver::new(rulesets.clone()).prove(&left, &Lisp::parse("1").unwrap());
+///
+/// let Verdict::Proved(proof) = report.verdict else { panic!() };
+/// let certificate = Certificate::from_proof(&proof, &rulesets).unwrap();
+/// assert_eq!(check_certificate(&certificate, &rulesets), Ok(()));
+/// ```
+pub fn check_certificate(
+    certificate: &Certificate,
+    rulesets: &BTreeMap<String, Vec<Rule>>,
+) -> Result<(), CheckError> {
+    let mut before = &certificate.start;
+
+    for (step, certificate_step) in certificate.steps.iter().enumerate() {
+        let CertificateStep {
+            ruleset,
+            justification,
+            reversed,
+            after,
+            ..
+        } = certificate_step;
+        let (from, to) = if *reversed {
+            (after, before)
+        } else {
+            (before, after)
+        };
+
+        let rewrite_at = |position: &[usize], rewrite: fn(Expression) -> Expression| {
+            let sub = subexpression(from, position).ok_or(CheckError::InvalidPosition { step })?;
+            replace_subexpression(from.clone(), position, rewrite(sub.clone()))
+                .ok_or(CheckError::InvalidPosition { step })
+        };
+
+        let result = match justification {
+            Justification::Rule {
+                rule,
+                position,
+                bindings,
+            } => {
+                if !known_rule(rule, ruleset, rulesets) {
+                    return Err(CheckError::UnknownRule { step });
+                }
+
+                let sub =
+                    subexpression(from, position).ok_or(CheckError::InvalidPosition { step })?;
+
+                // the recorded bindings have to be the ones the matcher finds, or at least
+                // rebuild the subexpression from the pattern
+                let matched = match_pattern(sub.clone(), rule.pattern.clone())
+                    .map_err(|()| CheckError::NoMatch { step })?;
+                if matched != *bindings
+                    && replace_variable(rule.pattern.clone(), bindings).as_ref() != Ok(sub)
+                {
+                    return Err(CheckError::NoMatch { step });
+                }
+
+                if !rule.guard.as_ref().is_none_or(|g| g.holds(bindings)) {
+                    return Err(CheckError::GuardFailed { step });
+                }
+
+                let replaced = replace_variable(rule.replacement.clone(), bindings)
+                    .map_err(|()| CheckError::Unbound { step })?;
+                replace_subexpression(from.clone(), position, replaced)
+                    .ok_or(CheckError::InvalidPosition { step })?
+            }
+            Justification::FoldConstants { position } => rewrite_at(position, fold_constants)?,
+            Justification::ExpandPowers { position } => rewrite_at(position, expand_powers)?,
+        };
+
+        if result != *to {
+            return Err(CheckError::WrongResult { step });
+        }
+
+        before = after;
+    }
+
+    Ok(())
+}
+
+// whether rule is in the ruleset, or in simplify, compared the way rules are stored when loaded
+fn known_rule(rule: &Rule, ruleset: &str, rulesets: &BTreeMap<String, Vec<Rule>>) -> bool {
+    let normalize = |rule: &Rule| Rule {
+        pattern: expand_powers(rule.pattern.clone()),
+        replacement: expand_powers(rule.replacement.clone()),
+        ..rule.clone()
+    };
+    let rule = normalize(rule);
+
+    [ruleset, SIMPLIFY_NAME]
+        .into_iter()
+        .filter_map(|name| rulesets.get(name))
+        .flatten()
+        .any(|known| normalize(known) == rule)
+}
+
+// a justification, whether it's reversed, and the expression after it
+type Traced = Vec<(Justification, bool, Expression)>;
+
+// the steps ruleset_combiner takes from before to after, or from after back to before
+fn fixpoint_steps(before: &Expression, after: &Expression, rules: &[Rule]) -> Option<Traced> {
+    let forward = trace_ruleset(before.clone(), rules).ok();
+    if let Some((_, steps)) = forward.filter(|(end, _)| end == after) {
+        return Some(
+            steps
+                .into_iter()
+                .map(|(justification, after)| (justification, false, after))
+                .collect(),
+        );
+    }
+
+    let (end, steps) = trace_ruleset(after.clone(), rules).ok()?;
+    (end == *before).then(|| reverse_steps(after, steps))
+}
+
+// one rule application between two rounds of simplify, from before to after or back
+fn simplified_step(
+    before: &Expression,
+    after: &Expression,
+    rules: &[Rule],
+    simplify: &[Rule],
+) -> Option<Traced> {
+    let trace = |from: &Expression, to: &Expression| {
+        let (start, steps) = trace_ruleset(from.clone(), simplify).ok()?;
+
+        rules.iter().find_map(|rule| {
+            rewrite_once(&start, rule)
+                .into_iter()
+                .find_map(|(position, bindings, rewritten)| {
+                    let (end, simplified) = trace_ruleset(rewritten.clone(), simplify).ok()?;
+                    (end == *to).then(|| {
+                        let justification = Justification::Rule {
+                            rule: Box::new(rule.clone()),
+                            position,
+                            bindings,
+                        };
+                        let mut steps = steps.clone();
+                        steps.push((justification, rewritten));
+                        steps.extend(simplified);
+                        steps
+                    })
+                })
+        })
+    };
+
+    if let Some(steps) = trace(before, after) {
+        return Some(
+            steps
+                .into_iter()
+                .map(|(justification, after)| (justification, false, after))
+                .collect(),
+        );
+    }
+
+    trace(after, before).map(|steps| reverse_steps(after, steps))
+}
+
+// steps going forward from start, turned into reversed steps going back to it, each one ends at
+// the expression the one after it started from
+fn reverse_steps(start: &Expression, steps: Vec<(Justification, Expression)>) -> Traced {
+    let mut starts: Vec<_> = [start.clone()]
+        .into_iter()
+        .chain(steps.iter().map(|(_, after)| after.clone()))
+        .collect();
+    starts.pop();
+
+    steps
+        .into_iter()
+        .zip(starts)
+        .rev()
+        .map(|((justification, _), start)| (justification, true, start))
+        .collect()
+}
+
+// a single rewrite anywhere in before or after giving the other one, like the e-graph makes,
+// which is constant folding or power expansion for the rulesets named after them
+fn single_step(
+    before: &Expression,
+    after: &Expression,
+    ruleset: &str,
+    rules: &[Rule],
+) -> Option<Traced> {
+    [(before, after, false), (after, before, true)]
+        .into_iter()
+        .find_map(|(from, to, reversed)| {
+            positions(from).into_iter().find_map(|position| {
+                let sub = subexpression(from, &position)?.clone();
+
+                let rewrites = match ruleset {
+                    FOLD_NAME => vec![(
+                        Justification::FoldConstants {
+                            position: position.clone(),
+                        },
+                        fold_constants(sub),
+                    )],
+                    EXPAND_POWERS_NAME => vec![(
+                        Justification::ExpandPowers {
+                            position: position.clone(),
+                        },
+                        expand_powers(sub),
+                    )],
+                    // the e-graph may have used any of the ways a rule matches
+                    _ => rules
+                        .iter()
+                        .flat_map(|rule| {
+                            apply_rule_all(&sub, rule).map(|(bindings, replaced)| {
+                                let justification = Justification::Rule {
+                                    rule: Box::new(rule.clone()),
+                                    position: position.clone(),
+                                    bindings,
+                                };
+                                (justification, replaced)
+                            })
+                        })
+                        .collect(),
+                };
+
+                rewrites.into_iter().find_map(|(justification, replaced)| {
+                    (replace_subexpression(from.clone(), &position, replaced)? == *to)
+                        .then(|| vec![(justification, reversed, after.clone())])
+                })
+            })
+        })
+}
+
+// like apply_rule, with the bindings used, None if it doesn't apply or changes nothing
+fn apply_rule_traced(expr: Expression, rule: &Rule) -> Option<(Expression, Bindings)> {
+    let bindings = match_pattern(expr.clone(), rule.pattern.clone()).ok()?;
+
+    if !rule.guard.as_ref().is_none_or(|g| g.holds(&bindings)) {
+        return None;
+    }
+
+    let replaced = replace_variable(rule.replacement.clone(), &bindings).ok()?;
+    (replaced != expr).then_some((replaced, bindings))
+}
+
+// apply_ruleset, recording every rule application and normalization it makes
+fn trace_ruleset(
+    mut expr: Expression,
+    rules: &[Rule],
+) -> Result<(Expression, Vec<(Justification, Expression)>), Divergence> {
+    let mut tracker = RewriteTracker::new(RewriteLimits::default());
+    let mut visited = HashMap::from([(expr.clone(), 0)]);
+    let mut steps = Vec::new();
+
+    loop {
+        let previous_expr = expr.clone();
+
+        for rule in rules {
+            let mut rule_steps = Vec::new();
+            expr = trace_recursive(expr, rule, &mut rule_steps, &mut tracker)?;
+
+            steps.extend(rule_steps.into_iter().map(|(position, bindings, after)| {
+                let justification = Justification::Rule {
+                    rule: Box::new(rule.clone()),
+                    position,
+                    bindings,
+                };
+                (justification, after)
+            }));
+        }
+
+        let folded = fold_constants(expr.clone());
+        if folded != expr {
+            steps.push((
+                Justification::FoldConstants {
+                    position: Position::root(),
+                },
+                folded.clone(),
+            ));
+        }
+
+        let expanded = expand_powers(folded.clone());
+        if expanded != folded {
+            steps.push((
+                Justification::ExpandPowers {
+                    position: Position::root(),
+                },
+                expanded.clone(),
+            ));
+        }
+        expr = expanded;
+
+        if expr == previous_expr {
+            break Ok((expr, steps));
+        }
+
+        tracker.step(&expr, &mut visited)?;
+    }
+}
+
+// transform_recursive with a single rule, recording where it applied, with what bindings, and
+// what the whole of expr was after each application
+fn trace_recursive(
+    mut expr: Expression,
+    rule: &Rule,
+    steps: &mut Vec<(Position, Bindings, Expression)>,
+    tracker: &mut RewriteTracker,
+) -> Result<Expression, Divergence> {
+    let mut visited = HashMap::new();
+
+    loop {
+        let previous_expr = expr.clone();
+        let originals: Vec<_> = children(&expr).into_iter().cloned().collect();
+
+        // each step inside a child is also a step in expr, with the child put back among the
+        // others as they are at that point, which may move it
+        for original in originals {
+            let mut child_steps = Vec::new();
+            let mut child = original.clone();
+            trace_recursive(original, rule, &mut child_steps, tracker)?;
+
+            for (mut position, bindings, after) in child_steps {
+                let index = children(&expr).iter().position(|c| **c == child).unwrap();
+                expr = replace_subexpression(expr, &[index], after.clone()).unwrap();
+
+                position.0.insert(0, index);
+                steps.push((position, bindings, expr.clone()));
+                child = after;
+            }
+        }
+
+        if let Some((replaced, bindings)) = apply_rule_traced(expr.clone(), rule) {
+            steps.push((Position::root(), bindings, replaced.clone()));
+            expr = replaced;
+        }
+
+        if expr == previous_expr {
+            break Ok(expr);
+        }
+
+        if visited.is_empty() {
+            visited.insert(previous_expr, 0);
+        }
+        tracker.step(&expr, &mut visited)?;
+    }
+}
+
+#[test]
+fn test_certificate() {
+    use crate::text::*;
+
+    let rulesets = RulesetLoader::new().load().unwrap().rules;
+    let left = Lisp::parse("(* (csc x) (tan x))").unwrap();
+    let right = Lisp::parse("(sec x)").unwrap();
+
+    for (engine, step) in [
+        (Engine::Graph, StepMode::Fixpoint),
+        (Engine::Egraph, StepMode::Fixpoint),
+        (Engine::BestFirst, StepMode::Fixpoint),
+        (Engine::BestFirst, StepMode::OneStep),
+    ] {
+        let report = IdentityProver::new(rulesets.clone())
+            .engine(engine)
+            .step_mode(step)
+            .prove(&left, &right);
+        let Verdict::Proved(proof) = report.verdict else {
+            panic!("{engine:?}: {:?}", report.verdict)
+        };
+
+        let certificate = Certificate::from_proof(&proof, &rulesets).unwrap();
+        assert_eq!(
+            check_certificate(&certificate, &rulesets),
+            Ok(()),
+            "{engine:?}"
+        );
+        assert_eq!(certificate.end(), &right);
+        assert!(certificate.steps.len() >= proof.steps.len());
+
+        // the traced rewriting has to end where the untraced one does
+        for ProofStep { ruleset, .. } in &proof.steps {
+            if let Some(rules) = rulesets.get(ruleset) {
+                let (end, _) = trace_ruleset(left.clone(), rules).unwrap();
+                let limits = RewriteLimits::default();
+                assert_eq!(Ok(end), ruleset_combiner(&[rules], limits)(left.clone()));
+            }
+        }
+    }
+
+    // a tampered step is caught
+    let proof = Proof {
+        start: Lisp::parse("(* a (/ a))").unwrap(),
+        steps: vec![ProofStep {
+            ruleset: "cancel".to_string(),
+            after: Lisp::parse("1").unwrap(),
+        }],
+    };
+    let certificate = Certificate::from_proof(&proof, &rulesets).unwrap();
+    assert_eq!(check_certificate(&certificate, &rulesets), Ok(()));
+
+    let mut tampered = certificate.clone();
+    tampered.steps[0].after = Lisp::parse("2").unwrap();
+    assert_eq!(
+        check_certificate(&tampered, &rulesets),
+        Err(CheckError::WrongResult { step: 0 })
+    );
+
+    // so is a rule that was never loaded, even one that checks on its own terms
+    let rule = Lisp::parse_ruleset("(=> 0 1)").unwrap().remove(0);
+    let forged = Certificate {
+        start: Lisp::parse("0").unwrap(),
+        steps: vec![CertificateStep {
+            ruleset: "cancel".to_string(),
+            justification: Justification::Rule {
+                rule: Box::new(rule),
+                position: Position::root(),
+                bindings: Bindings::default(),
+            },
+            reversed: false,
+            after: Lisp::parse("1").unwrap(),
+        }],
+    };
+    assert_eq!(
+        check_certificate(&forged, &rulesets),
+        Err(CheckError::UnknownRule { step: 0 })
+    );
+
+    // and a loaded rule credited to a ruleset it isn't in
+    let mut moved = certificate;
+    moved.steps[0].ruleset = "trigpyth".to_string();
+    assert_eq!(
+        check_certificate(&moved, &rulesets),
+        Err(CheckError::UnknownRule { step: 0 })
+    );
+
+    // rules written with powers are the same as the loaded ones with them expanded
+    let written = Lisp::parse_ruleset("(=> (^ (sin x) 2) (+ 1 (- (^ (cos x) 2))))").unwrap();
+    let loaded = BTreeMap::from([(
+        "square".to_string(),
+        vec![Rule {
+            pattern: expand_powers(written[0].pattern.clone()),
+            replacement: expand_powers(written[0].replacement.clone()),
+            ..written[0].clone()
+        }],
+    )]);
+    assert!(known_rule(&written[0], "square", &loaded));
+    assert!(!known_rule(&written[0], "cancel", &loaded));
+}
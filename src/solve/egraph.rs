@@ -0,0 +1,1005 @@
+use {
+    crate::{expression::*, rewrite::*},
+    std::{
+        cell::Cell,
+        collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+        time::{Duration, Instant},
+    },
+};
+
+// equivalence classes of expressions, every added node keeps its own id so that
+// the exact term it came from can be recovered when explaining a merge
+
+pub type Id = usize;
+
+// placeholder variables stand in for whole classes while matching against a node, the prefix is
+// repeated until no variable added with add_expr starts with it
+const PLACEHOLDER_PREFIX: &str = "#";
+// cap on how many terms a single node is expanded into for matching, the rest are dropped and
+// counted as truncated
+const MAX_EXPANSIONS: usize = 64;
+
+#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+enum ENode {
+    Variadic {
+        children: Vec<Id>,
+        kind: VariadicKind,
+    },
+    Unary {
+        child: Id,
+        kind: UnaryKind,
+    },
+    Power {
+        base: Id,
+        exponent: Id,
+    },
+    Literal(Literal),
+    Variable(Variable),
+}
+
+#[derive(Clone, Debug)]
+enum Justification {
+    Rule(String),
+    Congruence,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct EGraphLimits {
+    pub max_iterations: usize,
+    pub max_nodes: usize,
+    // matches on or producing sums and products with more terms than this are dropped
+    pub max_arity: usize,
+    // saturation stops once it has run this long, even in the middle of an iteration
+    pub max_time: Option<Duration>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    Equivalent,
+    Saturated,
+    IterationLimit,
+    NodeLimit,
+    TimeLimit,
+    // nothing more changed, but some matches were dropped for the caps on expansions and arity
+    Truncated,
+}
+
+#[derive(Debug)]
+pub struct EGraphReport {
+    pub stop_reason: StopReason,
+    pub iterations: usize,
+    pub nodes: usize,
+    pub classes: usize,
+    // matches dropped for the caps on expansions and arity
+    pub truncated: usize,
+    // steps from the left side to the right side, labelled with the ruleset that was used
+    pub steps: Option<Vec<(String, Expression)>>,
+}
+
+#[derive(Default)]
+pub struct EGraph {
+    nodes: Vec<ENode>,
+    union_find: Vec<Id>,
+    proof_forest: Vec<Option<(Id, Justification)>>,
+    exact: HashMap<ENode, Id>,
+    memo: HashMap<ENode, Id>,
+    classes: BTreeMap<Id, Vec<Id>>,
+    // how many times the placeholder prefix is repeated, less one
+    reserved: usize,
+    truncated: Cell<usize>,
+}
+
+impl Default for EGraphLimits {
+    fn default() -> Self {
+        Self {
+            max_iterations: 6,
+            max_nodes: 2_000,
+            max_arity: 6,
+            max_time: None,
+        }
+    }
+}
+
+impl EGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn num_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn num_classes(&self) -> usize {
+        self.classes.len()
+    }
+
+    pub fn find(&self, mut id: Id) -> Id {
+        while self.union_find[id] != id {
+            id = self.union_find[id];
+        }
+        id
+    }
+
+    pub fn equivalent(&self, a: Id, b: Id) -> bool {
+        self.find(a) == self.find(b)
+    }
+
+    pub fn truncated(&self) -> usize {
+        self.truncated.get()
+    }
+
+    pub fn add_expr(&mut self, expr: &Expression) -> Id {
+        // a variable named like a placeholder would be taken for the class it names
+        while variables(expr)
+            .iter()
+            .any(|v| v.id.as_ref().starts_with(&self.placeholder_prefix()))
+        {
+            self.reserved += 1;
+        }
+
+        self.add_term(expr)
+    }
+
+    // adds expr with its placeholders standing for their classes
+    fn add_term(&mut self, expr: &Expression) -> Id {
+        let node = match expr {
+            Expression::Concrete(c) => match c {
+                Concrete::Variadic(Variadic { terms, kind }) => {
+                    let mut children: Vec<_> = terms.iter().map(|t| self.add_term(t)).collect();
+                    children.sort_unstable();
+                    ENode::Variadic {
+                        children,
+                        kind: *kind,
+                    }
+                }
+                Concrete::Unary(Unary { argument, kind }) => ENode::Unary {
+                    child: self.add_term(argument),
+                    kind: kind.clone(),
+                },
+                Concrete::Power(Power { base, exponent }) => ENode::Power {
+                    base: self.add_term(base),
+                    exponent: self.add_term(exponent),
+                },
+                Concrete::Literal(l) => ENode::Literal(l.clone()),
+            },
+            Expression::Variable(v) => match self.placeholder_id(v) {
+                Some(id) if id < self.nodes.len() => return id,
+                _ => ENode::Variable(v.clone()),
+            },
+        };
+
+        self.add_node(node)
+    }
+
+    // the term an id was originally created from, with every child also in its original form
+    pub fn term(&self, id: Id) -> Expression {
+        match &self.nodes[id] {
+            ENode::Variadic { children, kind } => {
+                Variadic::new(children.iter().map(|&c| self.term(c)), *kind).into()
+            }
+            ENode::Unary { child, kind } => Unary::new(self.term(*child), kind.clone()).into(),
+            ENode::Power { base, exponent } => {
+                Power::new(self.term(*base), self.term(*exponent)).into()
+            }
+            ENode::Literal(l) => l.clone().into(),
+            ENode::Variable(v) => v.clone().into(),
+        }
+    }
+
+    // applies every rule at every node until the goal ids merge or a limit is reached
+    pub fn saturate(
+        &mut self,
+        rulesets: &BTreeMap<String, Vec<Rule>>,
+        goal: (Id, Id),
+        limits: EGraphLimits,
+    ) -> (StopReason, usize) {
+        let fold_name = FOLD_NAME.to_string();
+        let powers_name = EXPAND_POWERS_NAME.to_string();
+        let start = Instant::now();
+        let timed_out = || limits.max_time.is_some_and(|t| start.elapsed() >= t);
+
+        for iteration in 0..limits.max_iterations {
+            if self.equivalent(goal.0, goal.1) {
+                return (StopReason::Equivalent, iteration);
+            }
+
+            let mut matches = Vec::new();
+
+            for &id in self.memo.values() {
+                // the matches found so far are dropped, the graph is left as it was
+                if timed_out() {
+                    return (StopReason::TimeLimit, iteration);
+                }
+
+                // constant folding is built in, it runs on the literals known for each child
+                let before = self.literal_term(id);
+                let after = fold_constants(before.clone());
+
+                if after != before {
+                    matches.push((before.clone(), after, &fold_name));
+                }
+
+                // and so is the expansion of small powers
+                let after = expand_powers(before.clone());
+
+                if after != before {
+                    matches.push((before, after, &powers_name));
+                }
+
+                for (name, ruleset) in rulesets.iter() {
+                    for rule in ruleset.iter() {
+                        for before in self.expand(id, &rule.pattern) {
+                            if max_arity(&before) > limits.max_arity {
+                                self.truncate();
+                                continue;
+                            }
+
+                            match self.apply_rule(&before, rule) {
+                                Some(after) if max_arity(&after) <= limits.max_arity => {
+                                    matches.push((before, after, name))
+                                }
+                                Some(_) => self.truncate(),
+                                None => {}
+                            }
+                        }
+                    }
+                }
+            }
+
+            let size = self.num_nodes();
+            let mut changed = false;
+
+            for (before, after, name) in matches {
+                let before = self.add_term(&before);
+                let after = self.add_term(&after);
+                changed |= self.union(before, after, Justification::Rule(name.clone()));
+
+                if self.num_nodes() > limits.max_nodes {
+                    self.rebuild();
+                    return (StopReason::NodeLimit, iteration + 1);
+                }
+            }
+
+            self.rebuild();
+
+            if !changed && size == self.num_nodes() {
+                let reason = match self.truncated() {
+                    0 => StopReason::Saturated,
+                    _ => StopReason::Truncated,
+                };
+                return (reason, iteration + 1);
+            }
+        }
+
+        if self.equivalent(goal.0, goal.1) {
+            (StopReason::Equivalent, limits.max_iterations)
+        } else {
+            (StopReason::IterationLimit, limits.max_iterations)
+        }
+    }
+
+    // sequence of terms leading from term(a) to term(b), None if they aren't equivalent
+    pub fn explain(&self, a: Id, b: Id) -> Option<Vec<(String, Expression)>> {
+        self.explain_rec(a, b, &mut HashSet::new())
+    }
+
+    fn add_node(&mut self, node: ENode) -> Id {
+        if let Some(&id) = self.exact.get(&node) {
+            return id;
+        }
+
+        let id = self.nodes.len();
+        let canonical = self.canonicalize(&node);
+
+        self.nodes.push(node.clone());
+        self.union_find.push(id);
+        self.proof_forest.push(None);
+        self.exact.insert(node, id);
+        self.classes.insert(id, vec![id]);
+
+        if let Some(&existing) = self.memo.get(&canonical) {
+            self.union(existing, id, Justification::Congruence);
+        } else {
+            self.memo.insert(canonical, id);
+        }
+
+        id
+    }
+
+    fn canonicalize(&self, node: &ENode) -> ENode {
+        match node {
+            ENode::Variadic { children, kind } => {
+                let mut children: Vec<_> = children.iter().map(|&c| self.find(c)).collect();
+                children.sort_unstable();
+                ENode::Variadic {
+                    children,
+                    kind: *kind,
+                }
+            }
+            ENode::Unary { child, kind } => ENode::Unary {
+                child: self.find(*child),
+                kind: kind.clone(),
+            },
+            ENode::Power { base, exponent } => ENode::Power {
+                base: self.find(*base),
+                exponent: self.find(*exponent),
+            },
+            leaf => leaf.clone(),
+        }
+    }
+
+    fn union(&mut self, a: Id, b: Id, justification: Justification) -> bool {
+        let (root_a, root_b) = (self.find(a), self.find(b));
+
+        if root_a == root_b {
+            return false;
+        }
+
+        // make a the root of its proof tree, then hang it off of b
+        let mut previous = None;
+        let mut current = a;
+        while let Some((parent, j)) =
+            std::mem::replace(&mut self.proof_forest[current], previous.take())
+        {
+            previous = Some((current, j));
+            current = parent;
+        }
+        self.proof_forest[a] = Some((b, justification));
+
+        let (big, small) = if self.classes[&root_a].len() >= self.classes[&root_b].len() {
+            (root_a, root_b)
+        } else {
+            (root_b, root_a)
+        };
+
+        self.union_find[small] = big;
+        let members = self.classes.remove(&small).unwrap();
+        self.classes.get_mut(&big).unwrap().extend(members);
+
+        true
+    }
+
+    // restores the congruence invariant: nodes with equivalent children are equivalent
+    fn rebuild(&mut self) {
+        loop {
+            let mut changed = false;
+            self.memo.clear();
+
+            for id in 0..self.nodes.len() {
+                let canonical = self.canonicalize(&self.nodes[id]);
+
+                if let Some(&existing) = self.memo.get(&canonical) {
+                    changed |= self.union(existing, id, Justification::Congruence);
+                } else {
+                    self.memo.insert(canonical, id);
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    // terms equal to the node that have the shape of the pattern, with children either
+    // left as class placeholders or expanded into one of the nodes of their class
+    fn expand(&self, id: Id, patt: &Expression) -> Vec<Expression> {
+        let child_options = |child: Id, subpatterns: &[&Expression]| {
+            let mut options = vec![self.placeholder(child)];
+
+            for subpattern in subpatterns {
+                if matches!(
+                    subpattern,
+                    Expression::Variable(Variable {
+                        kind: VarKind::Any,
+                        ..
+                    })
+                ) {
+                    continue;
+                }
+
+                let mut seen = HashSet::new();
+
+                // congruent members all expand to the same shapes, only try one of each
+                for &member in self.classes[&self.find(child)].iter() {
+                    if !seen.insert(self.canonicalize(&self.nodes[member])) {
+                        continue;
+                    }
+
+                    // only keep expansions that could fill in for the subpattern on their own
+                    options.extend(
+                        self.expand(member, subpattern)
+                            .into_iter()
+                            .filter(|e| match_pattern(e.clone(), (*subpattern).clone()).is_ok()),
+                    );
+
+                    if options.len() > MAX_EXPANSIONS {
+                        options.truncate(MAX_EXPANSIONS);
+                        self.truncate();
+                        return options;
+                    }
+                }
+            }
+
+            options
+        };
+
+        let patt = match patt {
+            Expression::Concrete(patt) => patt,
+            Expression::Variable(Variable {
+                kind: VarKind::Any, ..
+            }) => return vec![self.placeholder(id)],
+            // typed variables need to see the node itself to know if they can bind to it
+            Expression::Variable(_) => return vec![self.shallow_term(id)],
+        };
+
+        match (&self.nodes[id], patt) {
+            (
+                ENode::Variadic { children, kind },
+                Concrete::Variadic(Variadic {
+                    terms,
+                    kind: pattern_kind,
+                }),
+            ) if kind == pattern_kind => {
+                let subpatterns: Vec<_> = terms.iter().collect();
+                // each concrete subpattern or typed variable can only use up one expanded
+                // child, the remaining ones are absorbed by variables and can stay placeholders
+                let max_expanded = terms
+                    .iter()
+                    .filter(|t| {
+                        !matches!(
+                            t,
+                            Expression::Variable(Variable {
+                                kind: VarKind::Any,
+                                ..
+                            })
+                        )
+                    })
+                    .count();
+                let mut combinations = vec![(Vec::new(), 0)];
+
+                for &child in children.iter() {
+                    let options = child_options(child, &subpatterns);
+                    combinations = combinations
+                        .into_iter()
+                        .flat_map(|(c, expanded)| {
+                            options.iter().enumerate().filter_map(move |(i, o)| {
+                                let expanded = expanded + usize::from(i > 0);
+                                (expanded <= max_expanded).then(|| {
+                                    let mut c = c.clone();
+                                    c.push(o.clone());
+                                    (c, expanded)
+                                })
+                            })
+                        })
+                        .take(MAX_EXPANSIONS + 1)
+                        .collect();
+
+                    if combinations.len() > MAX_EXPANSIONS {
+                        combinations.truncate(MAX_EXPANSIONS);
+                        self.truncate();
+                    }
+                }
+
+                combinations
+                    .into_iter()
+                    .map(|(c, _)| Variadic::new(c, *kind).into())
+                    .collect()
+            }
+            (
+                ENode::Unary { child, kind },
+                Concrete::Unary(Unary {
+                    argument,
+                    kind: pattern_kind,
+                }),
+            ) if kind == pattern_kind
+                || matches!(
+                    (kind, pattern_kind),
+                    (UnaryKind::Named { .. }, UnaryKind::Variable { .. })
+                ) =>
+            {
+                child_options(*child, &[argument])
+                    .into_iter()
+                    .map(|o| Unary::new(o, kind.clone()).into())
+                    .collect()
+            }
+            (
+                ENode::Power { base, exponent },
+                Concrete::Power(Power {
+                    base: pattern_base,
+                    exponent: pattern_exponent,
+                }),
+            ) => {
+                let exponents = child_options(*exponent, &[pattern_exponent]);
+
+                let mut powers: Vec<_> = child_options(*base, &[pattern_base])
+                    .into_iter()
+                    .flat_map(|b| {
+                        exponents
+                            .iter()
+                            .map(move |e| Power::new(b.clone(), e.clone()).into())
+                    })
+                    .take(MAX_EXPANSIONS + 1)
+                    .collect();
+
+                if powers.len() > MAX_EXPANSIONS {
+                    powers.truncate(MAX_EXPANSIONS);
+                    self.truncate();
+                }
+
+                powers
+            }
+            (ENode::Literal(l), Concrete::Literal(pattern_literal)) if l == pattern_literal => {
+                vec![l.clone().into()]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    // the node with each child replaced by a literal of its class, or a placeholder if it
+    // has none
+    fn literal_term(&self, id: Id) -> Expression {
+        let child = |c: Id| {
+            self.classes[&self.find(c)]
+                .iter()
+                .find_map(|&m| match &self.nodes[m] {
+                    ENode::Literal(l) => Some(l.clone().into()),
+                    _ => None,
+                })
+                .unwrap_or_else(|| self.placeholder(c))
+        };
+
+        match &self.nodes[id] {
+            ENode::Variadic { children, kind } => {
+                Variadic::new(children.iter().map(|&c| child(c)), *kind).into()
+            }
+            ENode::Unary { child: c, kind } => Unary::new(child(*c), kind.clone()).into(),
+            ENode::Power { base, exponent } => Power::new(child(*base), child(*exponent)).into(),
+            ENode::Literal(l) => l.clone().into(),
+            ENode::Variable(v) => v.clone().into(),
+        }
+    }
+
+    // the node with its children left as class placeholders
+    fn shallow_term(&self, id: Id) -> Expression {
+        match &self.nodes[id] {
+            ENode::Variadic { children, kind } => {
+                Variadic::new(children.iter().map(|&c| self.placeholder(c)), *kind).into()
+            }
+            ENode::Unary { child, kind } => {
+                Unary::new(self.placeholder(*child), kind.clone()).into()
+            }
+            ENode::Power { base, exponent } => {
+                Power::new(self.placeholder(*base), self.placeholder(*exponent)).into()
+            }
+            ENode::Literal(l) => l.clone().into(),
+            ENode::Variable(v) => v.clone().into(),
+        }
+    }
+
+    // like apply_rule, but with the guard holding for what the placeholders stand for, and
+    // typed variables not bound to them, since they'd take a placeholder for a symbol
+    fn apply_rule(&self, before: &Expression, rule: &Rule) -> Option<Expression> {
+        let typed: Vec<_> = variables(&rule.pattern)
+            .into_iter()
+            .filter(|v| v.kind != VarKind::Any)
+            .map(|v| v.untyped())
+            .collect();
+        let bind = match_all(before.clone(), rule.pattern.clone()).find(|bind| {
+            typed.iter().all(|v| {
+                !matches!(
+                    bind.terms.get(v),
+                    Some(Expression::Variable(p)) if self.placeholder_id(p).is_some()
+                )
+            })
+        })?;
+
+        if !rule
+            .guard
+            .as_ref()
+            .is_none_or(|g| self.guard_holds(g, &bind))
+        {
+            return None;
+        }
+
+        replace_variable(rule.replacement.clone(), &bind).ok()
+    }
+
+    // a placeholder in what a guard looks at could be any member of its class, so the guard has
+    // to hold with each of them in its place, and doesn't if there are too many ways to check
+    fn guard_holds(&self, guard: &Guard, bind: &Bindings) -> bool {
+        fn args<'a>(predicate: &'a Predicate, output: &mut Vec<&'a Variable>) {
+            match predicate {
+                Predicate::Not(p) => args(p, output),
+                p => output.extend(p.args()),
+            }
+        }
+
+        let mut guarded = Vec::new();
+        guard.predicates.iter().for_each(|p| args(p, &mut guarded));
+
+        let ids: BTreeSet<_> = guarded
+            .iter()
+            .filter_map(|v| bind.terms.get(v))
+            .flat_map(variables)
+            .filter_map(|v| self.placeholder_id(&v))
+            .collect();
+
+        let mut choices = vec![BTreeMap::new()];
+
+        for id in ids {
+            let class = &self.classes[&self.find(id)];
+
+            if choices.len() * class.len() > MAX_EXPANSIONS {
+                self.truncate();
+                return false;
+            }
+
+            let members: BTreeSet<_> = class.iter().map(|&m| self.term(m)).collect();
+
+            choices = choices
+                .into_iter()
+                .flat_map(|choice| {
+                    members.iter().map(move |member| {
+                        let mut choice = choice.clone();
+                        choice.insert(id, member.clone());
+                        choice
+                    })
+                })
+                .collect();
+        }
+
+        choices.iter().all(|choice| {
+            let mut bind = bind.clone();
+
+            for v in &guarded {
+                if let Some(term) = bind.terms.get_mut(v) {
+                    *term = transform_recursive(term.clone(), &mut |e| match &e {
+                        Expression::Variable(v) => self
+                            .placeholder_id(v)
+                            .and_then(|id| choice.get(&id))
+                            .cloned()
+                            .unwrap_or(e),
+                        _ => e,
+                    });
+                }
+            }
+
+            guard.holds(&bind)
+        })
+    }
+
+    fn placeholder_prefix(&self) -> String {
+        PLACEHOLDER_PREFIX.repeat(self.reserved + 1)
+    }
+
+    fn placeholder(&self, id: Id) -> Expression {
+        Variable::new(format!("{}{id}", self.placeholder_prefix())).into()
+    }
+
+    fn placeholder_id(&self, var: &Variable) -> Option<Id> {
+        var.id
+            .as_ref()
+            .strip_prefix(&self.placeholder_prefix())?
+            .parse()
+            .ok()
+    }
+
+    fn truncate(&self) {
+        self.truncated.set(self.truncated.get() + 1);
+    }
+
+    fn explain_rec(
+        &self,
+        a: Id,
+        b: Id,
+        visiting: &mut HashSet<(Id, Id)>,
+    ) -> Option<Vec<(String, Expression)>> {
+        if !self.equivalent(a, b) || !visiting.insert((a, b)) {
+            return None;
+        }
+
+        let ancestors = |mut id: Id| {
+            let mut path = vec![id];
+            while let Some((parent, _)) = &self.proof_forest[id] {
+                id = *parent;
+                path.push(id);
+            }
+            path
+        };
+
+        let (path_a, path_b) = (ancestors(a), ancestors(b));
+        let common = path_a.iter().find(|id| path_b.contains(id)).copied()?;
+
+        let mut edges = Vec::new();
+
+        for &id in path_a.iter().take_while(|&&id| id != common) {
+            let (parent, j) = self.proof_forest[id].clone().unwrap();
+            edges.push((id, parent, j));
+        }
+
+        for &id in path_b
+            .iter()
+            .take_while(|&&id| id != common)
+            .collect::<Vec<_>>()
+            .iter()
+            .rev()
+        {
+            let (parent, j) = self.proof_forest[*id].clone().unwrap();
+            edges.push((parent, *id, j));
+        }
+
+        let mut steps = Vec::new();
+
+        for (from, to, justification) in edges {
+            match justification {
+                Justification::Rule(name) => steps.push((name, self.term(to))),
+                Justification::Congruence => {
+                    steps.extend(self.explain_congruence(from, to, visiting)?)
+                }
+            }
+        }
+
+        visiting.remove(&(a, b));
+
+        Some(steps)
+    }
+
+    fn explain_congruence(
+        &self,
+        from: Id,
+        to: Id,
+        visiting: &mut HashSet<(Id, Id)>,
+    ) -> Option<Vec<(String, Expression)>> {
+        match (&self.nodes[from], &self.nodes[to]) {
+            (ENode::Unary { child: a, kind }, ENode::Unary { child: b, .. }) => Some(
+                self.explain_rec(*a, *b, visiting)?
+                    .into_iter()
+                    .map(|(name, t)| (name, Unary::new(t, kind.clone()).into()))
+                    .collect(),
+            ),
+            (
+                ENode::Power { base: a, exponent },
+                ENode::Power {
+                    base: b,
+                    exponent: target,
+                },
+            ) => {
+                // rewrite the base first, then the exponent
+                let mut steps: Vec<_> = self
+                    .explain_rec(*a, *b, visiting)?
+                    .into_iter()
+                    .map(|(name, t)| (name, Power::new(t, self.term(*exponent)).into()))
+                    .collect();
+                let base = self.term(*b);
+
+                steps.extend(
+                    self.explain_rec(*exponent, *target, visiting)?
+                        .into_iter()
+                        .map(|(name, t)| (name, Power::new(base.clone(), t).into())),
+                );
+
+                Some(steps)
+            }
+            (
+                ENode::Variadic {
+                    children: from_children,
+                    kind,
+                },
+                ENode::Variadic {
+                    children: to_children,
+                    ..
+                },
+            ) => {
+                let mut unused = to_children.clone();
+                let mut current: Vec<_> = from_children.iter().map(|&c| self.term(c)).collect();
+                let mut steps = Vec::new();
+
+                for (i, &child) in from_children.iter().enumerate() {
+                    let j = unused.iter().position(|&c| self.equivalent(c, child))?;
+                    let target = unused.swap_remove(j);
+
+                    for (name, t) in self.explain_rec(child, target, visiting)? {
+                        current[i] = t;
+                        steps.push((name, Variadic::new(current.clone(), *kind).into()));
+                    }
+                }
+
+                Some(steps)
+            }
+            _ => Some(Vec::new()),
+        }
+    }
+}
+
+pub fn egraph_identity(
+    left: Expression,
+    right: Expression,
+    rulesets: &BTreeMap<String, Vec<Rule>>,
+    limits: EGraphLimits,
+) -> EGraphReport {
+    let mut egraph = EGraph::new();
+    let goal = (egraph.add_expr(&left), egraph.add_expr(&right));
+
+    let (stop_reason, iterations) = egraph.saturate(rulesets, goal, limits);
+
+    EGraphReport {
+        stop_reason,
+        iterations,
+        nodes: egraph.num_nodes(),
+        classes: egraph.num_classes(),
+        truncated: egraph.truncated(),
+        steps: egraph.explain(goal.0, goal.1),
+    }
+}
+
+fn max_arity(expr: &Expression) -> usize {
+    match expr {
+        Expression::Concrete(Concrete::Variadic(Variadic { terms, .. })) => terms
+            .iter()
+            .map(max_arity)
+            .fold(terms.iter().count(), usize::max),
+        Expression::Concrete(Concrete::Unary(Unary { argument, .. })) => max_arity(argument),
+        Expression::Concrete(Concrete::Power(Power { base, exponent })) => {
+            max_arity(base).max(max_arity(exponent))
+        }
+        _ => 0,
+    }
+}
+
+#[test]
+fn test_egraph_congruence() {
+    use crate::text::*;
+
+    let mut egraph = EGraph::new();
+    let a = egraph.add_expr(&Lisp::parse("(sin (+ x 0))").unwrap());
+    let b = egraph.add_expr(&Lisp::parse("(sin x)").unwrap());
+    let x0 = egraph.add_expr(&Lisp::parse("(+ x 0)").unwrap());
+    let x = egraph.add_expr(&Lisp::parse("x").unwrap());
+
+    assert!(!egraph.equivalent(a, b));
+
+    egraph.union(x0, x, Justification::Rule("simplify".to_string()));
+    egraph.rebuild();
+
+    assert!(egraph.equivalent(a, b));
+    assert_eq!(
+        egraph.explain(a, b),
+        Some(vec![(
+            "simplify".to_string(),
+            Lisp::parse("(sin x)").unwrap()
+        )])
+    );
+}
+
+#[test]
+fn test_egraph_identity() {
+    use crate::{solve::load_rulesets, text::*};
+
+    let rulesets = BTreeMap::from_iter(load_rulesets());
+    let left = Lisp::parse("(* (tan x) (cos x))").unwrap();
+    let right = Lisp::parse("(sin x)").unwrap();
+
+    let report = egraph_identity(left.clone(), right.clone(), &rulesets, Default::default());
+
+    println!("{report:?}");
+
+    assert_eq!(report.stop_reason, StopReason::Equivalent);
+
+    let steps = report.steps.unwrap();
+    assert_eq!(steps.last().unwrap().1, right);
+}
+
+#[test]
+fn test_egraph_fold() {
+    use crate::text::*;
+
+    let report = egraph_identity(
+        Lisp::parse("(+ x (* 2 (/ 4)) 1/2)").unwrap(),
+        Lisp::parse("(+ 1 x)").unwrap(),
+        &BTreeMap::new(),
+        Default::default(),
+    );
+
+    assert_eq!(report.stop_reason, StopReason::Equivalent);
+    assert!(report
+        .steps
+        .unwrap()
+        .iter()
+        .all(|(name, _)| name == FOLD_NAME));
+}
+
+#[test]
+fn test_egraph_placeholder_names() {
+    use crate::text::*;
+
+    // #0 would name the class of x if placeholders kept their usual prefix
+    let mut egraph = EGraph::new();
+    let left = Lisp::parse("(+ x y)").unwrap();
+    let left = egraph.add_expr(&left);
+    let right = Variadic::new(
+        [Variable::new("x").into(), Variable::new("#0").into()],
+        VariadicKind::Addition,
+    )
+    .into();
+    let right_id = egraph.add_expr(&right);
+
+    assert!(!egraph.equivalent(left, right_id));
+    assert_eq!(egraph.term(right_id), right);
+}
+
+#[test]
+fn test_egraph_truncated() {
+    use crate::text::*;
+
+    let rulesets = BTreeMap::from([(
+        "test".to_string(),
+        Lisp::parse_ruleset("(=> (+ a b) (* a b))").unwrap(),
+    )]);
+    let limits = EGraphLimits {
+        max_arity: 1,
+        ..Default::default()
+    };
+
+    let report = egraph_identity(
+        Lisp::parse("(+ x y)").unwrap(),
+        Lisp::parse("(* x y)").unwrap(),
+        &rulesets,
+        limits,
+    );
+
+    assert_eq!(report.stop_reason, StopReason::Truncated);
+    assert!(report.truncated > 0);
+}
+
+#[test]
+fn test_egraph_guard_placeholders() {
+    use crate::{solve::load_rulesets, text::*};
+
+    // cancelling needs a nonzero factor, and everything in the class of 0 is zero
+    let rulesets = BTreeMap::from_iter(load_rulesets());
+    let report = egraph_identity(
+        Lisp::parse("(* 0 (/ 0))").unwrap(),
+        Lisp::parse("1").unwrap(),
+        &rulesets,
+        Default::default(),
+    );
+
+    assert_ne!(report.stop_reason, StopReason::Equivalent);
+    assert_eq!(report.steps, None);
+
+    let report = egraph_identity(
+        Lisp::parse("(* x (/ x))").unwrap(),
+        Lisp::parse("1").unwrap(),
+        &rulesets,
+        Default::default(),
+    );
+
+    assert_eq!(report.stop_reason, StopReason::Equivalent);
+}
+
+#[test]
+fn test_egraph_typed_placeholders() {
+    use crate::text::*;
+
+    let rulesets = BTreeMap::from([(
+        "test".to_string(),
+        Lisp::parse_ruleset("(=> (sin ?x:var) (cos ?x))").unwrap(),
+    )]);
+    let prove = |left: &str, right: &str| {
+        egraph_identity(
+            Lisp::parse(left).unwrap(),
+            Lisp::parse(right).unwrap(),
+            &rulesets,
+            Default::default(),
+        )
+        .stop_reason
+    };
+
+    assert_eq!(prove("(sin y)", "(cos y)"), StopReason::Equivalent);
+    // the argument is a class of its own, which isn't a variable
+    assert_eq!(
+        prove("(sin (+ y 1))", "(cos (+ y 1))"),
+        StopReason::Saturated
+    );
+}
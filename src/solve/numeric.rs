@@ -0,0 +1,148 @@
+use {crate::expression::*, std::collections::BTreeMap};
+
+// compares both sides of an identity at random points before (or instead of) proving it
+
+#[derive(Clone, Copy, Debug)]
+pub struct NumericOptions {
+    pub samples: usize,
+    // relative to the magnitude of the values being compared, absolute below 1
+    pub tolerance: f64,
+    pub seed: u64,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum NumericVerdict {
+    Counterexample {
+        assignment: BTreeMap<Variable, f64>,
+        left: f64,
+        right: f64,
+    },
+    // both sides agreed at this many sample points
+    Plausible(usize),
+    // too few points where both sides could be evaluated
+    Inconclusive,
+}
+
+// small deterministic generator so that counterexamples are reproducible
+pub struct Rng(u64);
+
+// sampling stays away from poles by discarding points with values this large
+const MAX_MAGNITUDE: f64 = 1e6;
+// how many more points are drawn than samples requested, to make up for discarded ones
+const ATTEMPT_FACTOR: usize = 4;
+
+impl Default for NumericOptions {
+    fn default() -> Self {
+        Self {
+            samples: 16,
+            tolerance: 1e-9,
+            seed: 0x5eed,
+        }
+    }
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    // splitmix64
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    // uniform in [low, high)
+    pub fn range(&mut self, low: f64, high: f64) -> f64 {
+        let unit = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        low + unit * (high - low)
+    }
+
+    // uniform in 0..n
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+pub fn approx_eq(a: f64, b: f64, tolerance: f64) -> bool {
+    (a - b).abs() <= tolerance * a.abs().max(b.abs()).max(1.0)
+}
+
+pub fn numeric_check(
+    left: &Expression,
+    right: &Expression,
+    options: NumericOptions,
+) -> NumericVerdict {
+    let mut rng = Rng::new(options.seed);
+    let mut vars = variables(left);
+    vars.extend(variables(right));
+    vars.sort();
+    vars.dedup();
+
+    let mut agreed = 0;
+
+    for _ in 0..options.samples * ATTEMPT_FACTOR {
+        if agreed == options.samples {
+            break;
+        }
+
+        let assignment: BTreeMap<_, _> = vars
+            .iter()
+            .map(|v| (v.clone(), rng.range(-3.0, 3.0)))
+            .collect();
+
+        let (Some(l), Some(r)) = (evaluate(left, &assignment), evaluate(right, &assignment)) else {
+            continue;
+        };
+
+        if !(l.is_finite() && r.is_finite() && l.abs() < MAX_MAGNITUDE && r.abs() < MAX_MAGNITUDE) {
+            continue;
+        }
+
+        if approx_eq(l, r, options.tolerance) {
+            agreed += 1;
+        } else {
+            return NumericVerdict::Counterexample {
+                assignment,
+                left: l,
+                right: r,
+            };
+        }
+    }
+
+    if agreed == 0 {
+        NumericVerdict::Inconclusive
+    } else {
+        NumericVerdict::Plausible(agreed)
+    }
+}
+
+#[test]
+fn test_numeric_check() {
+    use crate::text::*;
+
+    let verdict = |l, r| {
+        numeric_check(
+            &Lisp::parse(l).unwrap(),
+            &Lisp::parse(r).unwrap(),
+            NumericOptions::default(),
+        )
+    };
+
+    assert_eq!(
+        verdict("(+ (* (sin x) (sin x)) (* (cos x) (cos x)))", "1"),
+        NumericVerdict::Plausible(NumericOptions::default().samples)
+    );
+    assert_eq!(
+        verdict("(* (sec x) (sec x))", "(+ 1 (* (tan x) (tan x)))"),
+        NumericVerdict::Plausible(NumericOptions::default().samples)
+    );
+    assert!(matches!(
+        verdict("(sin x)", "(cos x)"),
+        NumericVerdict::Counterexample { .. }
+    ));
+    assert_eq!(verdict("(f x)", "(f x)"), NumericVerdict::Inconclusive);
+}
@@ -0,0 +1,617 @@
+use {
+    crate::{rewrite::RewriteLimits, solve::*},
+    std::{
+        collections::BTreeMap,
+        time::{Duration, Instant},
+    },
+};
+
+// one entry point for proving identities with any of the search backends, checking them
+// numerically first
+
+// the graph engine lists proofs up to this many steps longer than the shortest one
+const PROOF_SLACK: usize = 2;
+// and at most this many of them, since there can be exponentially many
+const MAX_PROOFS: usize = 100;
+
+/// Search backend used by [`IdentityProver`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Engine {
+    /// Rewrites whole expressions with each ruleset and keeps every intermediate form.
+    #[default]
+    Graph,
+    /// Equality saturation over equivalence classes of subexpressions.
+    Egraph,
+    /// Best-first search from both sides at once, within a [`SearchBudget`].
+    BestFirst,
+}
+
+/// Cost function ordering the best-first search.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HeuristicKind {
+    /// Number of nodes in the expression, see [`expression_size`].
+    #[default]
+    Size,
+    /// Weighs functions and nesting more than plain arithmetic, see [`expression_complexity`].
+    Complexity,
+}
+
+/// One rewrite in a [`Proof`], labelled with the ruleset that produced it.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProofStep {
+    pub ruleset: String,
+    pub after: Expression,
+}
+
+/// A chain of rewrites from one side of an identity to the other.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Proof {
+    pub start: Expression,
+    pub steps: Vec<ProofStep>,
+}
+
+/// Why no proof was found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Unproved {
+    /// Everything reachable with the rulesets was tried.
+    NoProof,
+    /// The search was cut short, a proof might still exist.
+    BudgetExhausted(BudgetKind),
+}
+
+/// The answer to whether two expressions are equal.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Verdict {
+    /// Rewrites from the left side to the right side.
+    Proved(Proof),
+    /// The engine showed both sides equal but couldn't reconstruct the steps.
+    Equivalent,
+    /// The sides differ numerically at this assignment, so no search was run.
+    Refuted {
+        assignment: BTreeMap<Variable, f64>,
+        left: f64,
+        right: f64,
+    },
+    /// No proof was found. `plausible` is how many random points both sides agreed at, if
+    /// enough could be evaluated.
+    Unproved {
+        reason: Unproved,
+        plausible: Option<usize>,
+    },
+}
+
+/// What the engine did while searching.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EngineStats {
+    /// The identity was refuted before searching, or both sides were already the same.
+    NotRun,
+    Graph {
+        /// The search graph in GraphViz format.
+        dot: String,
+        /// Every expression in the search graph.
+        nodes: Vec<Expression>,
+        /// Rewrites between `nodes`, by index, with the ruleset used.
+        edges: Vec<(usize, usize, String)>,
+        /// Proofs in the search graph, shortest first. Besides the shortest, only the ones
+        /// found in time are listed, up to 100 and at most 2 steps longer than it.
+        proofs: Vec<Proof>,
+        /// Expressions a ruleset diverged on, which have no edge for it.
+        diverged: Vec<(Expression, String, Divergence)>,
+    },
+    Egraph {
+        nodes: usize,
+        classes: usize,
+        iterations: usize,
+        /// Matches dropped for the caps on expansions and arity.
+        truncated: usize,
+        stop_reason: StopReason,
+    },
+    BestFirst {
+        expanded: usize,
+        visited: usize,
+        elapsed: Duration,
+        /// Expressions a ruleset diverged on, which were left without its successors.
+        diverged: Vec<(Expression, String, Divergence)>,
+    },
+}
+
+/// The result of [`IdentityProver::prove`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProofReport {
+    pub verdict: Verdict,
+    pub stats: EngineStats,
+}
+
+/// Proves identities with a set of rulesets, configured builder style.
+///
+/// ```
+/// use identity::{solve::*, text::*};
+///
+/// let rulesets = RulesetLoader::new().load().unwrap().rules;
+/// let prover = IdentityProver::new(rulesets).engine(Engine::BestFirst);
+///
+/// let left = Lisp::parse("(+ (^ (sin x) 2) (^ (cos x) 2))").unwrap();
+/// let report = prover.prove(&left, &Lisp::parse("1").unwrap());
+///
+/// assert!(matches!(report.verdict, Verdict::Proved(_)));
+/// ```
+#[derive(Clone, Debug)]
+pub struct IdentityProver {
+    rulesets: BTreeMap<String, Vec<Rule>>,
+    engine: Engine,
+    heuristic: HeuristicKind,
+    budget: SearchBudget,
+    step: StepMode,
+    rewrite_limits: RewriteLimits,
+    limits: EGraphLimits,
+    numeric: Option<NumericOptions>,
+}
+
+impl Proof {
+    /// The expression the proof ends at.
+    pub fn end(&self) -> &Expression {
+        self.steps.last().map_or(&self.start, |step| &step.after)
+    }
+}
+
+impl IdentityProver {
+    /// A prover using the graph engine, checking identities numerically first.
+    pub fn new(rulesets: BTreeMap<String, Vec<Rule>>) -> Self {
+        Self {
+            rulesets,
+            engine: Engine::default(),
+            heuristic: HeuristicKind::default(),
+            budget: SearchBudget::default(),
+            step: StepMode::default(),
+            rewrite_limits: RewriteLimits::default(),
+            limits: EGraphLimits::default(),
+            numeric: Some(NumericOptions::default()),
+        }
+    }
+
+    pub fn engine(mut self, engine: Engine) -> Self {
+        self.engine = engine;
+        self
+    }
+
+    /// Only used by [`Engine::BestFirst`].
+    pub fn heuristic(mut self, heuristic: HeuristicKind) -> Self {
+        self.heuristic = heuristic;
+        self
+    }
+
+    /// The time limit applies to every engine, the limits on nodes and depth are only used
+    /// by [`Engine::BestFirst`].
+    pub fn budget(mut self, budget: SearchBudget) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Only used by [`Engine::BestFirst`].
+    pub fn step_mode(mut self, step: StepMode) -> Self {
+        self.step = step;
+        self
+    }
+
+    /// Limits on rewriting with a ruleset until nothing changes, which is how
+    /// [`Engine::Graph`] and [`Engine::BestFirst`] take a step. Steps that go over them, or
+    /// go around in a cycle, are left out of the search.
+    pub fn rewrite_limits(mut self, limits: RewriteLimits) -> Self {
+        self.rewrite_limits = limits;
+        self
+    }
+
+    /// Only used by [`Engine::Egraph`].
+    pub fn egraph_limits(mut self, limits: EGraphLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Options for the numeric check run before searching, `None` skips it.
+    pub fn numeric(mut self, options: Option<NumericOptions>) -> Self {
+        self.numeric = options;
+        self
+    }
+
+    pub fn rulesets(&self) -> &BTreeMap<String, Vec<Rule>> {
+        &self.rulesets
+    }
+
+    /// Tries to prove that `left` and `right` are equal. Sides that are already the same are
+    /// proved with no steps, without searching.
+    pub fn prove(&self, left: &Expression, right: &Expression) -> ProofReport {
+        if left == right {
+            return ProofReport {
+                verdict: Verdict::Proved(Proof {
+                    start: left.clone(),
+                    steps: Vec::new(),
+                }),
+                stats: EngineStats::NotRun,
+            };
+        }
+
+        let numeric = self
+            .numeric
+            .map(|options| numeric_check(left, right, options));
+
+        if let Some(NumericVerdict::Counterexample {
+            assignment,
+            left,
+            right,
+        }) = numeric
+        {
+            return ProofReport {
+                verdict: Verdict::Refuted {
+                    assignment,
+                    left,
+                    right,
+                },
+                stats: EngineStats::NotRun,
+            };
+        }
+
+        let (outcome, stats) = match self.engine {
+            Engine::Graph => self.prove_graph(left, right),
+            Engine::Egraph => self.prove_egraph(left, right),
+            Engine::BestFirst => self.prove_best_first(left, right),
+        };
+
+        let verdict = match outcome {
+            Ok(Some(proof)) => Verdict::Proved(proof),
+            Ok(None) => Verdict::Equivalent,
+            Err(reason) => Verdict::Unproved {
+                reason,
+                plausible: match numeric {
+                    Some(NumericVerdict::Plausible(samples)) => Some(samples),
+                    _ => None,
+                },
+            },
+        };
+
+        ProofReport { verdict, stats }
+    }
+
+    fn prove_graph(&self, left: &Expression, right: &Expression) -> (Outcome, EngineStats) {
+        use petgraph::{
+            algo::{all_simple_paths, astar},
+            dot::Dot,
+        };
+
+        let start = Instant::now();
+        let arena = elsa::FrozenIndexSet::new();
+        let (graph, diverged) = auto_identity(
+            left.clone(),
+            right.clone(),
+            &self.rulesets,
+            &arena,
+            self.rewrite_limits,
+            self.budget.max_time,
+        );
+
+        let timed_out = || self.budget.max_time.is_some_and(|t| start.elapsed() >= t);
+
+        // the shortest proof comes first, the others only while there's time for them
+        let mut paths = Vec::new();
+        if let Some((_, shortest)) = astar(&graph, left, |n| n == right, |_| 1, |_| 0) {
+            paths.push(shortest.clone());
+            paths.extend(
+                all_simple_paths::<Vec<_>, _>(
+                    &graph,
+                    left,
+                    right,
+                    0,
+                    Some(shortest.len() - 2 + PROOF_SLACK),
+                )
+                .filter(|path| *path != shortest)
+                .take_while(|_| !timed_out())
+                .take(MAX_PROOFS - 1),
+            );
+        }
+        paths.sort_by_key(|v| v.len());
+
+        let proofs: Vec<_> = paths
+            .iter()
+            .map(|path| Proof {
+                start: left.clone(),
+                steps: path
+                    .windows(2)
+                    .map(|pair| {
+                        let [before, after] = pair else {
+                            unreachable!()
+                        };
+                        ProofStep {
+                            ruleset: graph.edge_weight(before, after).unwrap().clone(),
+                            after: (*after).clone(),
+                        }
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        let nodes: Vec<_> = graph.nodes().collect();
+        let index: BTreeMap<_, _> = nodes.iter().enumerate().map(|(i, n)| (*n, i)).collect();
+        let edges = graph
+            .all_edges()
+            .map(|(from, to, ruleset)| (index[from], index[to], ruleset.clone()))
+            .collect();
+
+        // without a proof, a graph left unfinished shows nothing
+        let reason = if timed_out() {
+            Unproved::BudgetExhausted(BudgetKind::Time)
+        } else if !diverged.is_empty() {
+            Unproved::BudgetExhausted(BudgetKind::Rewrites)
+        } else {
+            Unproved::NoProof
+        };
+        let outcome = proofs.first().cloned().map(Some).ok_or(reason);
+        let stats = EngineStats::Graph {
+            dot: Dot::new(&graph).to_string(),
+            nodes: nodes.into_iter().cloned().collect(),
+            edges,
+            proofs,
+            diverged,
+        };
+
+        (outcome, stats)
+    }
+
+    fn prove_egraph(&self, left: &Expression, right: &Expression) -> (Outcome, EngineStats) {
+        let limits = EGraphLimits {
+            max_time: self.budget.max_time.or(self.limits.max_time),
+            ..self.limits
+        };
+        let report = egraph_identity(left.clone(), right.clone(), &self.rulesets, limits);
+
+        let outcome = match (report.steps, report.stop_reason) {
+            (Some(steps), _) => Ok(Some(proof(left, steps))),
+            (None, StopReason::Equivalent) => Ok(None),
+            (None, StopReason::Saturated) => Err(Unproved::NoProof),
+            (None, StopReason::IterationLimit) => {
+                Err(Unproved::BudgetExhausted(BudgetKind::Iterations))
+            }
+            (None, StopReason::NodeLimit) => Err(Unproved::BudgetExhausted(BudgetKind::Nodes)),
+            (None, StopReason::TimeLimit) => Err(Unproved::BudgetExhausted(BudgetKind::Time)),
+            (None, StopReason::Truncated) => Err(Unproved::BudgetExhausted(BudgetKind::Matches)),
+        };
+        let stats = EngineStats::Egraph {
+            nodes: report.nodes,
+            classes: report.classes,
+            iterations: report.iterations,
+            truncated: report.truncated,
+            stop_reason: report.stop_reason,
+        };
+
+        (outcome, stats)
+    }
+
+    fn prove_best_first(&self, left: &Expression, right: &Expression) -> (Outcome, EngineStats) {
+        let (left, right) = (left.clone(), right.clone());
+        let start = left.clone();
+
+        let report = match self.heuristic {
+            HeuristicKind::Size => bidirectional_search(
+                left,
+                right,
+                &self.rulesets,
+                &expression_size,
+                self.budget,
+                self.step,
+                self.rewrite_limits,
+            ),
+            HeuristicKind::Complexity => bidirectional_search(
+                left,
+                right,
+                &self.rulesets,
+                &expression_complexity,
+                self.budget,
+                self.step,
+                self.rewrite_limits,
+            ),
+        };
+
+        let outcome = match report.outcome {
+            SearchOutcome::Proved(steps) => Ok(Some(proof(&start, steps))),
+            SearchOutcome::BudgetExhausted(kind) => Err(Unproved::BudgetExhausted(kind)),
+            SearchOutcome::NoProof => Err(Unproved::NoProof),
+        };
+        let stats = EngineStats::BestFirst {
+            expanded: report.expanded,
+            visited: report.visited,
+            elapsed: report.elapsed,
+            diverged: report.diverged,
+        };
+
+        (outcome, stats)
+    }
+}
+
+// a proof, None if the engine couldn't reconstruct one, or why there isn't one
+type Outcome = Result<Option<Proof>, Unproved>;
+
+fn proof(start: &Expression, steps: Vec<(String, Expression)>) -> Proof {
+    Proof {
+        start: start.clone(),
+        steps: steps
+            .into_iter()
+            .map(|(ruleset, after)| ProofStep { ruleset, after })
+            .collect(),
+    }
+}
+
+#[test]
+fn test_prover() {
+    use crate::text::*;
+
+    let rulesets = RulesetLoader::new().load().unwrap().rules;
+    let left = Lisp::parse("(+ (^ (sin x) 2) (^ (cos x) 2))").unwrap();
+    let one = Lisp::parse("1").unwrap();
+
+    for engine in [Engine::Graph, Engine::Egraph, Engine::BestFirst] {
+        let report = IdentityProver::new(rulesets.clone())
+            .engine(engine)
+            .prove(&left, &one);
+
+        let Verdict::Proved(proof) = report.verdict else {
+            panic!("{engine:?}: {:?}", report.verdict)
+        };
+        assert_eq!(proof.start, left);
+        assert_eq!(proof.end(), &one);
+    }
+
+    // the graph engine has no path from an expression to itself to find
+    for engine in [Engine::Graph, Engine::Egraph, Engine::BestFirst] {
+        let report = IdentityProver::new(rulesets.clone())
+            .engine(engine)
+            .prove(&left, &left);
+
+        assert_eq!(
+            report.verdict,
+            Verdict::Proved(Proof {
+                start: left.clone(),
+                steps: Vec::new()
+            })
+        );
+    }
+
+    let report = IdentityProver::new(rulesets.clone()).prove(&left, &Lisp::parse("2").unwrap());
+    assert!(matches!(report.verdict, Verdict::Refuted { .. }));
+    assert_eq!(report.stats, EngineStats::NotRun);
+
+    // without the numeric check a false identity is searched for, and runs out of rewrites
+    let report = IdentityProver::new(rulesets)
+        .engine(Engine::BestFirst)
+        .budget(SearchBudget {
+            max_nodes: Some(50),
+            ..Default::default()
+        })
+        .numeric(None)
+        .prove(&left, &Lisp::parse("2").unwrap());
+    assert_eq!(
+        report.verdict,
+        Verdict::Unproved {
+            reason: Unproved::NoProof,
+            plausible: None
+        }
+    );
+}
+
+#[test]
+fn test_prover_timeout() {
+    use crate::text::*;
+
+    let rulesets = RulesetLoader::new().load().unwrap().rules;
+    let left = Lisp::parse("(+ (^ (sin x) 2) (^ (cos x) 2))").unwrap();
+    let one = Lisp::parse("1").unwrap();
+
+    // every engine gives up once out of time, and says that's why
+    for engine in [Engine::Graph, Engine::Egraph, Engine::BestFirst] {
+        let report = IdentityProver::new(rulesets.clone())
+            .engine(engine)
+            .budget(SearchBudget {
+                max_time: Some(Duration::ZERO),
+                ..Default::default()
+            })
+            .numeric(None)
+            .prove(&left, &one);
+
+        assert_eq!(
+            report.verdict,
+            Verdict::Unproved {
+                reason: Unproved::BudgetExhausted(BudgetKind::Time),
+                plausible: None
+            },
+            "{engine:?}"
+        );
+    }
+}
+
+#[test]
+fn test_prover_without_simplify() {
+    use crate::text::*;
+
+    let rulesets = BTreeMap::from([(
+        "shift".to_string(),
+        Lisp::parse_ruleset("(=> (sin (+ a 1)) (cos a))").unwrap(),
+    )]);
+    let left = Lisp::parse("(sin (+ x 1))").unwrap();
+    let right = Lisp::parse("(cos x)").unwrap();
+
+    // rulesets loaded without a simplify ruleset are applied on their own
+    for (engine, step) in [
+        (Engine::Graph, StepMode::Fixpoint),
+        (Engine::Egraph, StepMode::Fixpoint),
+        (Engine::BestFirst, StepMode::Fixpoint),
+        (Engine::BestFirst, StepMode::OneStep),
+    ] {
+        let report = IdentityProver::new(rulesets.clone())
+            .engine(engine)
+            .step_mode(step)
+            .numeric(None)
+            .prove(&left, &right);
+
+        assert!(
+            matches!(report.verdict, Verdict::Proved(_)),
+            "{engine:?} {step:?}: {:?}",
+            report.verdict
+        );
+    }
+}
+
+#[test]
+fn test_prover_diverged() {
+    use crate::text::*;
+
+    let rulesets = BTreeMap::from([(
+        "grow".to_string(),
+        Lisp::parse_ruleset("(=> (sin a) (sin (+ a a)))").unwrap(),
+    )]);
+    let prover = IdentityProver::new(rulesets)
+        .numeric(None)
+        .rewrite_limits(RewriteLimits {
+            max_rewrites: Some(10),
+            max_term_size: None,
+        });
+    let (left, right) = (
+        Lisp::parse("(sin x)").unwrap(),
+        Lisp::parse("(cos x)").unwrap(),
+    );
+    let report = prover.prove(&left, &right);
+
+    // a ruleset left out of the graph means the search wasn't exhaustive
+    assert_eq!(
+        report.verdict,
+        Verdict::Unproved {
+            reason: Unproved::BudgetExhausted(BudgetKind::Rewrites),
+            plausible: None
+        }
+    );
+    let EngineStats::Graph { diverged, .. } = report.stats else {
+        panic!("{:?}", report.stats)
+    };
+    let [(expr, ruleset, divergence)] = &diverged[..] else {
+        panic!("{diverged:?}")
+    };
+    assert_eq!(expr, &Lisp::parse("(sin x)").unwrap());
+    assert_eq!(ruleset, "grow");
+    assert_eq!(divergence.kind, DivergenceKind::RewriteLimit);
+
+    // and so is a best-first search that had to leave out the same successor
+    let report = prover.engine(Engine::BestFirst).prove(&left, &right);
+    assert_eq!(
+        report.verdict,
+        Verdict::Unproved {
+            reason: Unproved::BudgetExhausted(BudgetKind::Rewrites),
+            plausible: None
+        }
+    );
+    let EngineStats::BestFirst { diverged, .. } = report.stats else {
+        panic!("{:?}", report.stats)
+    };
+    let [(expr, ruleset, _)] = &diverged[..] else {
+        panic!("{diverged:?}")
+    };
+    assert_eq!(expr, &left);
+    assert_eq!(ruleset, "grow");
+}
@@ -0,0 +1,407 @@
+use {
+    crate::{
+        expression::*,
+        rewrite::*,
+        solve::{ruleset_combiner, SIMPLIFY_NAME},
+    },
+    std::{
+        cmp::Reverse,
+        collections::{BTreeMap, BinaryHeap, HashMap},
+        time::{Duration, Instant},
+    },
+};
+
+// best-first search from both sides of an identity at once, proofs are found where the
+// two frontiers meet
+
+pub trait Heuristic {
+    fn cost(&self, expr: &Expression) -> usize;
+}
+
+impl<F: Fn(&Expression) -> usize> Heuristic for F {
+    fn cost(&self, expr: &Expression) -> usize {
+        self(expr)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SearchBudget {
+    pub max_nodes: Option<usize>,
+    pub max_depth: Option<usize>,
+    pub max_time: Option<Duration>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BudgetKind {
+    Nodes,
+    Depth,
+    Time,
+    // rounds of the e-graph engine
+    Iterations,
+    // matches the e-graph engine dropped for its caps on expansions and arity
+    Matches,
+    // rewrites the graph engine left out, for rulesets that diverged
+    Rewrites,
+}
+
+// how a ruleset takes an expression to its successors
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StepMode {
+    // apply the ruleset with simplify everywhere until nothing changes, one successor each
+    #[default]
+    Fixpoint,
+    // every single application of one of its rules, with any bindings, to the simplified
+    // expression, then simplify
+    OneStep,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SearchOutcome {
+    // steps from the left side to the right side, labelled with the ruleset that was used
+    Proved(Vec<(String, Expression)>),
+    // the search was cut short, a proof might still exist
+    BudgetExhausted(BudgetKind),
+    // every expression reachable from either side was visited
+    NoProof,
+}
+
+#[derive(Debug)]
+pub struct SearchReport {
+    pub outcome: SearchOutcome,
+    pub expanded: usize,
+    pub visited: usize,
+    pub elapsed: Duration,
+    // expressions a ruleset diverged on, which were left without its successors
+    pub diverged: Vec<(Expression, String, Divergence)>,
+}
+
+// the expressions a ruleset takes one expression to, with the ways rewriting diverged on the way
+type Successor<'a> = Box<dyn Fn(&Expression, &mut Vec<Divergence>) -> Vec<Expression> + 'a>;
+
+#[derive(Default)]
+struct Frontier {
+    queue: BinaryHeap<Reverse<(usize, usize, Expression)>>,
+    // expression -> (predecessor and the ruleset that connects them, depth)
+    visited: HashMap<Expression, (Option<(Expression, String)>, usize)>,
+}
+
+// like expression_size, but named functions and inverses weigh more than arithmetic,
+// and deep nesting is penalized
+pub fn expression_complexity(expr: &Expression) -> usize {
+    fn rec(expr: &Expression, depth: usize) -> usize {
+        depth
+            + match expr {
+                Expression::Concrete(Concrete::Variadic(Variadic { terms, .. })) => {
+                    1 + terms.iter().map(|t| rec(t, depth + 1)).sum::<usize>()
+                }
+                Expression::Concrete(Concrete::Unary(Unary { argument, kind })) => {
+                    let weight = match kind {
+                        UnaryKind::Negation => 1,
+                        UnaryKind::Reciprocal => 2,
+                        UnaryKind::Named { .. } | UnaryKind::Variable { .. } => 3,
+                    };
+                    weight + rec(argument, depth + 1)
+                }
+                Expression::Concrete(Concrete::Power(Power { base, exponent })) => {
+                    2 + rec(base, depth + 1) + rec(exponent, depth + 1)
+                }
+                _ => 1,
+            }
+    }
+
+    rec(expr, 0)
+}
+
+pub fn bidirectional_search(
+    left: Expression,
+    right: Expression,
+    rulesets: &BTreeMap<String, Vec<Rule>>,
+    heuristic: &impl Heuristic,
+    budget: SearchBudget,
+    step: StepMode,
+    limits: RewriteLimits,
+) -> SearchReport {
+    let start = Instant::now();
+    let empty = Vec::new();
+    let always_apply = rulesets.get(SIMPLIFY_NAME).unwrap_or(&empty);
+    let simplify = ruleset_combiner(&[always_apply], limits);
+    let successors: Vec<_> = rulesets
+        .iter()
+        .map(|(name, ruleset)| {
+            let successor: Successor = match step {
+                StepMode::Fixpoint => {
+                    let combined = ruleset_combiner(&[ruleset, always_apply], limits);
+                    // a ruleset that diverges has no successor rather than a made up one
+                    Box::new(move |expr, diverged| match combined(expr.clone()) {
+                        Ok(expr) => vec![expr],
+                        Err(divergence) => {
+                            diverged.push(divergence);
+                            Vec::new()
+                        }
+                    })
+                }
+                StepMode::OneStep => Box::new(|expr, diverged| {
+                    // rules are stored with their powers expanded, so only match simplified forms
+                    let simplified = match simplify(expr.clone()) {
+                        Ok(simplified) => simplified,
+                        Err(divergence) => {
+                            diverged.push(divergence);
+                            return Vec::new();
+                        }
+                    };
+                    one_step_successors(&simplified, ruleset)
+                        .into_iter()
+                        .filter_map(|expr| {
+                            simplify(expr)
+                                .map_err(|divergence| diverged.push(divergence))
+                                .ok()
+                        })
+                        .collect()
+                }),
+            };
+            (name, successor)
+        })
+        .collect();
+
+    let mut frontiers = [Frontier::default(), Frontier::default()];
+    let mut counter = 0;
+    let mut expanded = 0;
+    let mut depth_pruned = false;
+    let mut diverged = Vec::new();
+
+    for (frontier, expr) in frontiers.iter_mut().zip([left.clone(), right]) {
+        frontier
+            .queue
+            .push(Reverse((heuristic.cost(&expr), counter, expr.clone())));
+        frontier.visited.insert(expr, (None, 0));
+        counter += 1;
+    }
+
+    let report = |outcome, frontiers: &[Frontier; 2], expanded, diverged| SearchReport {
+        outcome,
+        expanded,
+        visited: frontiers.iter().map(|f| f.visited.len()).sum(),
+        elapsed: start.elapsed(),
+        diverged,
+    };
+
+    if let Some(steps) = meeting_path(&frontiers, &left) {
+        return report(SearchOutcome::Proved(steps), &frontiers, expanded, diverged);
+    }
+
+    loop {
+        // expand whichever side has the cheaper expression waiting
+        let side = match frontiers.each_ref().map(|f| f.queue.peek()) {
+            [None, None] => {
+                // successors left out for diverging mean not everything was visited
+                let outcome = if depth_pruned {
+                    SearchOutcome::BudgetExhausted(BudgetKind::Depth)
+                } else if !diverged.is_empty() {
+                    SearchOutcome::BudgetExhausted(BudgetKind::Rewrites)
+                } else {
+                    SearchOutcome::NoProof
+                };
+                return report(outcome, &frontiers, expanded, diverged);
+            }
+            [Some(_), None] => 0,
+            [None, Some(_)] => 1,
+            [Some(a), Some(b)] => usize::from(b < a),
+        };
+
+        if budget.max_time.is_some_and(|t| start.elapsed() >= t) {
+            return report(
+                SearchOutcome::BudgetExhausted(BudgetKind::Time),
+                &frontiers,
+                expanded,
+                diverged,
+            );
+        }
+
+        if budget.max_nodes.is_some_and(|n| expanded >= n) {
+            return report(
+                SearchOutcome::BudgetExhausted(BudgetKind::Nodes),
+                &frontiers,
+                expanded,
+                diverged,
+            );
+        }
+
+        let Reverse((_, _, expr)) = frontiers[side].queue.pop().unwrap();
+        let depth = frontiers[side].visited[&expr].1;
+        expanded += 1;
+
+        if budget.max_depth.is_some_and(|d| depth >= d) {
+            depth_pruned = true;
+            continue;
+        }
+
+        let mut found = Vec::new();
+        for (name, successor) in &successors {
+            let mut divergences = Vec::new();
+            found.extend(
+                successor(&expr, &mut divergences)
+                    .into_iter()
+                    .map(|e| (name, e)),
+            );
+            diverged.extend(
+                divergences
+                    .into_iter()
+                    .map(|divergence| (expr.clone(), name.to_string(), divergence)),
+            );
+        }
+
+        for (name, expr_new) in found {
+            if expr_new == expr || frontiers[side].visited.contains_key(&expr_new) {
+                continue;
+            }
+
+            frontiers[side].visited.insert(
+                expr_new.clone(),
+                (Some((expr.clone(), name.to_string())), depth + 1),
+            );
+
+            if let Some(steps) = meeting_path(&frontiers, &expr_new) {
+                return report(SearchOutcome::Proved(steps), &frontiers, expanded, diverged);
+            }
+
+            frontiers[side]
+                .queue
+                .push(Reverse((heuristic.cost(&expr_new), counter, expr_new)));
+            counter += 1;
+        }
+    }
+}
+
+// if both sides reached the expression, the path from the left side through it to the right side
+fn meeting_path(frontiers: &[Frontier; 2], meet: &Expression) -> Option<Vec<(String, Expression)>> {
+    let [forward, backward] = frontiers;
+
+    if !(forward.visited.contains_key(meet) && backward.visited.contains_key(meet)) {
+        return None;
+    }
+
+    let mut steps = Vec::new();
+    let mut current = meet;
+
+    while let (Some((previous, name)), _) = &forward.visited[current] {
+        steps.push((name.clone(), current.clone()));
+        current = previous;
+    }
+
+    steps.reverse();
+    current = meet;
+
+    while let (Some((previous, name)), _) = &backward.visited[current] {
+        steps.push((name.clone(), previous.clone()));
+        current = previous;
+    }
+
+    Some(steps)
+}
+
+#[test]
+fn test_bidirectional_search() {
+    use crate::{solve::load_rulesets, text::*};
+
+    let rulesets = BTreeMap::from_iter(load_rulesets());
+    let left = Lisp::parse("(* (csc x) (tan x))").unwrap();
+    let right = Lisp::parse("(sec x)").unwrap();
+
+    let report = bidirectional_search(
+        left,
+        right.clone(),
+        &rulesets,
+        &expression_size,
+        SearchBudget::default(),
+        StepMode::Fixpoint,
+        RewriteLimits::default(),
+    );
+
+    println!("{report:?}");
+
+    let SearchOutcome::Proved(steps) = report.outcome else {
+        panic!("no proof found")
+    };
+    assert_eq!(steps.last().unwrap().1, right);
+}
+
+#[test]
+fn test_search_budget() {
+    use crate::{solve::load_rulesets, text::*};
+
+    let rulesets = BTreeMap::from_iter(load_rulesets());
+    let left = Lisp::parse("(* (csc x) (tan x))").unwrap();
+    let right = Lisp::parse("(sec x)").unwrap();
+
+    let report = bidirectional_search(
+        left.clone(),
+        right.clone(),
+        &rulesets,
+        &expression_complexity,
+        SearchBudget {
+            max_nodes: Some(0),
+            ..Default::default()
+        },
+        StepMode::Fixpoint,
+        RewriteLimits::default(),
+    );
+
+    assert_eq!(
+        report.outcome,
+        SearchOutcome::BudgetExhausted(BudgetKind::Nodes)
+    );
+
+    let report = bidirectional_search(
+        left,
+        Lisp::parse("(cos x)").unwrap(),
+        &rulesets,
+        &expression_complexity,
+        SearchBudget {
+            max_depth: Some(1),
+            ..Default::default()
+        },
+        StepMode::Fixpoint,
+        RewriteLimits::default(),
+    );
+
+    assert_eq!(
+        report.outcome,
+        SearchOutcome::BudgetExhausted(BudgetKind::Depth)
+    );
+
+    let report = bidirectional_search(
+        Lisp::parse("x").unwrap(),
+        Lisp::parse("y").unwrap(),
+        &rulesets,
+        &expression_size,
+        SearchBudget::default(),
+        StepMode::Fixpoint,
+        RewriteLimits::default(),
+    );
+
+    assert_eq!(report.outcome, SearchOutcome::NoProof);
+}
+
+#[test]
+fn test_one_step_search() {
+    use crate::{solve::load_rulesets, text::*};
+
+    let rulesets = BTreeMap::from_iter(load_rulesets());
+    let left = Lisp::parse("(* (csc x) (tan x))").unwrap();
+    let right = Lisp::parse("(sec x)").unwrap();
+
+    let report = bidirectional_search(
+        left,
+        right.clone(),
+        &rulesets,
+        &expression_size,
+        SearchBudget::default(),
+        StepMode::OneStep,
+        RewriteLimits::default(),
+    );
+
+    let SearchOutcome::Proved(steps) = report.outcome else {
+        panic!("no proof found: {report:?}")
+    };
+    assert_eq!(steps.last().unwrap().1, right);
+}
@@ -0,0 +1,227 @@
+use {
+    crate::{expression::*, rewrite::*, solve::*},
+    std::collections::BTreeMap,
+};
+
+// checks that both sides of a rule agree numerically for random instantiations of its
+// pattern variables, which catches typos and macros that change a rule's meaning
+
+#[derive(Clone, Copy, Debug)]
+pub struct SoundnessOptions {
+    // how many different instantiations of the pattern variables are tried
+    pub instantiations: usize,
+    pub numeric: NumericOptions,
+}
+
+#[derive(Debug)]
+pub struct Witness {
+    // what each pattern variable was replaced with
+    pub bind: Bindings,
+    // value of each variable in the instantiated rule
+    pub assignment: BTreeMap<Variable, f64>,
+    pub left: f64,
+    pub right: f64,
+}
+
+const FRESH_PREFIX: &str = "_";
+const FRESH_VARIABLES: usize = 3;
+const MAX_REST_TERMS: usize = 3;
+// functions with a numeric meaning that function variables are replaced with
+pub const FUNCTIONS: [&str; 6] = ["sin", "cos", "tan", "sec", "csc", "cot"];
+
+impl Default for SoundnessOptions {
+    fn default() -> Self {
+        Self {
+            instantiations: 32,
+            numeric: NumericOptions {
+                samples: 4,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+pub fn check_rule(rule: &Rule, options: SoundnessOptions) -> Option<Witness> {
+    let mut rng = Rng::new(options.numeric.seed);
+    let mut vars = variables(&rule.pattern);
+    // typed occurrences of a variable come last so that their bindings are the ones kept
+    vars.sort_by_key(|v| v.kind);
+
+    for i in 0..options.instantiations {
+        let terms = vars
+            .iter()
+            .map(|v| {
+                let term = match (v.kind, rest_kind(&rule.pattern, v)) {
+                    // rest variables stand for any number of terms, including none
+                    (VarKind::Any, Some(kind)) if rng.below(2) == 0 => Variadic::new(
+                        (0..rng.below(MAX_REST_TERMS + 1)).map(|_| random_term(&mut rng, 2)),
+                        kind,
+                    )
+                    .into(),
+                    (kind, _) => random_typed_term(&mut rng, kind),
+                };
+                (v.untyped(), term)
+            })
+            .collect();
+        let bind = Bindings {
+            terms,
+            functions: function_variables(&rule.pattern)
+                .into_iter()
+                .map(|f| (f, FuncId::new(FUNCTIONS[rng.below(FUNCTIONS.len())])))
+                .collect(),
+        };
+
+        if !rule.guard.as_ref().is_none_or(|g| g.holds(&bind)) {
+            continue;
+        }
+
+        let (Ok(left), Ok(right)) = (
+            replace_variable(rule.pattern.clone(), &bind),
+            replace_variable(rule.replacement.clone(), &bind),
+        ) else {
+            continue;
+        };
+
+        let numeric = NumericOptions {
+            seed: options.numeric.seed.wrapping_add(i as u64),
+            ..options.numeric
+        };
+
+        if let NumericVerdict::Counterexample {
+            assignment,
+            left,
+            right,
+        } = numeric_check(&left, &right, numeric)
+        {
+            return Some(Witness {
+                bind,
+                assignment,
+                left,
+                right,
+            });
+        }
+    }
+
+    None
+}
+
+// every rule in the rulesets that failed the check, with its index in the ruleset
+pub fn check_rulesets(
+    rulesets: &BTreeMap<String, Vec<Rule>>,
+    options: SoundnessOptions,
+) -> Vec<(&str, usize, &Rule, Witness)> {
+    rulesets
+        .iter()
+        .flat_map(|(name, rules)| {
+            rules.iter().enumerate().filter_map(move |(i, rule)| {
+                check_rule(rule, options).map(|w| (name.as_str(), i, rule, w))
+            })
+        })
+        .collect()
+}
+
+// the kind of variadic the variable is a direct term of, if any
+fn rest_kind(patt: &Expression, var: &Variable) -> Option<VariadicKind> {
+    match patt {
+        Expression::Concrete(Concrete::Variadic(Variadic { terms, kind })) => {
+            if terms
+                .iter()
+                .any(|t| matches!(t, Expression::Variable(v) if v == var))
+            {
+                Some(*kind)
+            } else {
+                terms.iter().find_map(|t| rest_kind(t, var))
+            }
+        }
+        Expression::Concrete(Concrete::Unary(Unary { argument, .. })) => rest_kind(argument, var),
+        Expression::Concrete(Concrete::Power(Power { base, exponent })) => {
+            rest_kind(base, var).or_else(|| rest_kind(exponent, var))
+        }
+        _ => None,
+    }
+}
+
+// random term that a variable of the kind can bind to
+fn random_typed_term(rng: &mut Rng, kind: VarKind) -> Expression {
+    loop {
+        let term = match kind {
+            VarKind::Literal if rng.below(3) == 0 => Literal::new("PI").into(),
+            VarKind::Integer | VarKind::Literal => Literal::new(rng.below(9) as i32 - 4).into(),
+            VarKind::Function | VarKind::Variable => random_term(rng, 1),
+            VarKind::Any => random_term(rng, 2),
+        };
+
+        if kind.admits(&term) {
+            break term;
+        }
+    }
+}
+
+// small expression made of integers, fresh variables and the built-in functions
+fn random_term(rng: &mut Rng, depth: usize) -> Expression {
+    let leaf = |rng: &mut Rng| -> Expression {
+        if rng.below(2) == 0 {
+            Literal::new(rng.below(9) as i32 - 4).into()
+        } else {
+            Variable::new(format!("{FRESH_PREFIX}{}", rng.below(FRESH_VARIABLES))).into()
+        }
+    };
+
+    if depth == 0 {
+        return leaf(rng);
+    }
+
+    match rng.below(6) {
+        0 => Variadic::new(
+            [random_term(rng, depth - 1), random_term(rng, depth - 1)],
+            VariadicKind::Addition,
+        )
+        .into(),
+        1 => Variadic::new(
+            [random_term(rng, depth - 1), random_term(rng, depth - 1)],
+            VariadicKind::Multiplication,
+        )
+        .into(),
+        2 => Unary::new(random_term(rng, depth - 1), UnaryKind::Negation).into(),
+        3 => Unary::new(
+            random_term(rng, depth - 1),
+            UnaryKind::named(FUNCTIONS[rng.below(FUNCTIONS.len())]),
+        )
+        .into(),
+        _ => leaf(rng),
+    }
+}
+
+#[test]
+fn test_check_rule() {
+    use crate::text::*;
+
+    let rule = |s| Lisp::parse_ruleset(s).unwrap().pop().unwrap();
+
+    assert!(check_rule(
+        &rule("(=> (* a (+ b c)) (+ (* a b) (* a c)))"),
+        Default::default()
+    )
+    .is_none());
+    assert!(check_rule(&rule("(=> (+ a (- a) ..) ..)"), Default::default()).is_none());
+    assert!(check_rule(
+        &rule("(=> (* a (+ b c)) (+ (* a b) c))"),
+        Default::default()
+    )
+    .is_some());
+    assert!(check_rule(&rule("(=> (+ a ..) a)"), Default::default()).is_some());
+    assert!(check_rule(&rule("(=> (/ (F u)) ((recip F) u))"), Default::default()).is_none());
+    assert!(check_rule(&rule("(=> (/ (F u)) ((cofunc F) u))"), Default::default()).is_some());
+    assert!(check_rule(
+        &rule("(=> (* ?n:int (+ ?n)) (* ?n ?n))"),
+        Default::default()
+    )
+    .is_none());
+}
+
+#[test]
+fn test_loaded_rulesets_sound() {
+    let rulesets = BTreeMap::from_iter(load_rulesets());
+
+    assert!(check_rulesets(&rulesets, Default::default()).is_empty());
+}
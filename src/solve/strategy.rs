@@ -0,0 +1,450 @@
+use {
+    crate::{expression::*, rewrite::*, text::*},
+    std::collections::{BTreeMap, BTreeSet, HashMap},
+};
+
+// strategy grammar, in the same brackets as lisp:
+// strategy := name | (innermost strategy) | (outermost strategy) | (once strategy)
+//           | (repeat strategy) | (try strategy) | (fix strategy)
+//           | (seq strategy+) | (choice strategy+)
+// name := ruleset | fold | powers
+
+/// A way of applying rulesets to an expression, built from combinators.
+///
+/// Every strategy either succeeds with a new expression or fails. A ruleset on its own rewrites
+/// the whole expression once, with the first of its rules that changes it, and fails if none
+/// does. The traversals pick which subexpressions that happens to, and how often.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    /// A ruleset by name, the first of its rules that changes the expression applied to the
+    /// expression itself, or `fold` and `powers` for constant folding and power expansion of
+    /// the whole expression when there's no ruleset with that name.
+    Ruleset(String),
+    /// Applies the strategy wherever it succeeds, the deepest subexpressions first, until it
+    /// succeeds nowhere. Fails if it never succeeded.
+    Innermost(Box<Strategy>),
+    /// Like [`Strategy::Innermost`], the outermost subexpressions first.
+    Outermost(Box<Strategy>),
+    /// Applies the strategy to the first subexpression it succeeds on, outermost first.
+    Once(Box<Strategy>),
+    /// Applies the strategy until it fails. Always succeeds, even if it failed right away.
+    Repeat(Box<Strategy>),
+    /// Applies each strategy to the result of the one before, failing if any of them fails.
+    Seq(Vec<Strategy>),
+    /// The result of the first strategy that succeeds.
+    Choice(Vec<Strategy>),
+    /// Applies the strategy, leaving the expression as it is if it fails.
+    Try(Box<Strategy>),
+    /// Applies the strategy until it fails or stops changing the expression. Unlike
+    /// [`Strategy::Repeat`], this ends with strategies that always succeed, like `try`.
+    Fix(Box<Strategy>),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum StrategyParseError {
+    EmptyString,
+    IncorrectBrackets,
+    UnclosedBracket,
+    EmptyBrackets,
+    IncorrectNumArgs,
+    UnknownCombinator,
+    ExpectedStrategy,
+    TrailingInput,
+}
+
+impl std::fmt::Display for StrategyParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            StrategyParseError::EmptyString => "expected a strategy",
+            StrategyParseError::IncorrectBrackets => "unexpected closing bracket",
+            StrategyParseError::UnclosedBracket => "unclosed bracket",
+            StrategyParseError::EmptyBrackets => "empty brackets",
+            StrategyParseError::IncorrectNumArgs => "wrong number of arguments",
+            StrategyParseError::UnknownCombinator => "unknown combinator",
+            StrategyParseError::ExpectedStrategy => "expected a ruleset name or a combinator",
+            StrategyParseError::TrailingInput => "unexpected input after the strategy",
+        };
+        f.write_str(message)
+    }
+}
+
+// combinators taking a single strategy, and the ones taking any number
+type UnaryCombinator = (&'static str, fn(Box<Strategy>) -> Strategy);
+type VariadicCombinator = (&'static str, fn(Vec<Strategy>) -> Strategy);
+
+const UNARY_COMBINATORS: [UnaryCombinator; 6] = [
+    ("innermost", Strategy::Innermost),
+    ("outermost", Strategy::Outermost),
+    ("once", Strategy::Once),
+    ("repeat", Strategy::Repeat),
+    ("try", Strategy::Try),
+    ("fix", Strategy::Fix),
+];
+const VARIADIC_COMBINATORS: [VariadicCombinator; 2] =
+    [("seq", Strategy::Seq), ("choice", Strategy::Choice)];
+
+impl Strategy {
+    /// Parses the s-expression form of a strategy, like `(repeat (seq trigexpand cancel))`.
+    pub fn parse(s: &str) -> Result<Strategy, ParseError<StrategyParseError>> {
+        let mut tokens = Lisp::tokenize(s);
+        let strategy = parse_tokens(&mut tokens)?;
+
+        if tokens.pop_front().is_some() {
+            return Err(tokens.error(StrategyParseError::TrailingInput));
+        }
+
+        Ok(strategy)
+    }
+
+    /// Whether `s` starts like a combinator rather than an expression, which is how the shell
+    /// tells them apart.
+    pub fn is_combinator(s: &str) -> bool {
+        let Some(rest) = s.trim_start().strip_prefix('(') else {
+            return false;
+        };
+        let word = rest
+            .trim_start()
+            .split(|c: char| c.is_whitespace() || c == '(' || c == ')')
+            .next()
+            .unwrap_or_default();
+
+        combinator_names().any(|name| name == word)
+    }
+
+    /// The names of the rulesets the strategy uses.
+    pub fn rulesets(&self) -> BTreeSet<&str> {
+        match self {
+            Strategy::Ruleset(name) => BTreeSet::from([name.as_str()]),
+            Strategy::Innermost(s)
+            | Strategy::Outermost(s)
+            | Strategy::Once(s)
+            | Strategy::Repeat(s)
+            | Strategy::Try(s)
+            | Strategy::Fix(s) => s.rulesets(),
+            Strategy::Seq(list) | Strategy::Choice(list) => {
+                list.iter().flat_map(Strategy::rulesets).collect()
+            }
+        }
+    }
+
+    /// The first name the strategy uses that is neither in `rulesets` nor built in.
+    pub fn unknown_ruleset<'a>(
+        &'a self,
+        rulesets: &BTreeMap<String, Vec<Rule>>,
+    ) -> Option<&'a str> {
+        self.rulesets().into_iter().find(|name| {
+            !rulesets.contains_key(*name) && ![FOLD_NAME, EXPAND_POWERS_NAME].contains(name)
+        })
+    }
+
+    /// Applies the strategy to `expr`, `None` if it fails. Names that aren't rulesets or built
+    /// in always fail, see [`Strategy::unknown_ruleset`].
+    ///
+    /// The loops in traversals, `repeat` and `fix` count against `limits` together, and stop
+    /// with a [`Divergence`] when they go over them or come back to an expression.
+    pub fn apply(
+        &self,
+        expr: &Expression,
+        rulesets: &BTreeMap<String, Vec<Rule>>,
+        limits: RewriteLimits,
+    ) -> Result<Option<Expression>, Divergence> {
+        self.apply_tracked(expr, rulesets, &mut RewriteTracker::new(limits))
+    }
+
+    fn apply_tracked(
+        &self,
+        expr: &Expression,
+        rulesets: &BTreeMap<String, Vec<Rule>>,
+        tracker: &mut RewriteTracker,
+    ) -> Result<Option<Expression>, Divergence> {
+        Ok(match self {
+            Strategy::Ruleset(name) => {
+                let changed = |new: Expression| (new != *expr).then_some(new);
+
+                match (rulesets.get(name), name.as_str()) {
+                    (Some(rules), _) => rules
+                        .iter()
+                        .find_map(|rule| changed(apply_rule(expr.clone(), rule).ok()?)),
+                    (None, FOLD_NAME) => changed(fold_constants(expr.clone())),
+                    (None, EXPAND_POWERS_NAME) => changed(expand_powers(expr.clone())),
+                    (None, _) => None,
+                }
+            }
+            Strategy::Innermost(s) | Strategy::Outermost(s) => {
+                let innermost = matches!(self, Strategy::Innermost(_));
+                let step = |current: &Expression, tracker: &mut RewriteTracker| {
+                    let mut positions = positions(current);
+                    // positions list parents first, the reverse has children before parents
+                    if innermost {
+                        positions.reverse();
+                    }
+                    for position in positions {
+                        if let Some(new) = s.apply_at(current, &position, rulesets, tracker)? {
+                            return Ok(Some(new));
+                        }
+                    }
+                    Ok(None)
+                };
+
+                let Some(mut current) = step(expr, tracker)? else {
+                    return Ok(None);
+                };
+                let mut visited = HashMap::from([(expr.clone(), 0)]);
+                tracker.step(&current, &mut visited)?;
+
+                while let Some(new) = step(&current, tracker)? {
+                    current = new;
+                    tracker.step(&current, &mut visited)?;
+                }
+
+                Some(current)
+            }
+            Strategy::Once(s) => {
+                for position in positions(expr) {
+                    if let Some(new) = s.apply_at(expr, &position, rulesets, tracker)? {
+                        return Ok(Some(new));
+                    }
+                }
+                None
+            }
+            Strategy::Repeat(s) | Strategy::Fix(s) => {
+                let fix = matches!(self, Strategy::Fix(_));
+                let mut current = expr.clone();
+                let mut visited = HashMap::from([(expr.clone(), 0)]);
+
+                while let Some(new) = s.apply_tracked(&current, rulesets, tracker)? {
+                    if fix && new == current {
+                        break;
+                    }
+                    current = new;
+                    // repeat of a strategy that succeeds without changing anything comes back
+                    // to the same expression right away
+                    tracker.step(&current, &mut visited)?;
+                }
+
+                Some(current)
+            }
+            Strategy::Seq(list) => {
+                let mut current = expr.clone();
+                for s in list {
+                    match s.apply_tracked(&current, rulesets, tracker)? {
+                        Some(new) => current = new,
+                        None => return Ok(None),
+                    }
+                }
+                Some(current)
+            }
+            Strategy::Choice(list) => {
+                for s in list {
+                    if let Some(new) = s.apply_tracked(expr, rulesets, tracker)? {
+                        return Ok(Some(new));
+                    }
+                }
+                None
+            }
+            Strategy::Try(s) => Some(
+                s.apply_tracked(expr, rulesets, tracker)?
+                    .unwrap_or_else(|| expr.clone()),
+            ),
+        })
+    }
+
+    // the strategy applied to the subexpression at position, None if it fails or changes
+    // nothing, which would keep the traversals going forever
+    fn apply_at(
+        &self,
+        expr: &Expression,
+        position: &[usize],
+        rulesets: &BTreeMap<String, Vec<Rule>>,
+        tracker: &mut RewriteTracker,
+    ) -> Result<Option<Expression>, Divergence> {
+        let Some(sub) = subexpression(expr, position) else {
+            return Ok(None);
+        };
+        let Some(new) = self.apply_tracked(sub, rulesets, tracker)? else {
+            return Ok(None);
+        };
+
+        if new == *sub {
+            return Ok(None);
+        }
+
+        Ok(replace_subexpression(expr.clone(), position, new))
+    }
+}
+
+impl std::fmt::Display for Strategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (name, args) = match self {
+            Strategy::Ruleset(name) => return f.write_str(name),
+            Strategy::Innermost(s) => ("innermost", std::slice::from_ref(&**s)),
+            Strategy::Outermost(s) => ("outermost", std::slice::from_ref(&**s)),
+            Strategy::Once(s) => ("once", std::slice::from_ref(&**s)),
+            Strategy::Repeat(s) => ("repeat", std::slice::from_ref(&**s)),
+            Strategy::Try(s) => ("try", std::slice::from_ref(&**s)),
+            Strategy::Fix(s) => ("fix", std::slice::from_ref(&**s)),
+            Strategy::Seq(list) => ("seq", list.as_slice()),
+            Strategy::Choice(list) => ("choice", list.as_slice()),
+        };
+
+        write!(f, "({name}")?;
+        for arg in args {
+            write!(f, " {arg}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+fn combinator_names() -> impl Iterator<Item = &'static str> {
+    UNARY_COMBINATORS
+        .iter()
+        .map(|(name, _)| *name)
+        .chain(VARIADIC_COMBINATORS.iter().map(|(name, _)| *name))
+}
+
+fn parse_tokens(tokens: &mut TokenStream) -> Result<Strategy, ParseError<StrategyParseError>> {
+    match tokens.pop_front() {
+        None => Err(tokens.error(StrategyParseError::EmptyString)),
+        Some(SourceToken::RGroup) => Err(tokens.error(StrategyParseError::IncorrectBrackets)),
+        Some(SourceToken::Text(name)) => Ok(Strategy::Ruleset(name)),
+        Some(SourceToken::LGroup) => {
+            let open = tokens.span();
+
+            let name = match tokens.pop_front() {
+                Some(SourceToken::Text(name)) => name,
+                Some(SourceToken::RGroup) => {
+                    return Err(open
+                        .to(tokens.span())
+                        .error(StrategyParseError::EmptyBrackets))
+                }
+                None => return Err(open.error(StrategyParseError::UnclosedBracket)),
+                Some(_) => return Err(tokens.error(StrategyParseError::UnknownCombinator)),
+            };
+            let name_span = tokens.span();
+
+            let mut args = Vec::new();
+            loop {
+                match tokens.front() {
+                    Some(SourceToken::RGroup) => {
+                        tokens.pop_front();
+                        break;
+                    }
+                    None => return Err(open.error(StrategyParseError::UnclosedBracket)),
+                    Some(_) => args.push(parse_tokens(tokens)?),
+                }
+            }
+            let whole = open.to(tokens.span());
+
+            if let Some((_, combinator)) = UNARY_COMBINATORS.iter().find(|(n, _)| *n == name) {
+                let [arg]: [Strategy; 1] = args
+                    .try_into()
+                    .map_err(|_| whole.error(StrategyParseError::IncorrectNumArgs))?;
+                Ok(combinator(Box::new(arg)))
+            } else if let Some((_, combinator)) =
+                VARIADIC_COMBINATORS.iter().find(|(n, _)| *n == name)
+            {
+                if args.is_empty() {
+                    return Err(whole.error(StrategyParseError::IncorrectNumArgs));
+                }
+                Ok(combinator(args))
+            } else {
+                Err(name_span.error(StrategyParseError::UnknownCombinator))
+            }
+        }
+        Some(_) => Err(tokens.error(StrategyParseError::ExpectedStrategy)),
+    }
+}
+
+#[test]
+fn test_strategy() {
+    use crate::solve::*;
+
+    let rulesets = RulesetLoader::new().load().unwrap().rules;
+    let expr = Lisp::parse("(* (csc x) (tan x))").unwrap();
+    let limits = RewriteLimits::default();
+
+    let strategy = Strategy::parse("(repeat (seq trigexpand simplify cancel))").unwrap();
+    assert_eq!(
+        strategy.to_string(),
+        "(repeat (seq trigexpand simplify cancel))"
+    );
+    assert_eq!(
+        strategy.rulesets(),
+        BTreeSet::from(["cancel", "simplify", "trigexpand"])
+    );
+
+    // a ruleset only rewrites the expression itself, here a product no rule is about
+    let trigexpand = Strategy::parse("trigexpand").unwrap();
+    assert_eq!(trigexpand.apply(&expr, &rulesets, limits), Ok(None));
+    assert_eq!(
+        trigexpand.apply(&Lisp::parse("(csc x)").unwrap(), &rulesets, limits),
+        Ok(Some(Lisp::parse("(/ (sin x))").unwrap()))
+    );
+
+    // the whole expression comes first, then its terms, so once rewrites the first of them
+    let once = Strategy::parse("(once trigexpand)").unwrap();
+    assert_eq!(
+        once.apply(&expr, &rulesets, limits),
+        Ok(Some(Lisp::parse("(* (/ (sin x)) (tan x))").unwrap()))
+    );
+
+    // innermost goes on until no subexpression is left for it
+    let expanded = Lisp::parse("(* (* (/ (cos x)) (sin x)) (/ (sin x)))").unwrap();
+    let innermost = Strategy::parse("(innermost trigexpand)").unwrap();
+    assert_eq!(
+        innermost.apply(&expr, &rulesets, limits),
+        Ok(Some(expanded.clone()))
+    );
+    assert_eq!(innermost.apply(&expanded, &rulesets, limits), Ok(None));
+
+    // the second time around the expansion has nothing left to do, which ends the repeat
+    let strategy = Strategy::parse(
+        "(repeat (seq (innermost trigexpand) (innermost simplify) (innermost cancel)))",
+    )
+    .unwrap();
+    let repeated = strategy.apply(&expr, &rulesets, limits).unwrap().unwrap();
+    assert_eq!(
+        Strategy::parse("(innermost simplify)")
+            .unwrap()
+            .apply(&repeated, &rulesets, limits),
+        Ok(Some(Lisp::parse("(/ (cos x))").unwrap()))
+    );
+
+    let strategy = Strategy::parse(
+        "(seq (innermost trigexpand) (fix (seq (try (innermost simplify)) (try (once cancel)))))",
+    )
+    .unwrap();
+    assert_eq!(
+        strategy.apply(&expr, &rulesets, limits),
+        Ok(Some(Lisp::parse("(/ (cos x))").unwrap()))
+    );
+
+    // try never fails, so repeating it only stops at the limits
+    let divergence = Strategy::parse("(repeat (try cancel))")
+        .unwrap()
+        .apply(&expr, &rulesets, limits)
+        .unwrap_err();
+    assert_eq!(divergence.kind, DivergenceKind::Cycle { length: 1 });
+    assert_eq!(divergence.last, vec![expr.clone()]);
+
+    assert_eq!(
+        Strategy::parse("(choice cancel (seq))").map_err(|e| e.kind),
+        Err(StrategyParseError::IncorrectNumArgs)
+    );
+    assert_eq!(
+        Strategy::parse("(every cancel)").map_err(|e| e.span.column),
+        Err(2)
+    );
+    assert_eq!(
+        Strategy::parse("(try cancel").map_err(|e| e.kind),
+        Err(StrategyParseError::UnclosedBracket)
+    );
+    assert_eq!(
+        Strategy::parse("(try nothing)")
+            .unwrap()
+            .unknown_ruleset(&rulesets),
+        Some("nothing")
+    );
+    assert!(Strategy::is_combinator(" ( fix cancel)"));
+    assert!(!Strategy::is_combinator("(sin x)"));
+}
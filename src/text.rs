@@ -1,13 +1,22 @@
-pub use lisp::*;
+pub use {infix::*, json::*, latex::*, lisp::*, mathml::*, span::*};
 use {
     crate::{expression::*, rewrite::*},
+    num_bigint::BigInt,
+    num_rational::BigRational,
+    num_traits::{Signed, Zero},
     std::collections::{BTreeMap, VecDeque},
 };
 
+mod infix;
+mod json;
+mod latex;
 mod lisp;
+mod mathml;
+mod span;
 
 const LGROUP: &str = "(";
 const RGROUP: &str = ")";
+const POWER: &str = "^";
 const VARIADIC_MAP: [(&str, VariadicKind); 2] = [
     ("+", VariadicKind::Addition),
     ("*", VariadicKind::Multiplication),
@@ -15,82 +24,55 @@ const VARIADIC_MAP: [(&str, VariadicKind); 2] = [
 const UNARY_MAP: [(&str, UnaryKind); 2] =
     [("-", UnaryKind::Negation), ("/", UnaryKind::Reciprocal)];
 const RULE_MAP: [(&str, RuleKind); 2] = [("=>", RuleKind::Replacement), ("==", RuleKind::Equality)];
+const RATIONAL_SEPARATOR: &str = "/";
+const VARIABLE_PREFIX: &str = "?";
+const VARIABLE_KIND_SEPARATOR: &str = ":";
+const REST: &str = "..";
+const VARIABLE_KIND_MAP: [(&str, VarKind); 4] = [
+    ("int", VarKind::Integer),
+    ("const", VarKind::Literal),
+    ("func", VarKind::Function),
+    ("var", VarKind::Variable),
+];
+// binding strength of each form in infix notations,
+// anything looser than the context gets brackets
+const SUM_LEVEL: u8 = 1;
+const TERM_LEVEL: u8 = 2;
+const UNARY_LEVEL: u8 = 3;
+const POWER_LEVEL: u8 = 4;
+const ATOM_LEVEL: u8 = 5;
+// functions that latex and mathml have their own names for
+const KNOWN_FUNCTIONS: [&str; 16] = [
+    "sin", "cos", "tan", "sec", "csc", "cot", "sinh", "cosh", "tanh", "coth", "arcsin", "arccos",
+    "arctan", "exp", "ln", "log",
+];
 
 #[derive(Debug, PartialEq)]
 pub enum SourceToken {
     Literal(LiteralValue),
     VariadicOperator(VariadicKind),
     UnaryOperator(UnaryKind),
+    PowerOperator,
     RuleOperator(RuleKind),
+    Variable(Variable),
     Text(String),
     LGroup,
     RGroup,
 }
 
-pub trait ExprTextFormat<ErrorType> {
-    fn parse(s: &str) -> Result<Expression, ErrorType> {
-        let mut tokens = Self::tokenize(s);
-        Self::parse_tokens(&mut tokens)
-    }
-
+// output only formats, ExprTextFormat adds parsing on top
+pub trait ExprTextEmitter {
     fn format(expr: &Expression) -> String {
         let tokens = Self::format_expr(expr);
         Self::format_tokens(tokens)
     }
 
-    fn tokenize(s: &str) -> VecDeque<SourceToken> {
-        let delim = |c| LGROUP.contains(c) || RGROUP.contains(c);
-        let variadic_map = BTreeMap::from(VARIADIC_MAP);
-        let unary_map = BTreeMap::from(UNARY_MAP);
-        let rule_map = BTreeMap::from(RULE_MAP);
-
-        s.split_inclusive(delim)
-            .flat_map(move |s| {
-                if let Some(i) = s.rfind(delim) {
-                    if i > 0 {
-                        let (a, b) = s.split_at(i);
-                        return vec![a, b];
-                    }
-                }
-                vec![s]
-            })
-            .flat_map(|s| s.split_whitespace())
-            .map(move |s| {
-                match s {
-                    LGROUP => return SourceToken::LGroup,
-                    RGROUP => return SourceToken::RGroup,
-                    _ => {}
-                }
-
-                if let Some(kind) = rule_map.get(s) {
-                    return SourceToken::RuleOperator(*kind);
-                }
-
-                if let Some(kind) = variadic_map.get(s) {
-                    return SourceToken::VariadicOperator(*kind);
-                }
-
-                if let Some(kind) = unary_map.get(s) {
-                    return SourceToken::UnaryOperator(kind.clone());
-                }
-
-                if let Ok(n) = s.parse::<i32>() {
-                    return SourceToken::Literal(LiteralValue::Integer(n));
-                }
-
-                if s.chars().all(|c| c.is_uppercase()) {
-                    return SourceToken::Literal(LiteralValue::Constant(s.to_string()));
-                }
-
-                SourceToken::Text(s.to_string())
-            })
-            .collect()
-    }
-
     fn format_tokens(mut tokens: VecDeque<SourceToken>) -> String {
         let variadic_map = BTreeMap::from_iter(VARIADIC_MAP.iter().copied().map(|(a, b)| (b, a)));
         let unary_map = BTreeMap::from_iter(UNARY_MAP.iter().map(|(a, b)| (b.clone(), *a)));
         let rule_map = BTreeMap::from_iter(RULE_MAP.iter().map(|(a, b)| (b, a)));
+        let variable_kind_map =
+            BTreeMap::from_iter(VARIABLE_KIND_MAP.iter().copied().map(|(a, b)| (b, a)));
 
         let mut output = String::new();
 
@@ -98,20 +80,40 @@ pub trait ExprTextFormat<ErrorType> {
             output.push_str(&match token {
                 SourceToken::Literal(l) => match l {
                     LiteralValue::Integer(i) => i.to_string(),
+                    LiteralValue::Rational(r) => {
+                        format!("{}{RATIONAL_SEPARATOR}{}", r.numer(), r.denom())
+                    }
                     LiteralValue::Constant(c) => c,
                 },
                 SourceToken::VariadicOperator(v) => variadic_map.get(&v).unwrap().to_string(),
                 SourceToken::UnaryOperator(u) => {
                     if let Some(s) = unary_map.get(&u) {
                         s.to_string()
+                    } else if let UnaryKind::Variable { id, relations } = u {
+                        relations
+                            .iter()
+                            .rev()
+                            .fold(id.as_ref().to_string(), |f, r| {
+                                format!("{LGROUP}{} {f}{RGROUP}", r.as_ref())
+                            })
                     } else {
                         let UnaryKind::Named { id } = u else {unreachable!()};
                         id.as_ref().to_string()
                     }
                 }
+                SourceToken::PowerOperator => POWER.to_string(),
                 SourceToken::LGroup => LGROUP.to_string(),
                 SourceToken::RGroup => RGROUP.to_string(),
                 SourceToken::RuleOperator(r) => rule_map.get(&r).unwrap().to_string(),
+                SourceToken::Variable(Variable { id, kind }) => {
+                    match variable_kind_map.get(&kind) {
+                        Some(k) => format!(
+                            "{VARIABLE_PREFIX}{}{VARIABLE_KIND_SEPARATOR}{k}",
+                            id.as_ref()
+                        ),
+                        None => id.as_ref().to_string(),
+                    }
+                }
                 SourceToken::Text(s) => s,
             });
         }
@@ -119,11 +121,177 @@ pub trait ExprTextFormat<ErrorType> {
         output
     }
 
-    fn parse_tokens(tokens: &mut VecDeque<SourceToken>) -> Result<Expression, ErrorType>;
-
     fn format_expr(expr: &Expression) -> VecDeque<SourceToken>;
 }
 
+pub trait ExprTextFormat<ErrorType>: ExprTextEmitter {
+    fn parse(s: &str) -> Result<Expression, ErrorType> {
+        let mut tokens = Self::tokenize(s);
+        Self::parse_tokens(&mut tokens)
+    }
+
+    fn tokenize(s: &str) -> TokenStream {
+        let mut tokens = TokenStream::new(s);
+        let mut word_start = None;
+
+        // words are split by whitespace and brackets, the end of the source also ends one
+        for (i, c) in s.char_indices().chain([(s.len(), ' ')]) {
+            let delim = LGROUP.contains(c) || RGROUP.contains(c);
+
+            if c.is_whitespace() || delim {
+                if let Some(start) = word_start.take() {
+                    tokens.push_back(word_token(&s[start..i]), Span::new(s, start, i));
+                }
+                if delim {
+                    let end = i + c.len_utf8();
+                    tokens.push_back(word_token(&s[i..end]), Span::new(s, i, end));
+                }
+            } else if word_start.is_none() {
+                word_start = Some(i);
+            }
+        }
+
+        tokens
+    }
+
+    fn parse_tokens(tokens: &mut TokenStream) -> Result<Expression, ErrorType>;
+}
+
+fn word_token(s: &str) -> SourceToken {
+    let variadic_map = BTreeMap::from(VARIADIC_MAP);
+    let unary_map = BTreeMap::from(UNARY_MAP);
+    let rule_map = BTreeMap::from(RULE_MAP);
+
+    match s {
+        LGROUP => return SourceToken::LGroup,
+        RGROUP => return SourceToken::RGroup,
+        POWER => return SourceToken::PowerOperator,
+        _ => {}
+    }
+
+    if let Some(kind) = rule_map.get(s) {
+        return SourceToken::RuleOperator(*kind);
+    }
+
+    if let Some(kind) = variadic_map.get(s) {
+        return SourceToken::VariadicOperator(*kind);
+    }
+
+    if let Some(kind) = unary_map.get(s) {
+        return SourceToken::UnaryOperator(kind.clone());
+    }
+
+    if let Some(v) = variable_token(s) {
+        return v;
+    }
+
+    if let Ok(n) = s.parse::<BigInt>() {
+        return SourceToken::Literal(LiteralValue::Integer(n));
+    }
+
+    // numerator/denominator, reduced to lowest terms
+    if let Some((n, d)) = s.split_once(RATIONAL_SEPARATOR) {
+        if let (Ok(n), Ok(d)) = (n.parse::<BigInt>(), d.parse::<BigInt>()) {
+            if !d.is_zero() {
+                return SourceToken::Literal(BigRational::new(n, d).into());
+            }
+        }
+    }
+
+    if s.chars().all(|c| c.is_uppercase()) {
+        return SourceToken::Literal(LiteralValue::Constant(s.to_string()));
+    }
+
+    SourceToken::Text(s.to_string())
+}
+
+// ?id or ?id:kind, None if the kind is unknown so it's left as text
+fn variable_token(s: &str) -> Option<SourceToken> {
+    let v = s.strip_prefix(VARIABLE_PREFIX)?;
+
+    match v.split_once(VARIABLE_KIND_SEPARATOR) {
+        None if !v.is_empty() => Some(SourceToken::Variable(Variable::new(v))),
+        Some((id, kind)) if !id.is_empty() => {
+            let kind = BTreeMap::from(VARIABLE_KIND_MAP).get(kind).copied()?;
+            Some(SourceToken::Variable(Variable::typed(id, kind)))
+        }
+        _ => None,
+    }
+}
+
+// how tightly expr binds when written with infix operators, for placing brackets
+fn level(expr: &Expression) -> u8 {
+    match expr {
+        Expression::Concrete(Concrete::Variadic(Variadic { terms, kind })) => {
+            match (terms.iter().count(), kind) {
+                (1, _) => level(terms.iter().next().unwrap()),
+                (0, _) => ATOM_LEVEL,
+                (_, VariadicKind::Addition) => SUM_LEVEL,
+                (_, VariadicKind::Multiplication) => TERM_LEVEL,
+            }
+        }
+        Expression::Concrete(Concrete::Unary(Unary { kind, .. })) => match kind {
+            UnaryKind::Negation => UNARY_LEVEL,
+            UnaryKind::Reciprocal => TERM_LEVEL,
+            UnaryKind::Named { .. } | UnaryKind::Variable { .. } => ATOM_LEVEL,
+        },
+        Expression::Concrete(Concrete::Power(_)) => POWER_LEVEL,
+        // signs and fraction bars read like operators
+        Expression::Concrete(Concrete::Literal(Literal { value })) => match value {
+            LiteralValue::Integer(n) if n.is_negative() => UNARY_LEVEL,
+            LiteralValue::Rational(_) => UNARY_LEVEL,
+            _ => ATOM_LEVEL,
+        },
+        Expression::Variable(_) => ATOM_LEVEL,
+    }
+}
+
+// like level, but fractions set off their contents already
+fn fraction_level(expr: &Expression) -> u8 {
+    match expr {
+        expr if is_reciprocal(expr) => POWER_LEVEL,
+        Expression::Concrete(Concrete::Variadic(Variadic {
+            terms,
+            kind: VariadicKind::Multiplication,
+        })) if terms.iter().count() > 1 && terms.iter().any(is_reciprocal) => POWER_LEVEL,
+        Expression::Concrete(Concrete::Literal(Literal {
+            value: LiteralValue::Rational(r),
+        })) if r.is_positive() => POWER_LEVEL,
+        expr => level(expr),
+    }
+}
+
+fn is_reciprocal(expr: &Expression) -> bool {
+    denominator(expr).is_some()
+}
+
+// what expr divides by, powers of a reciprocal like (^ (/ x) 2) go under the bar as x^2
+fn denominator(expr: &Expression) -> Option<Expression> {
+    match expr {
+        Expression::Concrete(Concrete::Unary(Unary {
+            argument,
+            kind: UnaryKind::Reciprocal,
+        })) => Some(*argument.clone()),
+        Expression::Concrete(Concrete::Power(Power { base, exponent })) => {
+            let denominator = denominator(base)?;
+            Some(Power::new(denominator, *exponent.clone()).into())
+        }
+        _ => None,
+    }
+}
+
+fn is_number(expr: &Expression) -> bool {
+    matches!(expr, Expression::Concrete(Concrete::Literal(Literal { value })) if value.number().is_some())
+}
+
+fn product(mut factors: Vec<Expression>) -> Expression {
+    if factors.len() == 1 {
+        factors.pop().unwrap()
+    } else {
+        Variadic::new(factors, VariadicKind::Multiplication).into()
+    }
+}
+
 impl std::fmt::Display for Expression {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", Lisp::format(self))
@@ -142,13 +310,14 @@ fn test_tokenizer() {
 
     let tokens: Vec<_> = Lisp::tokenize("(+ 1 ( * )((a (sin 4) (cos 2)))")
         .into_iter()
+        .map(|(token, _)| token)
         .collect();
 
     assert_eq!(
         vec![
             LGroup,
             VariadicOperator(VariadicKind::Addition),
-            Literal(LiteralValue::Integer(1)),
+            Literal(LiteralValue::Integer(1.into())),
             LGroup,
             VariadicOperator(VariadicKind::Multiplication),
             RGroup,
@@ -157,11 +326,11 @@ fn test_tokenizer() {
             Text("a".to_string()),
             LGroup,
             Text("sin".to_string()),
-            Literal(LiteralValue::Integer(4)),
+            Literal(LiteralValue::Integer(4.into())),
             RGroup,
             LGroup,
             Text("cos".to_string()),
-            Literal(LiteralValue::Integer(2)),
+            Literal(LiteralValue::Integer(2.into())),
             RGroup,
             RGroup,
             RGroup
@@ -172,18 +341,118 @@ fn test_tokenizer() {
     println!("tokens: {tokens:?}");
 }
 
+#[test]
+fn test_typed_variables() {
+    use SourceToken::*;
+
+    let tokens: Vec<_> = Lisp::tokenize("(+ ?n:int ?c:const ?x ?f:cake)")
+        .into_iter()
+        .map(|(token, _)| token)
+        .collect();
+
+    assert_eq!(
+        vec![
+            LGroup,
+            VariadicOperator(VariadicKind::Addition),
+            Variable(crate::expression::Variable::typed("n", VarKind::Integer)),
+            Variable(crate::expression::Variable::typed("c", VarKind::Literal)),
+            Variable(crate::expression::Variable::new("x")),
+            Text("?f:cake".to_string()),
+            RGroup
+        ],
+        tokens
+    );
+
+    let expr = Lisp::parse("(* ?n:int (sin ?x:var) ?f:func a)").unwrap();
+    assert_eq!(Lisp::parse(&expr.to_string()), Ok(expr));
+    assert_eq!(
+        Lisp::parse("?x"),
+        Ok(crate::expression::Variable::new("x").into())
+    );
+    assert_eq!(
+        Lisp::parse("(+ ?f:cake)").map_err(|e| e.kind),
+        Err(LispParseError::InvalidVariable)
+    );
+}
+
+#[test]
+fn test_function_variables() {
+    let expr = Lisp::parse("(* (F u) ((recip F) u) ((cofunc (recip G)) u))").unwrap();
+
+    assert!(expr.to_string().contains("((cofunc (recip G)) u)"));
+    assert_eq!(Lisp::parse(&expr.to_string()), Ok(expr));
+    assert_eq!(
+        Lisp::parse("((inverse F) u)").map_err(|e| e.kind),
+        Err(LispParseError::UnknownRelation)
+    );
+    assert_eq!(
+        Lisp::parse("((recip F G) u)").map_err(|e| e.kind),
+        Err(LispParseError::IncorrectNumArgs)
+    );
+}
+
+#[test]
+fn test_power() {
+    let expr = Lisp::parse("(^ (sin x) (+ n 1))").unwrap();
+
+    assert_eq!(
+        expr,
+        Power::new(
+            Lisp::parse("(sin x)").unwrap(),
+            Lisp::parse("(+ n 1)").unwrap()
+        )
+        .into()
+    );
+    assert_eq!(Lisp::parse(&expr.to_string()), Ok(expr));
+    assert_eq!(
+        Lisp::parse("(^ x)").map_err(|e| e.kind),
+        Err(LispParseError::IncorrectNumArgs)
+    );
+    assert_eq!(
+        Lisp::parse("(+ ^ 2)").map_err(|e| e.kind),
+        Err(LispParseError::ReservedOperator)
+    );
+}
+
 #[test]
 fn test_parser_errors() {
-    assert_eq!(Err(LispParseError::EmptyString), Lisp::parse(""));
+    assert_eq!(
+        Err(LispParseError::EmptyString),
+        Lisp::parse("").map_err(|e| e.kind)
+    );
     assert_eq!(
         Err(LispParseError::IncorrectBrackets),
-        Lisp::parse(")(+ 1 2)")
+        Lisp::parse(")(+ 1 2)").map_err(|e| e.kind)
+    );
+    assert_eq!(
+        Err(LispParseError::NotAFunction),
+        Lisp::parse("(1 2)").map_err(|e| e.kind)
+    );
+    assert_eq!(
+        Err(LispParseError::UnclosedBracket),
+        Lisp::parse("(* 4").map_err(|e| e.kind)
     );
-    assert_eq!(Err(LispParseError::NotAFunction), Lisp::parse("(1 2)"));
-    assert_eq!(Err(LispParseError::UnclosedBracket), Lisp::parse("(* 4"));
     assert_eq!(
         Err(LispParseError::IncorrectNumArgs),
-        Lisp::parse("(- 1 2 3)")
+        Lisp::parse("(- 1 2 3)").map_err(|e| e.kind)
+    );
+    assert_eq!(
+        Err(LispParseError::TrailingInput),
+        Lisp::parse("(sin x) y").map_err(|e| e.kind)
+    );
+
+    // unclosed brackets point at the bracket, argument counts at the whole group
+    assert_eq!(
+        Lisp::parse("(+ 1 (* 2 3)").unwrap_err().span,
+        Span::new("(+ 1 (* 2 3)", 0, 1)
+    );
+
+    let source = "(+ 1\n  (^ x))";
+    assert_eq!(
+        Lisp::parse(source)
+            .unwrap_err()
+            .diagnostic(source, "<input>"),
+        "error: wrong number of arguments\n --> <input>:2:3\n  |\n2 |   (^ x))\n  |   ^^^^^"
     );
 }
 
@@ -199,7 +468,7 @@ fn test_parser() {
                         Literal::new(1).into(),
                         Literal::new(2).into(),
                         Unary::new(Variable::new("a").into(), UnaryKind::Reciprocal).into(),
-                        Unary::new(Literal::new(0).into(), UnaryKind::named("sin").into()).into()
+                        Unary::new(Literal::new(0).into(), UnaryKind::named("sin")).into()
                     ],
                     VariadicKind::Addition
                 )
@@ -241,11 +510,11 @@ fn test_shared_identifiers() {
 
     assert_eq!(
         exp3,
-        Unary::new(Literal::new(1).into(), UnaryKind::named("sin").into()).into()
+        Unary::new(Literal::new(1).into(), UnaryKind::named("sin")).into()
     );
     assert_eq!(
         exp4,
-        Unary::new(Literal::new(2).into(), UnaryKind::named("sin").into()).into()
+        Unary::new(Literal::new(2).into(), UnaryKind::named("sin")).into()
     )
 }
 
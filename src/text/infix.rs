@@ -0,0 +1,776 @@
+use crate::{rewrite::*, text::*};
+
+pub struct Infix;
+
+// infix grammar, from the loosest binding to the tightest:
+// ruleset := rule (sep rule)*
+// rule := sum (=> | == | =) sum (if pred (, pred)*)?
+// sum := term ((+ | -) term)*
+// term := unary ((* | /)? unary)*
+// unary := - unary | power
+// power := atom (^ unary)?
+// atom := lit | var | (sum) | name(sum) | FUNCVAR(sum) | relation(func)(sum)
+// pred := name(var (, var)*) | not(pred)
+// a - b is (+ a (- b)), a / b is (* a (/ b)) and 1 / b is (/ b)
+
+const COMMENT: &str = "%";
+const SEPARATOR: &str = ";";
+const ARG_SEPARATOR: &str = ",";
+const EQUALS: &str = "=";
+
+#[derive(Debug, PartialEq)]
+pub enum InfixParseError {
+    EmptyString,
+    UnexpectedToken,
+    UnclosedBracket,
+    ExpectedBracket,
+    ExpectedRuleOp,
+    InvalidGuard,
+    InvalidVariable,
+    UnknownRelation,
+}
+
+impl std::fmt::Display for InfixParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            InfixParseError::EmptyString => "expected an expression",
+            InfixParseError::UnexpectedToken => "unexpected token",
+            InfixParseError::UnclosedBracket => "unclosed bracket",
+            InfixParseError::ExpectedBracket => "expected an opening bracket",
+            InfixParseError::ExpectedRuleOp => "expected =>, == or =",
+            InfixParseError::InvalidGuard => "invalid guard",
+            InfixParseError::InvalidVariable => "invalid variable",
+            InfixParseError::UnknownRelation => "unknown relation",
+        };
+        f.write_str(message)
+    }
+}
+
+impl ExprTextFormat<ParseError<InfixParseError>> for Infix {
+    fn tokenize(s: &str) -> TokenStream {
+        let mut chars = s.char_indices().peekable();
+        let mut output = TokenStream::new(s);
+
+        type Chars<'a> = std::iter::Peekable<std::str::CharIndices<'a>>;
+
+        let word = |chars: &mut Chars, first: char, kinded: bool| {
+            let mut word = first.to_string();
+            while let Some((_, c)) = chars.next_if(|&(_, c)| {
+                c.is_alphanumeric() || c == '_' || c == '.' || (kinded && c == ':')
+            }) {
+                word.push(c);
+            }
+            word
+        };
+        let end = |chars: &mut Chars| chars.peek().map_or(s.len(), |&(i, _)| i);
+
+        while let Some((start, c)) = chars.next() {
+            let token = match c {
+                // line breaks end rules, but only one separator is kept between them
+                '\n' | ';' => {
+                    if output.is_empty() || output.back() == Some(&separator()) {
+                        continue;
+                    }
+                    separator()
+                }
+                c if c.is_whitespace() => continue,
+                '(' => SourceToken::LGroup,
+                ')' => SourceToken::RGroup,
+                '^' => SourceToken::PowerOperator,
+                '+' => SourceToken::VariadicOperator(VariadicKind::Addition),
+                '*' => SourceToken::VariadicOperator(VariadicKind::Multiplication),
+                '-' => SourceToken::UnaryOperator(UnaryKind::Negation),
+                '/' => SourceToken::UnaryOperator(UnaryKind::Reciprocal),
+                ',' => SourceToken::Text(ARG_SEPARATOR.to_string()),
+                '=' => match chars.peek() {
+                    Some((_, '>')) => {
+                        chars.next();
+                        SourceToken::RuleOperator(RuleKind::Replacement)
+                    }
+                    Some((_, '=')) => {
+                        chars.next();
+                        SourceToken::RuleOperator(RuleKind::Equality)
+                    }
+                    _ => SourceToken::RuleOperator(RuleKind::Equality),
+                },
+                // digits, or digits/digits without spaces as a rational literal
+                c if c.is_ascii_digit() => {
+                    let mut number = c.to_string();
+                    while let Some((_, c)) = chars.next_if(|(_, c)| c.is_ascii_digit()) {
+                        number.push(c);
+                    }
+
+                    let mut lookahead = chars.clone();
+                    if lookahead.next().map(|(_, c)| c) == Some('/')
+                        && lookahead.peek().is_some_and(|(_, c)| c.is_ascii_digit())
+                    {
+                        let mut denom = String::new();
+                        while let Some((_, c)) = lookahead.next_if(|(_, c)| c.is_ascii_digit()) {
+                            denom.push(c);
+                        }
+                        let denom: BigInt = denom.parse().unwrap();
+                        if !denom.is_zero() {
+                            chars = lookahead;
+                            output.push_back(
+                                SourceToken::Literal(
+                                    BigRational::new(number.parse().unwrap(), denom).into(),
+                                ),
+                                Span::new(s, start, end(&mut chars)),
+                            );
+                            continue;
+                        }
+                    }
+
+                    SourceToken::Literal(LiteralValue::Integer(number.parse().unwrap()))
+                }
+                '?' => {
+                    let word = word(&mut chars, c, true);
+                    variable_token(&word).unwrap_or(SourceToken::Text(word))
+                }
+                c if c.is_alphabetic() || c == '_' || c == '.' => {
+                    let word = word(&mut chars, c, false);
+                    if word.chars().all(|c| c.is_uppercase()) {
+                        SourceToken::Literal(LiteralValue::Constant(word))
+                    } else {
+                        SourceToken::Text(word)
+                    }
+                }
+                c => SourceToken::Text(c.to_string()),
+            };
+
+            output.push_back(token, Span::new(s, start, end(&mut chars)));
+        }
+
+        if output.back() == Some(&separator()) {
+            output.pop_back();
+        }
+
+        output
+    }
+
+    fn parse_tokens(tokens: &mut TokenStream) -> Result<Expression, ParseError<InfixParseError>> {
+        let expr = parse_tokens_sum(tokens)?;
+
+        // whatever can't continue the sum, like the = in x = y, is left over
+        if tokens.pop_front().is_some() {
+            return Err(tokens.error(InfixParseError::UnexpectedToken));
+        }
+
+        Ok(expr)
+    }
+}
+
+impl ExprTextEmitter for Infix {
+    fn format_expr(expr: &Expression) -> VecDeque<SourceToken> {
+        format_expr_level(expr, 0)
+    }
+}
+
+impl RuleTextFormat<ParseError<InfixParseError>> for Infix {
+    fn parse_tokens_rule(tokens: &mut TokenStream) -> Result<Rule, ParseError<InfixParseError>> {
+        let pattern = parse_tokens_sum(tokens)?;
+
+        let Some(SourceToken::RuleOperator(kind)) = tokens.pop_front() else {
+            return Err(tokens.error(InfixParseError::ExpectedRuleOp));
+        };
+
+        let replacement = parse_tokens_sum(tokens)?;
+
+        let guard = if tokens.front() == Some(&SourceToken::Text(GUARD_KEYWORD.to_string())) {
+            tokens.pop_front();
+            let mut predicates = vec![parse_tokens_predicate(tokens)?];
+
+            while tokens.front() == Some(&SourceToken::Text(ARG_SEPARATOR.to_string())) {
+                tokens.pop_front();
+                predicates.push(parse_tokens_predicate(tokens)?);
+            }
+
+            Some(Guard { predicates })
+        } else {
+            None
+        };
+
+        match tokens.pop_front() {
+            None => {}
+            Some(token) if token == separator() => {}
+            Some(_) => return Err(tokens.error(InfixParseError::UnexpectedToken)),
+        }
+
+        Ok(Rule {
+            pattern,
+            replacement,
+            kind,
+            guard,
+        })
+    }
+
+    fn format_rule_tokens(rule: &Rule) -> VecDeque<SourceToken> {
+        let Rule {
+            pattern,
+            replacement,
+            kind,
+            guard,
+        } = rule;
+        let mut output = Self::format_expr(pattern);
+
+        output.push_back(SourceToken::Text(" ".to_string()));
+        output.push_back(match kind {
+            RuleKind::Replacement => SourceToken::RuleOperator(*kind),
+            RuleKind::Equality => SourceToken::Text(EQUALS.to_string()),
+        });
+        output.push_back(SourceToken::Text(" ".to_string()));
+        output.append(&mut Self::format_expr(replacement));
+
+        if let Some(Guard { predicates }) = guard {
+            output.push_back(SourceToken::Text(format!(" {GUARD_KEYWORD} ")));
+
+            for (i, predicate) in predicates.iter().enumerate() {
+                if i > 0 {
+                    output.push_back(SourceToken::Text(format!("{ARG_SEPARATOR} ")));
+                }
+                output.append(&mut format_predicate_tokens(predicate));
+            }
+        }
+
+        output
+    }
+
+    fn comment() -> &'static str {
+        COMMENT
+    }
+}
+
+fn separator() -> SourceToken {
+    SourceToken::Text(SEPARATOR.to_string())
+}
+
+// whether the token can begin an atom, which makes juxtaposition a multiplication
+fn starts_atom(token: Option<&SourceToken>) -> bool {
+    match token {
+        Some(SourceToken::Literal(_) | SourceToken::Variable(_) | SourceToken::LGroup) => true,
+        Some(SourceToken::Text(t)) => t != SEPARATOR && t != ARG_SEPARATOR && t != GUARD_KEYWORD,
+        _ => false,
+    }
+}
+
+// the bracket closing the one at open
+fn expect_rgroup(tokens: &mut TokenStream, open: Span) -> Result<(), ParseError<InfixParseError>> {
+    match tokens.pop_front() {
+        Some(SourceToken::RGroup) => Ok(()),
+        Some(_) => Err(tokens.error(InfixParseError::UnexpectedToken)),
+        None => Err(open.error(InfixParseError::UnclosedBracket)),
+    }
+}
+
+fn parse_tokens_sum(tokens: &mut TokenStream) -> Result<Expression, ParseError<InfixParseError>> {
+    let mut terms = vec![parse_tokens_term(tokens)?];
+
+    loop {
+        match tokens.front() {
+            Some(SourceToken::VariadicOperator(VariadicKind::Addition)) => {
+                tokens.pop_front();
+                terms.push(parse_tokens_term(tokens)?);
+            }
+            Some(SourceToken::UnaryOperator(UnaryKind::Negation)) => {
+                tokens.pop_front();
+                terms.push(Unary::new(parse_tokens_term(tokens)?, UnaryKind::Negation).into());
+            }
+            _ => break,
+        }
+    }
+
+    Ok(variadic_or_single(terms, VariadicKind::Addition))
+}
+
+fn parse_tokens_term(tokens: &mut TokenStream) -> Result<Expression, ParseError<InfixParseError>> {
+    let mut factors = vec![parse_tokens_unary(tokens)?];
+
+    // 1 / b is just the reciprocal
+    if factors[0] == Literal::new(1).into()
+        && tokens.front() == Some(&SourceToken::UnaryOperator(UnaryKind::Reciprocal))
+    {
+        factors.clear();
+    }
+
+    loop {
+        match tokens.front() {
+            Some(SourceToken::VariadicOperator(VariadicKind::Multiplication)) => {
+                tokens.pop_front();
+                factors.push(parse_tokens_unary(tokens)?);
+            }
+            Some(SourceToken::UnaryOperator(UnaryKind::Reciprocal)) => {
+                tokens.pop_front();
+                factors.push(Unary::new(parse_tokens_unary(tokens)?, UnaryKind::Reciprocal).into());
+            }
+            token if starts_atom(token) => factors.push(parse_tokens_unary(tokens)?),
+            _ => break,
+        }
+    }
+
+    Ok(variadic_or_single(factors, VariadicKind::Multiplication))
+}
+
+fn parse_tokens_unary(tokens: &mut TokenStream) -> Result<Expression, ParseError<InfixParseError>> {
+    if tokens.front() != Some(&SourceToken::UnaryOperator(UnaryKind::Negation)) {
+        return parse_tokens_power(tokens);
+    }
+
+    tokens.pop_front();
+
+    // a minus sign directly on a number is a negative literal, unless it's raised to a power
+    if let (Some(SourceToken::Literal(l)), next) = (tokens.front(), tokens.get(1)) {
+        if let (Some(n), false) = (l.number(), next == Some(&SourceToken::PowerOperator)) {
+            tokens.pop_front();
+            return Ok(Literal::new(-n).into());
+        }
+    }
+
+    Ok(Unary::new(parse_tokens_unary(tokens)?, UnaryKind::Negation).into())
+}
+
+fn parse_tokens_power(tokens: &mut TokenStream) -> Result<Expression, ParseError<InfixParseError>> {
+    let base = parse_tokens_atom(tokens)?;
+
+    if tokens.front() == Some(&SourceToken::PowerOperator) {
+        tokens.pop_front();
+        // the exponent can be another power, so ^ groups to the right
+        let exponent = parse_tokens_unary(tokens)?;
+        Ok(Power::new(base, exponent).into())
+    } else {
+        Ok(base)
+    }
+}
+
+fn parse_tokens_atom(tokens: &mut TokenStream) -> Result<Expression, ParseError<InfixParseError>> {
+    let token = tokens
+        .pop_front()
+        .ok_or_else(|| tokens.error(InfixParseError::EmptyString))?;
+    let open = tokens.span();
+    let applied = tokens.front() == Some(&SourceToken::LGroup);
+
+    match token {
+        SourceToken::LGroup => {
+            let expr = parse_tokens_sum(tokens)?;
+            expect_rgroup(tokens, open)?;
+            Ok(expr)
+        }
+        SourceToken::Literal(LiteralValue::Constant(id)) if applied => {
+            let kind = UnaryKind::Variable {
+                id: VarId::new(id),
+                relations: Vec::new(),
+            };
+            Ok(Unary::new(parse_tokens_argument(tokens)?, kind).into())
+        }
+        SourceToken::Literal(l) => Ok(Literal::new(l).into()),
+        SourceToken::Variable(v) => Ok(v.into()),
+        SourceToken::Text(t) if t.starts_with(VARIABLE_PREFIX) => {
+            Err(tokens.error(InfixParseError::InvalidVariable))
+        }
+        SourceToken::Text(t) if applied && is_relation(&t) && relates_function(tokens) => {
+            tokens.pop_front();
+            let kind = parse_tokens_function(t, tokens)?;
+            Ok(Unary::new(parse_tokens_argument(tokens)?, kind).into())
+        }
+        SourceToken::Text(t) if applied => {
+            Ok(Unary::new(parse_tokens_argument(tokens)?, UnaryKind::named(t)).into())
+        }
+        SourceToken::Text(t)
+            if t == REST
+                || t != GUARD_KEYWORD && t.chars().all(|c| c.is_alphanumeric() || c == '_') =>
+        {
+            Ok(Variable::new(t).into())
+        }
+        _ => Err(tokens.error(InfixParseError::UnexpectedToken)),
+    }
+}
+
+// (sum) after a function
+fn parse_tokens_argument(
+    tokens: &mut TokenStream,
+) -> Result<Expression, ParseError<InfixParseError>> {
+    if tokens.pop_front() != Some(SourceToken::LGroup) {
+        return Err(tokens.error(InfixParseError::ExpectedBracket));
+    }
+
+    let open = tokens.span();
+    let argument = parse_tokens_sum(tokens)?;
+    expect_rgroup(tokens, open)?;
+    Ok(argument)
+}
+
+// whether relation( is followed by a function variable, otherwise it's a plain function
+fn relates_function(tokens: &TokenStream) -> bool {
+    match tokens.get(1) {
+        Some(SourceToken::Literal(LiteralValue::Constant(_))) => true,
+        Some(SourceToken::Text(t)) => is_relation(t),
+        _ => false,
+    }
+}
+
+// relation(func) after its opening bracket, as a function variable
+fn parse_tokens_function(
+    relation: String,
+    tokens: &mut TokenStream,
+) -> Result<UnaryKind, ParseError<InfixParseError>> {
+    let open = tokens.span();
+
+    if !is_relation(&relation) {
+        return Err(tokens.error(InfixParseError::UnknownRelation));
+    }
+
+    let (id, mut relations) = match tokens.pop_front() {
+        Some(SourceToken::Literal(LiteralValue::Constant(id))) => (VarId::new(id), Vec::new()),
+        Some(SourceToken::Text(inner)) => {
+            if tokens.pop_front() != Some(SourceToken::LGroup) {
+                return Err(tokens.error(InfixParseError::ExpectedBracket));
+            }
+            let UnaryKind::Variable { id, relations } = parse_tokens_function(inner, tokens)?
+            else {
+                unreachable!()
+            };
+            (id, relations)
+        }
+        Some(_) => return Err(tokens.error(InfixParseError::UnknownRelation)),
+        None => return Err(open.error(InfixParseError::UnclosedBracket)),
+    };
+
+    expect_rgroup(tokens, open)?;
+    relations.insert(0, FuncId::new(relation));
+
+    Ok(UnaryKind::Variable { id, relations })
+}
+
+fn parse_tokens_predicate(
+    tokens: &mut TokenStream,
+) -> Result<Predicate, ParseError<InfixParseError>> {
+    let Some(SourceToken::Text(name)) = tokens.pop_front() else {
+        return Err(tokens.error(InfixParseError::InvalidGuard));
+    };
+    let name_span = tokens.span();
+
+    if tokens.pop_front() != Some(SourceToken::LGroup) {
+        return Err(tokens.error(InfixParseError::ExpectedBracket));
+    }
+
+    let open = tokens.span();
+
+    let predicate = if name == "not" {
+        Predicate::Not(Box::new(parse_tokens_predicate(tokens)?))
+    } else {
+        let mut args = Vec::new();
+
+        loop {
+            match tokens.pop_front() {
+                Some(SourceToken::Text(arg)) if arg != ARG_SEPARATOR => {
+                    args.push(Variable::new(arg))
+                }
+                Some(SourceToken::Variable(arg)) => args.push(arg.untyped()),
+                _ => return Err(tokens.error(InfixParseError::InvalidGuard)),
+            }
+
+            if tokens.front() != Some(&SourceToken::Text(ARG_SEPARATOR.to_string())) {
+                break;
+            }
+            tokens.pop_front();
+        }
+
+        Predicate::from_name(&name, args)
+            .ok_or_else(|| name_span.error(InfixParseError::InvalidGuard))?
+    };
+
+    expect_rgroup(tokens, open)?;
+
+    Ok(predicate)
+}
+
+fn format_predicate_tokens(predicate: &Predicate) -> VecDeque<SourceToken> {
+    let mut output = VecDeque::from([
+        SourceToken::Text(predicate.name().to_string()),
+        SourceToken::LGroup,
+    ]);
+
+    if let Predicate::Not(inner) = predicate {
+        output.append(&mut format_predicate_tokens(inner));
+    }
+
+    let args: Vec<_> = predicate
+        .args()
+        .iter()
+        .map(|v| v.id.as_ref().clone())
+        .collect();
+    output.push_back(SourceToken::Text(args.join(&format!("{ARG_SEPARATOR} "))));
+    output.push_back(SourceToken::RGroup);
+
+    output
+}
+
+fn variadic_or_single(mut terms: Vec<Expression>, kind: VariadicKind) -> Expression {
+    if terms.len() == 1 {
+        terms.pop().unwrap()
+    } else {
+        Variadic::new(terms, kind).into()
+    }
+}
+
+// expr with brackets around it if it binds looser than min_level
+fn format_expr_level(expr: &Expression, min_level: u8) -> VecDeque<SourceToken> {
+    let mut output = VecDeque::new();
+    let bracket = level(expr) < min_level;
+    let text = |s: &str| SourceToken::Text(s.to_string());
+
+    if bracket {
+        output.push_back(SourceToken::LGroup);
+    }
+
+    match expr {
+        Expression::Concrete(c) => match c {
+            Concrete::Variadic(Variadic { terms, kind }) => {
+                let (inverse, separator, inverse_separator, empty) = match kind {
+                    VariadicKind::Addition => (UnaryKind::Negation, " + ", " - ", "0"),
+                    VariadicKind::Multiplication => (UnaryKind::Reciprocal, " * ", " / ", "1"),
+                };
+                let operand_level = match kind {
+                    VariadicKind::Addition => TERM_LEVEL,
+                    VariadicKind::Multiplication => UNARY_LEVEL,
+                };
+
+                // inverted terms go last so they can be written with the binary operator
+                let (inverted, plain): (Vec<_>, Vec<_>) = terms.iter().partition(|t| {
+                    matches!(t, Expression::Concrete(Concrete::Unary(Unary { kind, .. })) if *kind == inverse)
+                });
+
+                match terms.iter().count() {
+                    0 => output.push_back(text(empty)),
+                    1 => output.append(&mut format_expr_level(
+                        terms.iter().next().unwrap(),
+                        min_level,
+                    )),
+                    _ => {
+                        // products of only reciprocals are written 1 / a / b
+                        let mut first = true;
+                        if plain.is_empty() && inverse == UnaryKind::Reciprocal {
+                            output.push_back(text(empty));
+                            first = false;
+                        }
+                        // so a product of 1 and reciprocals is written 1 * (1 / a) / b
+                        let mut one_apart = inverse == UnaryKind::Reciprocal
+                            && plain[..] == [&Literal::new(1).into()];
+
+                        for term in plain {
+                            if !first {
+                                output.push_back(text(separator));
+                            }
+                            output.append(&mut format_expr_level(term, operand_level));
+                            first = false;
+                        }
+
+                        for term in inverted {
+                            let Expression::Concrete(Concrete::Unary(Unary { argument, .. })) =
+                                term
+                            else {
+                                unreachable!()
+                            };
+
+                            if first {
+                                // a leading minus is just a negation
+                                output.append(&mut format_expr_level(term, operand_level));
+                            } else if one_apart {
+                                output.push_back(text(separator));
+                                output.append(&mut format_expr_level(term, operand_level));
+                                one_apart = false;
+                            } else {
+                                output.push_back(text(inverse_separator));
+                                output.append(&mut format_expr_level(argument, operand_level));
+                            }
+                            first = false;
+                        }
+                    }
+                }
+            }
+            Concrete::Unary(Unary { argument, kind }) => match kind {
+                UnaryKind::Negation => {
+                    output.push_back(text("-"));
+                    // -(2) stays apart from the literal -2
+                    let min_level = match argument.as_ref() {
+                        Expression::Concrete(Concrete::Literal(Literal { value }))
+                            if value.number().is_some() =>
+                        {
+                            ATOM_LEVEL + 1
+                        }
+                        _ => POWER_LEVEL,
+                    };
+                    output.append(&mut format_expr_level(argument, min_level));
+                }
+                UnaryKind::Reciprocal => {
+                    output.push_back(text("1 / "));
+                    output.append(&mut format_expr_level(argument, UNARY_LEVEL));
+                }
+                UnaryKind::Named { id } => {
+                    output.push_back(text(id.as_ref()));
+                    output.push_back(SourceToken::LGroup);
+                    output.append(&mut format_expr_level(argument, 0));
+                    output.push_back(SourceToken::RGroup);
+                }
+                UnaryKind::Variable { id, relations } => {
+                    let func = relations
+                        .iter()
+                        .rev()
+                        .fold(id.as_ref().to_string(), |f, r| {
+                            format!("{}{LGROUP}{f}{RGROUP}", r.as_ref())
+                        });
+                    output.push_back(SourceToken::Text(func));
+                    output.push_back(SourceToken::LGroup);
+                    output.append(&mut format_expr_level(argument, 0));
+                    output.push_back(SourceToken::RGroup);
+                }
+            },
+            Concrete::Power(Power { base, exponent }) => {
+                output.append(&mut format_expr_level(base, ATOM_LEVEL));
+                output.push_back(SourceToken::PowerOperator);
+                output.append(&mut format_expr_level(exponent, POWER_LEVEL));
+            }
+            Concrete::Literal(Literal { value }) => {
+                output.push_back(SourceToken::Literal(value.clone()));
+            }
+        },
+        Expression::Variable(v) => output.push_back(SourceToken::Variable(v.clone())),
+    }
+
+    if bracket {
+        output.push_back(SourceToken::RGroup);
+    }
+
+    output
+}
+
+#[test]
+fn test_infix_parser() {
+    let expr = Infix::parse("sin(x)^2 + cos(x)^2").unwrap();
+    assert_eq!(
+        expr,
+        Lisp::parse("(+ (^ (sin x) 2) (^ (cos x) 2))").unwrap()
+    );
+
+    let cases = [
+        ("a - b / c", "(+ a (- (* b (/ c))))"),
+        ("1 / x", "(/ x)"),
+        ("2x y", "(* 2 x y)"),
+        ("2(x + 1)", "(* 2 (+ x 1))"),
+        ("-x^2", "(- (^ x 2))"),
+        ("-2^2", "(- (^ 2 2))"),
+        ("-2 * x", "(* -2 x)"),
+        ("x^y^z", "(^ x (^ y z))"),
+        ("x^-1", "(^ x -1)"),
+        ("3/4 x", "(* 3/4 x)"),
+        ("3 / 4", "(* 3 (/ 4))"),
+        ("-(3)", "(- 3)"),
+        (
+            "F(u) recip(cofunc(G))(u)",
+            "(* (F u) ((recip (cofunc G)) u))",
+        ),
+        ("?n:int * PI + ..", "(+ (* ?n:int PI) ..)"),
+    ];
+
+    for (infix, lisp) in cases {
+        assert_eq!(
+            Infix::parse(infix),
+            Ok(Lisp::parse(lisp).unwrap()),
+            "{infix}"
+        );
+    }
+
+    assert_eq!(
+        Infix::parse("").map_err(|e| e.kind),
+        Err(InfixParseError::EmptyString)
+    );
+    assert_eq!(
+        Infix::parse("(x + 1").map_err(|e| e.kind),
+        Err(InfixParseError::UnclosedBracket)
+    );
+    assert_eq!(
+        Infix::parse("x + * y").map_err(|e| e.kind),
+        Err(InfixParseError::UnexpectedToken)
+    );
+    assert_eq!(
+        Infix::parse("x + ?f:cake").map_err(|e| e.kind),
+        Err(InfixParseError::InvalidVariable)
+    );
+    assert_eq!(
+        Infix::parse("sin(x) + * y").unwrap_err().span,
+        Span::new("sin(x) + * y", 9, 10)
+    );
+    assert_eq!(
+        Infix::parse("x = y = z").unwrap_err().span,
+        Span::new("x = y = z", 2, 3)
+    );
+    assert_eq!(
+        Infix::parse("(x) y)").map_err(|e| e.kind),
+        Err(InfixParseError::UnexpectedToken)
+    );
+}
+
+#[test]
+fn test_infix_printer() {
+    let cases = [
+        ("(+ (^ (sin x) 2) (^ (cos x) 2))", "cos(x)^2 + sin(x)^2"),
+        ("(+ (- a) b)", "b - a"),
+        ("(+ (- a) (- b))", "-a - b"),
+        ("(* (/ a) b)", "b / a"),
+        ("(* (+ a b) (/ (+ c d)))", "(a + b) / (c + d)"),
+        ("(/ (* a b))", "1 / (a * b)"),
+        ("(^ (- x) (+ n 1))", "(-x)^(1 + n)"),
+        ("(^ -2 1/2)", "(-2)^(1/2)"),
+        ("(- (- x))", "-(-x)"),
+        ("(- 3)", "-(3)"),
+        ("(* 3 (/ 4))", "3 / 4"),
+        ("(* 1 (/ a))", "1 * (1 / a)"),
+        ("(* 1 (/ a) (/ b))", "1 * (1 / a) / b"),
+        ("(* 1 x (/ a))", "1 * x / a"),
+        ("((cofunc (recip F)) u)", "cofunc(recip(F))(u)"),
+    ];
+
+    for (lisp, infix) in cases {
+        let expr = Lisp::parse(lisp).unwrap();
+        assert_eq!(Infix::format(&expr), infix);
+        assert_eq!(Infix::parse(infix), Ok(expr), "{infix}");
+    }
+}
+
+#[test]
+fn test_infix_rules() {
+    let rules = Infix::parse_ruleset(
+        "
+        % comments run to the end of the line
+        sin(x)^2 + cos(x)^2 = 1
+        a * (b + c) == a b + a c; x / x => 1 if nonzero(x), not(integer(x))
+        ",
+    )
+    .unwrap();
+
+    assert_eq!(rules.len(), 3);
+    assert_eq!(
+        rules[0],
+        Lisp::parse_ruleset("(== (+ (^ (sin x) 2) (^ (cos x) 2)) 1)").unwrap()[0]
+    );
+    assert_eq!(rules[1].kind, RuleKind::Equality);
+    assert_eq!(
+        rules[2],
+        Lisp::parse_ruleset("(=> (* x (/ x)) 1 (if (nonzero x) (not (integer x))))").unwrap()[0]
+    );
+
+    for rule in rules {
+        assert_eq!(
+            Infix::parse_ruleset(&Infix::format_rule(&rule)),
+            Ok(vec![rule])
+        );
+    }
+
+    assert_eq!(
+        Infix::format_rule(&Lisp::parse_ruleset("(=> (* x (/ x)) 1 (if (nonzero x)))").unwrap()[0]),
+        "x / x => 1 if nonzero(x)"
+    );
+    assert_eq!(
+        Infix::parse_ruleset("x + 1").map_err(|e| e.kind),
+        Err(InfixParseError::ExpectedRuleOp)
+    );
+}
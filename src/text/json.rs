@@ -0,0 +1,422 @@
+use {
+    crate::{rewrite::*, solve::*, text::*},
+    serde_json::{json, Map, Value},
+    std::time::Duration,
+};
+
+// expressions as a tree of json objects that can be read back, operators are
+// {"op": "+", "args": [...]} for + * - / and ^, functions {"function": "sin", "args": [x]},
+// function variables also have "relations", literals are {"integer": "2"},
+// {"rational": "1/2"} or {"constant": "PI"}, and variables {"variable": "x"} with an optional
+// "kind" like "int"
+
+pub struct JsonTree;
+
+#[derive(Debug, PartialEq)]
+pub enum JsonParseError {
+    Json(String),
+    ExpectedObject,
+    UnknownNode(String),
+    UnknownOperator(String),
+    UnknownVariableKind(String),
+    UnknownRelation(String),
+    IncorrectNumArgs(String),
+    InvalidNumber(String),
+    ExpectedString(String),
+}
+
+const OP: &str = "op";
+const ARGS: &str = "args";
+const FUNCTION: &str = "function";
+const RELATIONS: &str = "relations";
+const VARIABLE: &str = "variable";
+const KIND: &str = "kind";
+const INTEGER: &str = "integer";
+const RATIONAL: &str = "rational";
+const CONSTANT: &str = "constant";
+
+impl ExprTextEmitter for JsonTree {
+    fn format_expr(expr: &Expression) -> VecDeque<SourceToken> {
+        VecDeque::from([SourceToken::Text(Self::value(expr).to_string())])
+    }
+}
+
+impl JsonTree {
+    pub fn parse(s: &str) -> Result<Expression, JsonParseError> {
+        let value = serde_json::from_str(s).map_err(|e| JsonParseError::Json(e.to_string()))?;
+        Self::from_value(&value)
+    }
+
+    pub fn value(expr: &Expression) -> Value {
+        match expr {
+            Expression::Concrete(Concrete::Variadic(Variadic { terms, kind })) => {
+                let op = VARIADIC_MAP.iter().find(|(_, k)| k == kind).unwrap().0;
+                json!({ OP: op, ARGS: terms.iter().map(Self::value).collect::<Vec<_>>() })
+            }
+            Expression::Concrete(Concrete::Unary(Unary { argument, kind })) => {
+                let args = [Self::value(argument)];
+
+                match kind {
+                    UnaryKind::Named { id } => json!({ FUNCTION: id.as_ref(), ARGS: args }),
+                    UnaryKind::Variable { id, relations } => json!({
+                        FUNCTION: id.as_ref(),
+                        RELATIONS: relations.iter().map(FuncId::as_ref).collect::<Vec<_>>(),
+                        ARGS: args
+                    }),
+                    kind => {
+                        let op = UNARY_MAP.iter().find(|(_, k)| k == kind).unwrap().0;
+                        json!({ OP: op, ARGS: args })
+                    }
+                }
+            }
+            Expression::Concrete(Concrete::Power(Power { base, exponent })) => {
+                json!({ OP: POWER, ARGS: [Self::value(base), Self::value(exponent)] })
+            }
+            Expression::Concrete(Concrete::Literal(Literal { value })) => match value {
+                LiteralValue::Integer(i) => json!({ INTEGER: i.to_string() }),
+                LiteralValue::Rational(r) => {
+                    json!({ RATIONAL: format!("{}{RATIONAL_SEPARATOR}{}", r.numer(), r.denom()) })
+                }
+                LiteralValue::Constant(c) => json!({ CONSTANT: c }),
+            },
+            Expression::Variable(Variable { id, kind }) => {
+                match VARIABLE_KIND_MAP.iter().find(|(_, k)| k == kind) {
+                    Some((name, _)) => json!({ VARIABLE: id.as_ref(), KIND: name }),
+                    None => json!({ VARIABLE: id.as_ref() }),
+                }
+            }
+        }
+    }
+
+    pub fn from_value(value: &Value) -> Result<Expression, JsonParseError> {
+        let object = value.as_object().ok_or(JsonParseError::ExpectedObject)?;
+
+        if let Some(op) = object.get(OP) {
+            let op = string(op, OP)?;
+            let args = args(object, op)?;
+
+            let incorrect_args = |_| JsonParseError::IncorrectNumArgs(op.to_string());
+
+            if let Some((_, kind)) = VARIADIC_MAP.iter().find(|(s, _)| *s == op) {
+                return Ok(Variadic::new(args, *kind).into());
+            }
+
+            if op == POWER {
+                let [base, exponent] = <[_; 2]>::try_from(args).map_err(incorrect_args)?;
+                return Ok(Power::new(base, exponent).into());
+            }
+
+            let (_, kind) = UNARY_MAP
+                .iter()
+                .find(|(s, _)| *s == op)
+                .ok_or_else(|| JsonParseError::UnknownOperator(op.to_string()))?;
+            let [argument] = <[_; 1]>::try_from(args).map_err(incorrect_args)?;
+            return Ok(Unary::new(argument, kind.clone()).into());
+        }
+
+        if let Some(id) = object.get(FUNCTION) {
+            let id = string(id, FUNCTION)?;
+            let [argument] = <[_; 1]>::try_from(args(object, id)?)
+                .map_err(|_| JsonParseError::IncorrectNumArgs(id.to_string()))?;
+
+            let kind = match object.get(RELATIONS) {
+                None => UnaryKind::named(id),
+                Some(relations) => UnaryKind::Variable {
+                    id: VarId::new(id),
+                    relations: relations
+                        .as_array()
+                        .ok_or_else(|| JsonParseError::ExpectedString(RELATIONS.to_string()))?
+                        .iter()
+                        .map(|r| {
+                            let r = string(r, RELATIONS)?;
+                            if is_relation(r) {
+                                Ok(FuncId::new(r))
+                            } else {
+                                Err(JsonParseError::UnknownRelation(r.to_string()))
+                            }
+                        })
+                        .collect::<Result<_, _>>()?,
+                },
+            };
+
+            return Ok(Unary::new(argument, kind).into());
+        }
+
+        if let Some(id) = object.get(VARIABLE) {
+            let id = string(id, VARIABLE)?;
+
+            return match object.get(KIND) {
+                None => Ok(Variable::new(id).into()),
+                Some(kind) => {
+                    let kind = string(kind, KIND)?;
+                    let (_, kind) = VARIABLE_KIND_MAP
+                        .iter()
+                        .find(|(s, _)| *s == kind)
+                        .ok_or_else(|| JsonParseError::UnknownVariableKind(kind.to_string()))?;
+                    Ok(Variable::typed(id, *kind).into())
+                }
+            };
+        }
+
+        if let Some(n) = object.get(INTEGER) {
+            let n = string(n, INTEGER)?;
+            let n = n
+                .parse::<BigInt>()
+                .map_err(|_| JsonParseError::InvalidNumber(n.to_string()))?;
+            return Ok(Literal::new(n).into());
+        }
+
+        if let Some(r) = object.get(RATIONAL) {
+            let r = string(r, RATIONAL)?;
+            let invalid = || JsonParseError::InvalidNumber(r.to_string());
+            let (n, d) = r.split_once(RATIONAL_SEPARATOR).ok_or_else(invalid)?;
+            let (n, d) = (
+                n.parse::<BigInt>().map_err(|_| invalid())?,
+                d.parse::<BigInt>().map_err(|_| invalid())?,
+            );
+            if d.is_zero() {
+                return Err(invalid());
+            }
+            return Ok(Literal::new(BigRational::new(n, d)).into());
+        }
+
+        if let Some(c) = object.get(CONSTANT) {
+            return Ok(Literal::new(string(c, CONSTANT)?).into());
+        }
+
+        Err(JsonParseError::UnknownNode(value.to_string()))
+    }
+}
+
+fn string<'a>(value: &'a Value, field: &str) -> Result<&'a str, JsonParseError> {
+    value
+        .as_str()
+        .ok_or_else(|| JsonParseError::ExpectedString(field.to_string()))
+}
+
+fn args(object: &Map<String, Value>, head: &str) -> Result<Vec<Expression>, JsonParseError> {
+    object
+        .get(ARGS)
+        .and_then(Value::as_array)
+        .ok_or_else(|| JsonParseError::IncorrectNumArgs(head.to_string()))?
+        .iter()
+        .map(JsonTree::from_value)
+        .collect()
+}
+
+// everything about one attempt at proving left = right, for other programs to read, expressions
+// are written both as lisp and as trees
+pub fn json_report(
+    left: &Expression,
+    right: &Expression,
+    report: &ProofReport,
+    elapsed: Duration,
+) -> Value {
+    let ProofReport { verdict, stats } = report;
+
+    let verdict = match verdict {
+        Verdict::Proved(proof) => json!({ "result": "proved", "proof": proof_steps(proof) }),
+        Verdict::Equivalent => json!({ "result": "equivalent" }),
+        Verdict::Refuted {
+            assignment,
+            left,
+            right,
+        } => json!({
+            "result": "refuted",
+            "assignment": assignment
+                .iter()
+                .map(|(var, value)| (var.id.as_ref().clone(), json!(value)))
+                .collect::<Map<_, _>>(),
+            "left": left,
+            "right": right
+        }),
+        Verdict::Unproved { reason, plausible } => json!({
+            "result": "unproved",
+            "reason": match reason {
+                Unproved::NoProof => "no_proof",
+                Unproved::BudgetExhausted(_) => "budget_exhausted",
+            },
+            "budget": match reason {
+                Unproved::BudgetExhausted(kind) => json!(budget_name(*kind)),
+                Unproved::NoProof => Value::Null,
+            },
+            "plausible": plausible
+        }),
+    };
+
+    let time = elapsed.as_secs_f64();
+    let (stats, solutions, graph) = match stats {
+        EngineStats::NotRun => (json!({ "engine": null, "time": time }), vec![], Value::Null),
+        EngineStats::Graph {
+            nodes,
+            edges,
+            proofs,
+            diverged,
+            ..
+        } => (
+            json!({ "engine": "graph", "nodes": nodes.len(), "edges": edges.len(), "time": time }),
+            proofs.iter().map(proof_steps).collect(),
+            json!({
+                "nodes": nodes.iter().map(expression).collect::<Vec<_>>(),
+                "edges": edges
+                    .iter()
+                    .map(|(from, to, ruleset)| json!({ "from": from, "to": to, "ruleset": ruleset }))
+                    .collect::<Vec<_>>(),
+                "diverged": diverged.iter().map(divergence).collect::<Vec<_>>()
+            }),
+        ),
+        EngineStats::Egraph {
+            nodes,
+            classes,
+            iterations,
+            truncated,
+            stop_reason,
+        } => (
+            json!({
+                "engine": "egraph",
+                "nodes": nodes,
+                "classes": classes,
+                "iterations": iterations,
+                "truncated": truncated,
+                "stop_reason": match stop_reason {
+                    StopReason::Equivalent => "equivalent",
+                    StopReason::Saturated => "saturated",
+                    StopReason::IterationLimit => "iteration_limit",
+                    StopReason::NodeLimit => "node_limit",
+                    StopReason::TimeLimit => "time_limit",
+                    StopReason::Truncated => "truncated",
+                },
+                "time": time
+            }),
+            vec![],
+            Value::Null,
+        ),
+        EngineStats::BestFirst {
+            expanded,
+            visited,
+            diverged,
+            ..
+        } => (
+            json!({
+                "engine": "best_first",
+                "nodes": visited,
+                "expanded": expanded,
+                "time": time,
+                "diverged": diverged.iter().map(divergence).collect::<Vec<_>>()
+            }),
+            vec![],
+            Value::Null,
+        ),
+    };
+
+    // engines other than the graph only find one solution, the one in the verdict
+    let solutions = match (solutions.is_empty(), &report.verdict) {
+        (true, Verdict::Proved(proof)) => vec![proof_steps(proof)],
+        _ => solutions,
+    };
+
+    json!({
+        "identity": { "left": expression(left), "right": expression(right) },
+        "verdict": verdict,
+        "stats": stats,
+        "solutions": solutions,
+        "graph": graph
+    })
+}
+
+fn expression(expr: &Expression) -> Value {
+    json!({ "lisp": Lisp::format(expr), "tree": JsonTree::value(expr) })
+}
+
+// the start of the proof is the first step, with no ruleset
+fn proof_steps(proof: &Proof) -> Value {
+    let start = json!({ "ruleset": null, "expression": expression(&proof.start) });
+
+    [start]
+        .into_iter()
+        .chain(proof.steps.iter().map(|ProofStep { ruleset, after }| {
+            json!({ "ruleset": ruleset, "expression": expression(after) })
+        }))
+        .collect()
+}
+
+// a ruleset an engine left out on an expression
+fn divergence((expr, ruleset, divergence): &(Expression, String, Divergence)) -> Value {
+    json!({
+        "expression": expression(expr),
+        "ruleset": ruleset,
+        "divergence": divergence.to_string(),
+    })
+}
+
+fn budget_name(kind: BudgetKind) -> &'static str {
+    match kind {
+        BudgetKind::Nodes => "nodes",
+        BudgetKind::Depth => "depth",
+        BudgetKind::Time => "time",
+        BudgetKind::Iterations => "iterations",
+        BudgetKind::Matches => "matches",
+        BudgetKind::Rewrites => "rewrites",
+    }
+}
+
+#[test]
+fn test_json_tree() {
+    for s in [
+        "(+ (^ (sin x) 2) (^ (cos x) 2) 1/2 -3 PI)",
+        "(* ?n:int (/ ?x:var) ?x ((cofunc (recip F)) (- u)))",
+        "(* x x)",
+    ] {
+        let expr = Lisp::parse(s).unwrap();
+        assert_eq!(JsonTree::parse(&JsonTree::format(&expr)), Ok(expr));
+    }
+
+    assert_eq!(
+        JsonTree::value(&Lisp::parse("(^ (sin x) 1/2)").unwrap()),
+        json!({
+            "op": "^",
+            "args": [
+                { "function": "sin", "args": [{ "variable": "x" }] },
+                { "rational": "1/2" }
+            ]
+        })
+    );
+    assert_eq!(
+        JsonTree::parse(r#"{"op": "-", "args": []}"#),
+        Err(JsonParseError::IncorrectNumArgs("-".to_string()))
+    );
+    assert_eq!(
+        JsonTree::parse(
+            r#"{"function": "F", "relations": ["inverse"], "args": [{"integer": "1"}]}"#
+        ),
+        Err(JsonParseError::UnknownRelation("inverse".to_string()))
+    );
+}
+
+#[test]
+fn test_json_report() {
+    let rulesets = RulesetLoader::new().load().unwrap().rules;
+    let left = Lisp::parse("(* a (/ a))").unwrap();
+    let right = Lisp::parse("1").unwrap();
+    let report = IdentityProver::new(rulesets).prove(&left, &right);
+
+    let json = json_report(&left, &right, &report, Duration::ZERO);
+
+    assert_eq!(json["verdict"]["result"], "proved");
+    assert_eq!(json["identity"]["right"]["lisp"], "1");
+    assert_eq!(json["stats"]["engine"], "graph");
+
+    let nodes = json["graph"]["nodes"].as_array().unwrap();
+    assert_eq!(json["stats"]["nodes"], nodes.len());
+    for edge in json["graph"]["edges"].as_array().unwrap() {
+        assert!(edge["from"].as_u64().unwrap() < nodes.len() as u64);
+        assert!(edge["ruleset"].is_string());
+    }
+
+    let solution = json["solutions"][0].as_array().unwrap();
+    assert_eq!(solution[0]["ruleset"], Value::Null);
+    assert_eq!(
+        JsonTree::from_value(&solution.last().unwrap()["expression"]["tree"]),
+        Ok(right)
+    );
+}
@@ -0,0 +1,257 @@
+use crate::{
+    rewrite::*,
+    solve::{Proof, ProofStep},
+    text::*,
+};
+
+pub struct Latex;
+
+const KNOWN_CONSTANTS: [(&str, &str); 2] = [("PI", "\\pi"), ("E", "e")];
+
+impl ExprTextEmitter for Latex {
+    fn format_expr(expr: &Expression) -> VecDeque<SourceToken> {
+        // repeated factors are shown as exponents
+        VecDeque::from([SourceToken::Text(latex(&collect_powers(expr.clone()), 0))])
+    }
+}
+
+// a proof as an align* block, every step is annotated with the name of the ruleset used
+pub fn latex_proof(proof: &Proof) -> String {
+    let mut output = format!("\\begin{{align*}}\n{}", Latex::format(&proof.start));
+
+    for (i, ProofStep { ruleset, after }) in proof.steps.iter().enumerate() {
+        if i > 0 {
+            output.push_str(" \\\\\n");
+        }
+        output.push_str(&format!(
+            " &= {} && \\text{{{}}}",
+            Latex::format(after),
+            ruleset.replace('_', "\\_")
+        ));
+    }
+
+    output.push_str("\n\\end{align*}");
+    output
+}
+
+// known functions get their own command, anything else is set upright
+fn function(id: &str) -> String {
+    if KNOWN_FUNCTIONS.contains(&id) {
+        format!("\\{id}")
+    } else if id.chars().count() == 1 {
+        id.to_string()
+    } else {
+        format!("\\operatorname{{{id}}}")
+    }
+}
+
+fn bracketed(s: &str) -> String {
+    format!("\\left({s}\\right)")
+}
+
+// expr with brackets around it if it binds looser than min_level
+fn latex(expr: &Expression, min_level: u8) -> String {
+    let output = match expr {
+        Expression::Concrete(c) => match c {
+            Concrete::Variadic(Variadic {
+                terms,
+                kind: VariadicKind::Addition,
+            }) => {
+                let (negated, plain): (Vec<_>, Vec<_>) = terms.iter().partition(|t| {
+                    matches!(
+                        t,
+                        Expression::Concrete(Concrete::Unary(Unary {
+                            kind: UnaryKind::Negation,
+                            ..
+                        }))
+                    )
+                });
+
+                if plain.len() + negated.len() == 1 {
+                    return latex(plain.iter().chain(&negated).next().unwrap(), min_level);
+                }
+
+                let mut output = plain
+                    .iter()
+                    .map(|t| latex(t, TERM_LEVEL))
+                    .collect::<Vec<_>>()
+                    .join(" + ");
+
+                for term in negated {
+                    let Expression::Concrete(Concrete::Unary(Unary { argument, .. })) = term else {
+                        unreachable!()
+                    };
+
+                    // a leading minus is just a negation
+                    if output.is_empty() {
+                        output = latex(term, TERM_LEVEL);
+                    } else {
+                        output.push_str(&format!(" - {}", latex(argument, TERM_LEVEL)));
+                    }
+                }
+
+                if output.is_empty() {
+                    "0".to_string()
+                } else {
+                    output
+                }
+            }
+            Concrete::Variadic(Variadic {
+                terms,
+                kind: VariadicKind::Multiplication,
+            }) => {
+                let (reciprocals, mut plain): (Vec<_>, Vec<_>) =
+                    terms.iter().cloned().partition(is_reciprocal);
+
+                if plain.len() + reciprocals.len() == 1 {
+                    return latex(plain.iter().chain(&reciprocals).next().unwrap(), min_level);
+                }
+
+                if !reciprocals.is_empty() {
+                    let denominator = reciprocals.iter().filter_map(denominator).collect();
+
+                    let numerator = if plain.is_empty() {
+                        "1".to_string()
+                    } else {
+                        latex(&product(plain), 0)
+                    };
+
+                    format!(
+                        "\\frac{{{numerator}}}{{{}}}",
+                        latex(&collect_powers(product(denominator)), 0)
+                    )
+                } else if plain.is_empty() {
+                    "1".to_string()
+                } else {
+                    // numbers lead, and are only kept apart from each other with a dot
+                    plain.sort_by_key(|f| !is_number(f));
+
+                    let mut output = String::new();
+
+                    for (i, factor) in plain.iter().enumerate() {
+                        let factor = latex(factor, UNARY_LEVEL);
+                        if i > 0 {
+                            if factor.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+                                output.push_str(" \\cdot ");
+                            } else {
+                                output.push(' ');
+                            }
+                        }
+                        output.push_str(&factor);
+                    }
+
+                    output
+                }
+            }
+            Concrete::Unary(Unary { argument, kind }) => match kind {
+                UnaryKind::Negation => format!("-{}", latex(argument, POWER_LEVEL)),
+                UnaryKind::Reciprocal => format!("\\frac{{1}}{{{}}}", latex(argument, 0)),
+                UnaryKind::Named { id } => {
+                    format!(
+                        "{}{}",
+                        function(id.as_ref()),
+                        bracketed(&latex(argument, 0))
+                    )
+                }
+                UnaryKind::Variable { id, relations } => {
+                    let func = relations.iter().rev().fold(function(id.as_ref()), |f, r| {
+                        format!("{}{}", function(r.as_ref()), bracketed(&f))
+                    });
+                    format!("{func}{}", bracketed(&latex(argument, 0)))
+                }
+            },
+            Concrete::Power(Power { base, exponent }) => {
+                let half = Literal::new(BigRational::new(1.into(), 2.into())).into();
+
+                match base.as_ref() {
+                    _ if is_reciprocal(base) => {
+                        let denominator = denominator(expr).unwrap();
+                        format!("\\frac{{1}}{{{}}}", latex(&denominator, 0))
+                    }
+                    _ if **exponent == half => format!("\\sqrt{{{}}}", latex(base, 0)),
+                    // written like \sin^{2}\left(x\right)
+                    Expression::Concrete(Concrete::Unary(Unary {
+                        argument,
+                        kind: UnaryKind::Named { id },
+                    })) => format!(
+                        "{}^{{{}}}{}",
+                        function(id.as_ref()),
+                        latex(exponent, 0),
+                        bracketed(&latex(argument, 0))
+                    ),
+                    _ => format!("{}^{{{}}}", latex(base, ATOM_LEVEL), latex(exponent, 0)),
+                }
+            }
+            Concrete::Literal(Literal { value }) => match value {
+                LiteralValue::Integer(n) => n.to_string(),
+                LiteralValue::Rational(r) => {
+                    let sign = if r.is_negative() { "-" } else { "" };
+                    format!("{sign}\\frac{{{}}}{{{}}}", r.numer().abs(), r.denom())
+                }
+                LiteralValue::Constant(c) => KNOWN_CONSTANTS
+                    .iter()
+                    .find(|(name, _)| name == c)
+                    .map(|(_, latex)| latex.to_string())
+                    .unwrap_or_else(|| format!("\\mathrm{{{c}}}")),
+            },
+        },
+        Expression::Variable(Variable { id, .. }) => match id.as_ref().as_str() {
+            REST => "\\ldots".to_string(),
+            id if id.chars().count() == 1 => id.to_string(),
+            id => format!("\\mathit{{{id}}}"),
+        },
+    };
+
+    if fraction_level(expr) < min_level {
+        bracketed(&output)
+    } else {
+        output
+    }
+}
+
+#[test]
+fn test_latex() {
+    let cases = [
+        (
+            "(+ (* (sin x) (sin x)) (^ (cos x) 2))",
+            "\\cos^{2}\\left(x\\right) + \\sin^{2}\\left(x\\right)",
+        ),
+        ("(+ a (- b) (- c))", "a - b - c"),
+        ("(+ (- a) b)", "b - a"),
+        ("(* a (/ (+ b c)))", "\\frac{a}{b + c}"),
+        ("(* (/ x) (/ x) 2)", "\\frac{2}{x^{2}}"),
+        ("(/ (sin x))", "\\frac{1}{\\sin\\left(x\\right)}"),
+        ("(* x 2 (+ x 1))", "2 \\left(1 + x\\right) x"),
+        ("(* 2 -3)", "-3 \\cdot 2"),
+        ("(- (+ a b))", "-\\left(a + b\\right)"),
+        ("(^ (+ a b) (+ n 1))", "\\left(a + b\\right)^{1 + n}"),
+        ("(^ x 1/2)", "\\sqrt{x}"),
+        ("(* -3/4 PI)", "-\\frac{3}{4} \\pi"),
+        ("(^ 1/2 n)", "\\left(\\frac{1}{2}\\right)^{n}"),
+        ("(+ (foo theta) ((recip F) u))", "\\operatorname{foo}\\left(\\mathit{theta}\\right) + \\operatorname{recip}\\left(F\\right)\\left(u\\right)"),
+    ];
+
+    for (lisp, latex) in cases {
+        assert_eq!(Latex::format(&Lisp::parse(lisp).unwrap()), latex, "{lisp}");
+    }
+}
+
+#[test]
+fn test_latex_proof() {
+    let step = |ruleset: &str, after| ProofStep {
+        ruleset: ruleset.to_string(),
+        after: Lisp::parse(after).unwrap(),
+    };
+    let proof = Proof {
+        start: Lisp::parse("(+ (^ (sin x) 2) (^ (cos x) 2))").unwrap(),
+        steps: vec![step("trig_pyth", "1"), step("cancel", "(* x (/ x))")],
+    };
+
+    assert_eq!(
+        latex_proof(&proof),
+        "\\begin{align*}\n\
+         \\cos^{2}\\left(x\\right) + \\sin^{2}\\left(x\\right) &= 1 && \\text{trig\\_pyth} \\\\\n \
+         &= \\frac{x}{x} && \\text{cancel}\n\
+         \\end{align*}"
+    );
+}
@@ -3,8 +3,12 @@ use crate::{rewrite::*, text::*};
 pub struct Lisp;
 
 // lisp grammar:
-// expr := (func expr*) | lit | var
-// ruleset := (=> expr expr)*
+// expr := (func expr*) | (^ expr expr) | lit | var
+// func := name | op | FUNCVAR | (relation func)
+// var := name | ?name | ?name:kind
+// ruleset := (=> expr expr guard?)*
+// guard := (if pred*)
+// pred := (name var*) | (not pred)
 
 const COMMENT: &str = ";";
 
@@ -19,58 +23,130 @@ pub enum LispParseError {
     NotAFunction,
     ExpectedBracket,
     ExpectedRuleOp,
+    InvalidGuard,
+    InvalidVariable,
+    UnknownRelation,
+    TrailingInput,
 }
 
-impl ExprTextFormat<LispParseError> for Lisp {
-    fn parse_tokens(tokens: &mut VecDeque<SourceToken>) -> Result<Expression, LispParseError> {
+impl std::fmt::Display for LispParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            LispParseError::EmptyString => "expected an expression",
+            LispParseError::IncorrectBrackets => "unexpected closing bracket",
+            LispParseError::EmptyFuncBody => "empty brackets",
+            LispParseError::UnclosedBracket => "unclosed bracket",
+            LispParseError::IncorrectNumArgs => "wrong number of arguments",
+            LispParseError::ReservedOperator => "operator used outside of function position",
+            LispParseError::NotAFunction => "expected a function",
+            LispParseError::ExpectedBracket => "expected an opening bracket",
+            LispParseError::ExpectedRuleOp => "expected a rule operator",
+            LispParseError::InvalidGuard => "invalid guard",
+            LispParseError::InvalidVariable => "invalid variable",
+            LispParseError::UnknownRelation => "unknown relation",
+            LispParseError::TrailingInput => "unexpected input after the expression",
+        };
+        f.write_str(message)
+    }
+}
+
+impl ExprTextFormat<ParseError<LispParseError>> for Lisp {
+    fn parse(s: &str) -> Result<Expression, ParseError<LispParseError>> {
+        let mut tokens = Self::tokenize(s);
+        let expr = Self::parse_tokens(&mut tokens)?;
+
+        if tokens.pop_front().is_some() {
+            return Err(tokens.error(LispParseError::TrailingInput));
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_tokens(tokens: &mut TokenStream) -> Result<Expression, ParseError<LispParseError>> {
         if let Some(token) = tokens.pop_front() {
             match token {
                 SourceToken::Literal(l) => Ok(Literal::new(l).into()),
+                SourceToken::Text(t) if t.starts_with(VARIABLE_PREFIX) => {
+                    Err(tokens.error(LispParseError::InvalidVariable))
+                }
                 SourceToken::Text(t) => Ok(Variable::new(t).into()),
+                SourceToken::Variable(v) => Ok(v.into()),
                 SourceToken::LGroup => {
+                    let open = tokens.span();
+
                     if let Some(func) = tokens.pop_front() {
+                        let func_span = tokens.span();
+                        // function variables stand in for named functions
+                        let func = match func {
+                            SourceToken::LGroup => {
+                                SourceToken::UnaryOperator(parse_tokens_function(tokens)?)
+                            }
+                            SourceToken::Literal(LiteralValue::Constant(id)) => {
+                                SourceToken::UnaryOperator(UnaryKind::Variable {
+                                    id: VarId::new(id),
+                                    relations: Vec::new(),
+                                })
+                            }
+                            func => func,
+                        };
                         let mut args = Vec::new();
 
                         loop {
                             match Self::parse_tokens(tokens) {
                                 Ok(expr) => args.push(expr),
-                                Err(LispParseError::IncorrectBrackets) => break,
-                                Err(LispParseError::EmptyString) => {
-                                    return Err(LispParseError::UnclosedBracket)
-                                }
+                                Err(ParseError {
+                                    kind: LispParseError::IncorrectBrackets,
+                                    ..
+                                }) => break,
+                                Err(ParseError {
+                                    kind: LispParseError::EmptyString,
+                                    ..
+                                }) => return Err(open.error(LispParseError::UnclosedBracket)),
                                 Err(e) => return Err(e),
                             }
                         }
 
+                        // the whole group, up to the closing bracket
+                        let group = open.to(tokens.span());
+
                         if let SourceToken::VariadicOperator(kind) = func {
                             Ok(Variadic::new(args, kind).into())
+                        } else if SourceToken::PowerOperator == func {
+                            let Ok([base, exponent]) = <_ as TryInto<[_; 2]>>::try_into(args)
+                            else {
+                                return Err(group.error(LispParseError::IncorrectNumArgs));
+                            };
+                            Ok(Power::new(base, exponent).into())
                         } else if let Ok([arg]) = <_ as TryInto<[_; 1]>>::try_into(args) {
                             if let SourceToken::UnaryOperator(kind) = func {
                                 Ok(Unary::new(arg, kind).into())
                             } else if let SourceToken::Text(kind) = func {
                                 Ok(Unary::new(arg, UnaryKind::named(kind)).into())
                             } else {
-                                Err(LispParseError::NotAFunction)
+                                Err(func_span.error(LispParseError::NotAFunction))
                             }
                         } else {
-                            Err(LispParseError::IncorrectNumArgs)
+                            Err(group.error(LispParseError::IncorrectNumArgs))
                         }
                     } else {
-                        Err(LispParseError::EmptyFuncBody)
+                        Err(open.error(LispParseError::EmptyFuncBody))
                     }
                 }
-                SourceToken::RGroup => Err(LispParseError::IncorrectBrackets),
-                SourceToken::VariadicOperator(_) => Err(LispParseError::ReservedOperator),
-                SourceToken::UnaryOperator(_) => Err(LispParseError::ReservedOperator),
-                SourceToken::RuleOperator(_) => {
-                    panic!("rules are not allowed to appear in expressions")
+                SourceToken::RGroup => Err(tokens.error(LispParseError::IncorrectBrackets)),
+                SourceToken::VariadicOperator(_)
+                | SourceToken::UnaryOperator(_)
+                | SourceToken::PowerOperator
+                | SourceToken::RuleOperator(_) => {
+                    Err(tokens.error(LispParseError::ReservedOperator))
                 }
             }
         } else {
-            Err(LispParseError::EmptyString)
+            Err(tokens.error(LispParseError::EmptyString))
         }
     }
+}
 
+impl ExprTextEmitter for Lisp {
     fn format_expr(expr: &Expression) -> VecDeque<SourceToken> {
         let mut output = VecDeque::new();
 
@@ -96,38 +172,63 @@ impl ExprTextFormat<LispParseError> for Lisp {
                     output.append(&mut Self::format_expr(argument));
                     output.push_back(SourceToken::RGroup);
                 }
+                Concrete::Power(Power { base, exponent }) => {
+                    output.extend([
+                        SourceToken::LGroup,
+                        SourceToken::PowerOperator,
+                        SourceToken::Text(" ".to_string()),
+                    ]);
+
+                    output.append(&mut Self::format_expr(base));
+                    output.push_back(SourceToken::Text(" ".to_string()));
+                    output.append(&mut Self::format_expr(exponent));
+                    output.push_back(SourceToken::RGroup);
+                }
                 Concrete::Literal(Literal { value }) => {
                     output.push_back(SourceToken::Literal(value.clone()));
                 }
             },
-            Expression::Variable(Variable { id }) => {
-                output.push_back(SourceToken::Text(id.as_ref().to_string()))
-            }
+            Expression::Variable(v) => output.push_back(SourceToken::Variable(v.clone())),
         }
 
         output
     }
 }
 
-impl RuleTextFormat<LispParseError> for Lisp {
-    fn parse_tokens_rule(tokens: &mut VecDeque<SourceToken>) -> Result<Rule, LispParseError> {
-        if SourceToken::LGroup != tokens.pop_front().ok_or(LispParseError::EmptyString)? {
-            return Err(LispParseError::ExpectedBracket);
+impl RuleTextFormat<ParseError<LispParseError>> for Lisp {
+    fn parse_tokens_rule(tokens: &mut TokenStream) -> Result<Rule, ParseError<LispParseError>> {
+        if Some(SourceToken::LGroup) != tokens.pop_front() {
+            return Err(tokens.error(LispParseError::ExpectedBracket));
         }
 
-        let SourceToken::RuleOperator(kind) = tokens.pop_front().ok_or(LispParseError::EmptyFuncBody)? else {return Err(LispParseError::ExpectedRuleOp)};
+        let open = tokens.span();
+
+        let Some(SourceToken::RuleOperator(kind)) = tokens.pop_front() else {return Err(tokens.error(LispParseError::ExpectedRuleOp))};
 
         let pattern = Self::parse_tokens(tokens)?;
         let replacement = Self::parse_tokens(tokens)?;
 
-        if SourceToken::RGroup != tokens.pop_front().ok_or(LispParseError::UnclosedBracket)? {
-            return Err(LispParseError::IncorrectNumArgs);
+        let guard = if tokens.front() == Some(&SourceToken::LGroup) {
+            Some(parse_tokens_guard(tokens)?)
+        } else {
+            None
+        };
+
+        match tokens.pop_front() {
+            Some(SourceToken::RGroup) => {}
+            Some(_) => {
+                return Err(open
+                    .to(tokens.span())
+                    .error(LispParseError::IncorrectNumArgs))
+            }
+            None => return Err(open.error(LispParseError::UnclosedBracket)),
         }
 
         Ok(Rule {
             pattern,
             replacement,
             kind,
+            guard,
         })
     }
 
@@ -136,6 +237,7 @@ impl RuleTextFormat<LispParseError> for Lisp {
             pattern,
             replacement,
             kind,
+            guard,
         } = rule;
         let mut output = VecDeque::new();
 
@@ -151,6 +253,21 @@ impl RuleTextFormat<LispParseError> for Lisp {
 
         output.append(&mut Self::format_expr(replacement));
 
+        if let Some(Guard { predicates }) = guard {
+            output.extend([
+                SourceToken::Text(" ".to_string()),
+                SourceToken::LGroup,
+                SourceToken::Text(GUARD_KEYWORD.to_string()),
+            ]);
+
+            for predicate in predicates {
+                output.push_back(SourceToken::Text(" ".to_string()));
+                output.append(&mut format_predicate_tokens(predicate));
+            }
+
+            output.push_back(SourceToken::RGroup);
+        }
+
         output.push_back(SourceToken::RGroup);
 
         output
@@ -160,3 +277,128 @@ impl RuleTextFormat<LispParseError> for Lisp {
         COMMENT
     }
 }
+
+// (relation func) after its opening bracket, as a function variable
+fn parse_tokens_function(
+    tokens: &mut TokenStream,
+) -> Result<UnaryKind, ParseError<LispParseError>> {
+    let open = tokens.span();
+
+    let Some(SourceToken::Text(relation)) = tokens.pop_front() else {
+        return Err(tokens.error(LispParseError::NotAFunction));
+    };
+
+    if !is_relation(&relation) {
+        return Err(tokens.error(LispParseError::UnknownRelation));
+    }
+
+    let (id, mut relations) = match tokens.pop_front() {
+        Some(SourceToken::Literal(LiteralValue::Constant(id))) => (VarId::new(id), Vec::new()),
+        Some(SourceToken::LGroup) => {
+            let UnaryKind::Variable { id, relations } = parse_tokens_function(tokens)? else {
+                unreachable!()
+            };
+            (id, relations)
+        }
+        Some(_) => return Err(tokens.error(LispParseError::NotAFunction)),
+        None => return Err(open.error(LispParseError::UnclosedBracket)),
+    };
+
+    match tokens.pop_front() {
+        Some(SourceToken::RGroup) => {}
+        Some(_) => {
+            return Err(open
+                .to(tokens.span())
+                .error(LispParseError::IncorrectNumArgs))
+        }
+        None => return Err(open.error(LispParseError::UnclosedBracket)),
+    }
+
+    relations.insert(0, FuncId::new(relation));
+
+    Ok(UnaryKind::Variable { id, relations })
+}
+
+fn parse_tokens_guard(tokens: &mut TokenStream) -> Result<Guard, ParseError<LispParseError>> {
+    if Some(SourceToken::LGroup) != tokens.pop_front() {
+        return Err(tokens.error(LispParseError::ExpectedBracket));
+    }
+
+    let open = tokens.span();
+
+    if Some(SourceToken::Text(GUARD_KEYWORD.to_string())) != tokens.pop_front() {
+        return Err(tokens.error(LispParseError::InvalidGuard));
+    }
+
+    let mut predicates = Vec::new();
+
+    loop {
+        match tokens.front() {
+            Some(SourceToken::RGroup) => {
+                tokens.pop_front();
+                break Ok(Guard { predicates });
+            }
+            Some(_) => predicates.push(parse_tokens_predicate(tokens)?),
+            None => break Err(open.error(LispParseError::UnclosedBracket)),
+        }
+    }
+}
+
+fn parse_tokens_predicate(
+    tokens: &mut TokenStream,
+) -> Result<Predicate, ParseError<LispParseError>> {
+    if Some(SourceToken::LGroup) != tokens.pop_front() {
+        return Err(tokens.error(LispParseError::ExpectedBracket));
+    }
+
+    let open = tokens.span();
+
+    let Some(SourceToken::Text(name)) = tokens.pop_front() else {return Err(tokens.error(LispParseError::InvalidGuard))};
+    let name_span = tokens.span();
+
+    let predicate = if name == "not" {
+        Predicate::Not(Box::new(parse_tokens_predicate(tokens)?))
+    } else {
+        let mut args = Vec::new();
+
+        while let Some(SourceToken::Text(_) | SourceToken::Variable(_)) = tokens.front() {
+            match tokens.pop_front() {
+                Some(SourceToken::Text(arg)) => args.push(Variable::new(arg)),
+                Some(SourceToken::Variable(arg)) => args.push(arg.untyped()),
+                _ => unreachable!(),
+            }
+        }
+
+        Predicate::from_name(&name, args)
+            .ok_or_else(|| name_span.error(LispParseError::InvalidGuard))?
+    };
+
+    match tokens.pop_front() {
+        Some(SourceToken::RGroup) => {}
+        Some(_) => return Err(tokens.error(LispParseError::InvalidGuard)),
+        None => return Err(open.error(LispParseError::UnclosedBracket)),
+    }
+
+    Ok(predicate)
+}
+
+fn format_predicate_tokens(predicate: &Predicate) -> VecDeque<SourceToken> {
+    let mut output = VecDeque::from([
+        SourceToken::LGroup,
+        SourceToken::Text(predicate.name().to_string()),
+    ]);
+
+    if let Predicate::Not(inner) = predicate {
+        output.push_back(SourceToken::Text(" ".to_string()));
+        output.append(&mut format_predicate_tokens(inner));
+    }
+
+    for Variable { id, .. } in predicate.args() {
+        output.push_back(SourceToken::Text(" ".to_string()));
+        output.push_back(SourceToken::Text(id.as_ref().to_string()));
+    }
+
+    output.push_back(SourceToken::RGroup);
+
+    output
+}
@@ -0,0 +1,653 @@
+use {
+    crate::{
+        rewrite::*,
+        solve::{Proof, ProofStep},
+        text::*,
+    },
+    roxmltree::{Document, Node},
+};
+
+// Content MathML describes what an expression means and can be read back, Presentation
+// MathML only describes how it looks
+
+pub struct ContentMathml;
+pub struct PresentationMathml;
+
+const NAMESPACE: &str = "http://www.w3.org/1998/Math/MathML";
+const KNOWN_CONSTANTS: [(&str, &str, &str); 2] = [
+    ("PI", "pi", "<mi>&#x3C0;</mi>"),
+    ("E", "exponentiale", "<mi>e</mi>"),
+];
+// head of an apply for a function that isn't a content element of its own
+const FUNCTION_TYPE: &str = "function";
+const INVISIBLE_TIMES: &str = "<mo>&#x2062;</mo>";
+const APPLY_FUNCTION: &str = "<mo>&#x2061;</mo>";
+
+#[derive(Debug, PartialEq)]
+pub enum MathmlParseError {
+    Xml(String),
+    UnsupportedElement(String),
+    EmptyApply,
+    IncorrectNumArgs(String),
+    InvalidNumber(String),
+    InvalidVariable(String),
+    UnknownRelation(String),
+    ExpectedEquation,
+}
+
+impl ExprTextEmitter for ContentMathml {
+    fn format_expr(expr: &Expression) -> VecDeque<SourceToken> {
+        VecDeque::from([SourceToken::Text(format!(
+            "<math xmlns=\"{NAMESPACE}\">{}</math>",
+            content(expr)
+        ))])
+    }
+}
+
+impl ContentMathml {
+    pub fn parse(s: &str) -> Result<Expression, MathmlParseError> {
+        let document = Document::parse(s).map_err(|e| MathmlParseError::Xml(e.to_string()))?;
+        parse_node(root(&document)?)
+    }
+
+    // an identity written as <apply><eq/> left right </apply>
+    pub fn parse_equation(s: &str) -> Result<Rule, MathmlParseError> {
+        let document = Document::parse(s).map_err(|e| MathmlParseError::Xml(e.to_string()))?;
+        let node = root(&document)?;
+        let children = elements(node);
+
+        match children.as_slice() {
+            [head, left, right] if node.has_tag_name("apply") && head.has_tag_name("eq") => {
+                Ok(Rule {
+                    pattern: parse_node(*left)?,
+                    replacement: parse_node(*right)?,
+                    kind: RuleKind::Equality,
+                    guard: None,
+                })
+            }
+            _ => Err(MathmlParseError::ExpectedEquation),
+        }
+    }
+}
+
+impl ExprTextEmitter for PresentationMathml {
+    fn format_expr(expr: &Expression) -> VecDeque<SourceToken> {
+        VecDeque::from([SourceToken::Text(format!(
+            "<math xmlns=\"{NAMESPACE}\">{}</math>",
+            Self::element(expr)
+        ))])
+    }
+}
+
+impl PresentationMathml {
+    // a single element without the math wrapper, repeated factors are shown as exponents
+    pub fn element(expr: &Expression) -> String {
+        presentation(&collect_powers(expr.clone()), 0)
+    }
+}
+
+// a proof as a table with one step per row, annotated with the name of the ruleset used
+pub fn mathml_proof(proof: &Proof) -> String {
+    let mut output = format!("<math xmlns=\"{NAMESPACE}\" display=\"block\"><mtable>");
+
+    for (i, ProofStep { ruleset, after }) in proof.steps.iter().enumerate() {
+        let before = if i == 0 {
+            PresentationMathml::element(&proof.start)
+        } else {
+            String::new()
+        };
+
+        output.push_str(&format!(
+            "<mtr><mtd>{before}</mtd><mtd><mo>=</mo>{}</mtd><mtd><mtext>{}</mtext></mtd></mtr>",
+            PresentationMathml::element(after),
+            escape(ruleset)
+        ));
+    }
+
+    // a proof of an expression equal to itself is just the expression
+    if proof.steps.is_empty() {
+        output.push_str(&format!(
+            "<mtr><mtd>{}</mtd></mtr>",
+            PresentationMathml::element(&proof.start)
+        ));
+    }
+
+    output.push_str("</mtable></math>");
+    output
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn root<'a>(document: &'a Document) -> Result<Node<'a, 'a>, MathmlParseError> {
+    let node = document.root_element();
+
+    if !node.has_tag_name("math") {
+        return Ok(node);
+    }
+
+    match elements(node).as_slice() {
+        [node] => Ok(*node),
+        _ => Err(MathmlParseError::IncorrectNumArgs("math".to_string())),
+    }
+}
+
+fn elements<'a>(node: Node<'a, 'a>) -> Vec<Node<'a, 'a>> {
+    node.children().filter(Node::is_element).collect()
+}
+
+fn text(node: Node) -> String {
+    node.text().unwrap_or_default().trim().to_string()
+}
+
+fn parse_node(node: Node) -> Result<Expression, MathmlParseError> {
+    let name = node.tag_name().name();
+
+    match name {
+        "apply" => parse_apply(node),
+        "ci" => {
+            let id = text(node);
+            let kind = node.attribute("type");
+
+            match kind.map(|k| VARIABLE_KIND_MAP.iter().find(|(name, _)| *name == k)) {
+                _ if id.is_empty() => Err(MathmlParseError::InvalidVariable(id)),
+                None => Ok(Variable::new(id).into()),
+                Some(Some((_, kind))) => Ok(Variable::typed(id, *kind).into()),
+                Some(None) => Err(MathmlParseError::InvalidVariable(id)),
+            }
+        }
+        "cn" => parse_number(node),
+        // annotated expressions from other tools, only the first child is the expression
+        "semantics" => parse_node(
+            *elements(node)
+                .first()
+                .ok_or(MathmlParseError::IncorrectNumArgs(name.to_string()))?,
+        ),
+        _ => match KNOWN_CONSTANTS
+            .iter()
+            .find(|(_, element, _)| *element == name)
+        {
+            Some((constant, _, _)) => Ok(Literal::new(*constant).into()),
+            None => Err(MathmlParseError::UnsupportedElement(name.to_string())),
+        },
+    }
+}
+
+fn parse_number(node: Node) -> Result<Expression, MathmlParseError> {
+    let invalid = || MathmlParseError::InvalidNumber(text(node));
+
+    let value = match node.attribute("type") {
+        None | Some("integer") | Some("real") => {
+            let s = text(node);
+
+            if let Ok(n) = s.parse::<BigInt>() {
+                LiteralValue::Integer(n)
+            } else {
+                // decimals are exact, 2.5 is 5/2
+                let (whole, fraction) = s.split_once('.').ok_or_else(invalid)?;
+                let digits: BigInt = format!("{whole}{fraction}")
+                    .parse()
+                    .map_err(|_| invalid())?;
+                BigRational::new(digits, BigInt::from(10).pow(fraction.len() as u32)).into()
+            }
+        }
+        // numerator<sep/>denominator
+        Some("rational") => {
+            let parts: Vec<_> = node
+                .children()
+                .filter(Node::is_text)
+                .map(|n| n.text().unwrap_or_default().trim().parse::<BigInt>())
+                .collect();
+
+            match parts.as_slice() {
+                [Ok(n), Ok(d)]
+                    if !d.is_zero() && node.children().any(|n| n.has_tag_name("sep")) =>
+                {
+                    BigRational::new(n.clone(), d.clone()).into()
+                }
+                _ => return Err(invalid()),
+            }
+        }
+        Some("constant") if !text(node).is_empty() => LiteralValue::Constant(text(node)),
+        Some(_) => return Err(invalid()),
+    };
+
+    Ok(Literal::new(value).into())
+}
+
+fn parse_apply(node: Node) -> Result<Expression, MathmlParseError> {
+    let children = elements(node);
+    let (head, args) = children.split_first().ok_or(MathmlParseError::EmptyApply)?;
+    let name = head.tag_name().name();
+
+    let mut args = args
+        .iter()
+        .map(|n| parse_node(*n))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let incorrect = || MathmlParseError::IncorrectNumArgs(name.to_string());
+
+    // binary minus and divide are desugared the same way as in infix, 1 / b is just (/ b)
+    let kind = match (name, args.len()) {
+        ("plus", _) => return Ok(Variadic::new(args, VariadicKind::Addition).into()),
+        ("times", _) => return Ok(Variadic::new(args, VariadicKind::Multiplication).into()),
+        ("minus", 1) => UnaryKind::Negation,
+        ("minus", 2) => {
+            let negated = Unary::new(args.pop().unwrap(), UnaryKind::Negation).into();
+            return Ok(
+                Variadic::new([args.pop().unwrap(), negated], VariadicKind::Addition).into(),
+            );
+        }
+        ("divide", 2) => {
+            let inverted: Expression =
+                Unary::new(args.pop().unwrap(), UnaryKind::Reciprocal).into();
+            return match args.pop().unwrap() {
+                one if one == Literal::new(1).into() => Ok(inverted),
+                numerator => {
+                    Ok(Variadic::new([numerator, inverted], VariadicKind::Multiplication).into())
+                }
+            };
+        }
+        ("power", 2) => {
+            let exponent = args.pop().unwrap();
+            return Ok(Power::new(args.pop().unwrap(), exponent).into());
+        }
+        ("minus" | "divide" | "power", _) => return Err(incorrect()),
+        (name, _) if KNOWN_FUNCTIONS.contains(&name) => UnaryKind::named(name),
+        ("ci" | "apply", _) => parse_function(*head)?,
+        _ => return Err(MathmlParseError::UnsupportedElement(name.to_string())),
+    };
+
+    match <_ as TryInto<[_; 1]>>::try_into(args) {
+        Ok([arg]) => Ok(Unary::new(arg, kind).into()),
+        Err(_) => Err(incorrect()),
+    }
+}
+
+// <ci>f</ci> as a named function, or a function variable if it's all uppercase like in lisp,
+// and <apply><csymbol>relation</csymbol> func </apply> for a related function variable
+fn parse_function(node: Node) -> Result<UnaryKind, MathmlParseError> {
+    if node.has_tag_name("ci") {
+        let id = text(node);
+
+        return if id.is_empty() {
+            Err(MathmlParseError::InvalidVariable(id))
+        } else if id.chars().all(|c| c.is_uppercase()) {
+            Ok(UnaryKind::Variable {
+                id: VarId::new(id),
+                relations: Vec::new(),
+            })
+        } else {
+            Ok(UnaryKind::named(id))
+        };
+    }
+
+    let [relation, func] = elements(node)[..] else {
+        return Err(MathmlParseError::IncorrectNumArgs("apply".to_string()));
+    };
+
+    if !relation.has_tag_name("csymbol") || !is_relation(&text(relation)) {
+        return Err(MathmlParseError::UnknownRelation(text(relation)));
+    }
+
+    match parse_function(func)? {
+        UnaryKind::Variable { id, mut relations } => {
+            relations.insert(0, FuncId::new(text(relation)));
+            Ok(UnaryKind::Variable { id, relations })
+        }
+        _ => Err(MathmlParseError::UnknownRelation(text(relation))),
+    }
+}
+
+fn content(expr: &Expression) -> String {
+    match expr {
+        Expression::Concrete(c) => match c {
+            Concrete::Variadic(Variadic { terms, kind }) => {
+                let head = match kind {
+                    VariadicKind::Addition => "<plus/>",
+                    VariadicKind::Multiplication => "<times/>",
+                };
+                let terms: String = terms.iter().map(content).collect();
+                format!("<apply>{head}{terms}</apply>")
+            }
+            Concrete::Unary(Unary { argument, kind }) => {
+                let head = match kind {
+                    UnaryKind::Negation => "<minus/>".to_string(),
+                    UnaryKind::Reciprocal => "<divide/><cn type=\"integer\">1</cn>".to_string(),
+                    UnaryKind::Named { id } if KNOWN_FUNCTIONS.contains(&id.as_ref().as_str()) => {
+                        format!("<{}/>", id.as_ref())
+                    }
+                    UnaryKind::Named { id } => {
+                        format!("<ci type=\"{FUNCTION_TYPE}\">{}</ci>", escape(id.as_ref()))
+                    }
+                    UnaryKind::Variable { id, relations } => relations.iter().rev().fold(
+                        format!("<ci type=\"{FUNCTION_TYPE}\">{}</ci>", escape(id.as_ref())),
+                        |f, r| format!("<apply><csymbol>{}</csymbol>{f}</apply>", r.as_ref()),
+                    ),
+                };
+                format!("<apply>{head}{}</apply>", content(argument))
+            }
+            Concrete::Power(Power { base, exponent }) => {
+                format!(
+                    "<apply><power/>{}{}</apply>",
+                    content(base),
+                    content(exponent)
+                )
+            }
+            Concrete::Literal(Literal { value }) => match value {
+                LiteralValue::Integer(n) => format!("<cn type=\"integer\">{n}</cn>"),
+                LiteralValue::Rational(r) => {
+                    format!(
+                        "<cn type=\"rational\">{}<sep/>{}</cn>",
+                        r.numer(),
+                        r.denom()
+                    )
+                }
+                LiteralValue::Constant(c) => {
+                    match KNOWN_CONSTANTS
+                        .iter()
+                        .find(|(constant, _, _)| constant == c)
+                    {
+                        Some((_, element, _)) => format!("<{element}/>"),
+                        None => format!("<cn type=\"constant\">{}</cn>", escape(c)),
+                    }
+                }
+            },
+        },
+        Expression::Variable(Variable { id, kind }) => {
+            match VARIABLE_KIND_MAP.iter().find(|(_, k)| k == kind) {
+                Some((name, _)) => format!("<ci type=\"{name}\">{}</ci>", escape(id.as_ref())),
+                None => format!("<ci>{}</ci>", escape(id.as_ref())),
+            }
+        }
+    }
+}
+
+fn row(s: &str) -> String {
+    format!("<mrow>{s}</mrow>")
+}
+
+fn bracketed(s: &str) -> String {
+    row(&format!("<mo>(</mo>{s}<mo>)</mo>"))
+}
+
+fn function(id: &str) -> String {
+    format!("<mi>{}</mi>", escape(id))
+}
+
+// expr with brackets around it if it binds looser than min_level, always a single element
+fn presentation(expr: &Expression, min_level: u8) -> String {
+    let output = match expr {
+        Expression::Concrete(c) => match c {
+            Concrete::Variadic(Variadic {
+                terms,
+                kind: VariadicKind::Addition,
+            }) => {
+                let (negated, plain): (Vec<_>, Vec<_>) = terms.iter().partition(|t| {
+                    matches!(
+                        t,
+                        Expression::Concrete(Concrete::Unary(Unary {
+                            kind: UnaryKind::Negation,
+                            ..
+                        }))
+                    )
+                });
+
+                if plain.len() + negated.len() == 1 {
+                    return presentation(plain.iter().chain(&negated).next().unwrap(), min_level);
+                }
+
+                let mut output = plain
+                    .iter()
+                    .map(|t| presentation(t, TERM_LEVEL))
+                    .collect::<Vec<_>>()
+                    .join("<mo>+</mo>");
+
+                for term in negated {
+                    let Expression::Concrete(Concrete::Unary(Unary { argument, .. })) = term else {
+                        unreachable!()
+                    };
+
+                    // a leading minus is just a negation
+                    if output.is_empty() {
+                        output = presentation(term, TERM_LEVEL);
+                    } else {
+                        output
+                            .push_str(&format!("<mo>-</mo>{}", presentation(argument, TERM_LEVEL)));
+                    }
+                }
+
+                if output.is_empty() {
+                    "<mn>0</mn>".to_string()
+                } else {
+                    row(&output)
+                }
+            }
+            Concrete::Variadic(Variadic {
+                terms,
+                kind: VariadicKind::Multiplication,
+            }) => {
+                let (reciprocals, mut plain): (Vec<_>, Vec<_>) =
+                    terms.iter().cloned().partition(is_reciprocal);
+
+                if plain.len() + reciprocals.len() == 1 {
+                    return presentation(
+                        plain.iter().chain(&reciprocals).next().unwrap(),
+                        min_level,
+                    );
+                }
+
+                if !reciprocals.is_empty() {
+                    let denominator = reciprocals.iter().filter_map(denominator).collect();
+
+                    let numerator = if plain.is_empty() {
+                        "<mn>1</mn>".to_string()
+                    } else {
+                        presentation(&product(plain), 0)
+                    };
+
+                    format!(
+                        "<mfrac>{numerator}{}</mfrac>",
+                        presentation(&collect_powers(product(denominator)), 0)
+                    )
+                } else if plain.is_empty() {
+                    "<mn>1</mn>".to_string()
+                } else {
+                    // numbers lead, and are only kept apart from each other with a dot
+                    plain.sort_by_key(|f| !is_number(f));
+
+                    let mut output = String::new();
+
+                    for (i, factor) in plain.iter().enumerate() {
+                        if i > 0 {
+                            if is_number(factor) {
+                                output.push_str("<mo>&#xB7;</mo>");
+                            } else {
+                                output.push_str(INVISIBLE_TIMES);
+                            }
+                        }
+                        output.push_str(&presentation(factor, UNARY_LEVEL));
+                    }
+
+                    row(&output)
+                }
+            }
+            Concrete::Unary(Unary { argument, kind }) => match kind {
+                UnaryKind::Negation => row(&format!(
+                    "<mo>-</mo>{}",
+                    presentation(argument, POWER_LEVEL)
+                )),
+                UnaryKind::Reciprocal => {
+                    format!("<mfrac><mn>1</mn>{}</mfrac>", presentation(argument, 0))
+                }
+                UnaryKind::Named { id } => row(&format!(
+                    "{}{APPLY_FUNCTION}{}",
+                    function(id.as_ref()),
+                    bracketed(&presentation(argument, 0))
+                )),
+                UnaryKind::Variable { id, relations } => {
+                    let func = relations.iter().rev().fold(function(id.as_ref()), |f, r| {
+                        row(&format!(
+                            "{}{APPLY_FUNCTION}{}",
+                            function(r.as_ref()),
+                            bracketed(&f)
+                        ))
+                    });
+                    row(&format!(
+                        "{func}{APPLY_FUNCTION}{}",
+                        bracketed(&presentation(argument, 0))
+                    ))
+                }
+            },
+            Concrete::Power(Power { base, exponent }) => {
+                let half = Literal::new(BigRational::new(1.into(), 2.into())).into();
+
+                match base.as_ref() {
+                    _ if is_reciprocal(base) => {
+                        let denominator = denominator(expr).unwrap();
+                        format!("<mfrac><mn>1</mn>{}</mfrac>", presentation(&denominator, 0))
+                    }
+                    _ if **exponent == half => format!("<msqrt>{}</msqrt>", presentation(base, 0)),
+                    // written like sin^2(x)
+                    Expression::Concrete(Concrete::Unary(Unary {
+                        argument,
+                        kind: UnaryKind::Named { id },
+                    })) => row(&format!(
+                        "<msup>{}{}</msup>{APPLY_FUNCTION}{}",
+                        function(id.as_ref()),
+                        presentation(exponent, 0),
+                        bracketed(&presentation(argument, 0))
+                    )),
+                    _ => format!(
+                        "<msup>{}{}</msup>",
+                        presentation(base, ATOM_LEVEL),
+                        presentation(exponent, 0)
+                    ),
+                }
+            }
+            Concrete::Literal(Literal { value }) => match value {
+                LiteralValue::Integer(n) if n.is_negative() => {
+                    row(&format!("<mo>-</mo><mn>{}</mn>", n.abs()))
+                }
+                LiteralValue::Integer(n) => format!("<mn>{n}</mn>"),
+                LiteralValue::Rational(r) => {
+                    let fraction = format!(
+                        "<mfrac><mn>{}</mn><mn>{}</mn></mfrac>",
+                        r.numer().abs(),
+                        r.denom()
+                    );
+                    if r.is_negative() {
+                        row(&format!("<mo>-</mo>{fraction}"))
+                    } else {
+                        fraction
+                    }
+                }
+                LiteralValue::Constant(c) => {
+                    match KNOWN_CONSTANTS
+                        .iter()
+                        .find(|(constant, _, _)| constant == c)
+                    {
+                        Some((_, _, presentation)) => presentation.to_string(),
+                        None => format!("<mi mathvariant=\"normal\">{}</mi>", escape(c)),
+                    }
+                }
+            },
+        },
+        Expression::Variable(Variable { id, .. }) => match id.as_ref().as_str() {
+            REST => "<mo>&#x2026;</mo>".to_string(),
+            id => format!("<mi>{}</mi>", escape(id)),
+        },
+    };
+
+    if fraction_level(expr) < min_level {
+        bracketed(&output)
+    } else {
+        output
+    }
+}
+
+#[test]
+fn test_content_mathml() {
+    let cases = [
+        "(+ (^ (sin x) 2) (^ (cos x) 2))",
+        "(* 2 (/ (+ a b)) (- c) (f y))",
+        "(+ -3 1/2 PI E FOO)",
+        "(* (F u) ((cofunc (recip G)) u))",
+        "(+ ?n:int ?c:const ?x:var ..)",
+        "(* (+) (*))",
+    ];
+
+    for lisp in cases {
+        let expr = Lisp::parse(lisp).unwrap();
+        let mathml = ContentMathml::format(&expr);
+        assert_eq!(ContentMathml::parse(&mathml), Ok(expr), "{mathml}");
+    }
+
+    let parse = |s: &str| ContentMathml::parse(&format!("<math xmlns=\"{NAMESPACE}\">{s}</math>"));
+
+    assert_eq!(
+        parse("<apply><minus/><ci>a</ci><cn>2.5</cn></apply>"),
+        Ok(Lisp::parse("(+ a (- 5/2))").unwrap())
+    );
+    assert_eq!(
+        parse("<apply><divide/><cn>1</cn><apply><tan/><ci>x</ci></apply></apply>"),
+        Ok(Lisp::parse("(/ (tan x))").unwrap())
+    );
+    assert_eq!(
+        parse("<apply><factorial/><ci>n</ci></apply>"),
+        Err(MathmlParseError::UnsupportedElement(
+            "factorial".to_string()
+        ))
+    );
+    assert_eq!(
+        parse("<apply><power/><ci>x</ci></apply>"),
+        Err(MathmlParseError::IncorrectNumArgs("power".to_string()))
+    );
+    assert_eq!(parse("<apply/>"), Err(MathmlParseError::EmptyApply));
+    assert_eq!(
+        parse("<cn type=\"complex-cartesian\">1<sep/>2</cn>"),
+        Err(MathmlParseError::InvalidNumber("1".to_string()))
+    );
+    assert!(matches!(
+        ContentMathml::parse("<math><ci>x</math>"),
+        Err(MathmlParseError::Xml(_))
+    ));
+
+    let rule = ContentMathml::parse_equation(
+        "<apply><eq/><apply><times/><ci>a</ci><ci>a</ci></apply><apply><power/><ci>a</ci><cn>2</cn></apply></apply>",
+    )
+    .unwrap();
+    assert_eq!(
+        rule,
+        Lisp::parse_ruleset("(== (* a a) (^ a 2))").unwrap()[0]
+    );
+}
+
+#[test]
+fn test_presentation_mathml() {
+    let format = |s: &str| PresentationMathml::format(&Lisp::parse(s).unwrap());
+
+    assert_eq!(
+        format("(+ a (- b))"),
+        format!("<math xmlns=\"{NAMESPACE}\"><mrow><mi>a</mi><mo>-</mo><mi>b</mi></mrow></math>")
+    );
+    assert_eq!(
+        format("(* x x (/ (sin y)))"),
+        format!(
+            "<math xmlns=\"{NAMESPACE}\"><mfrac><msup><mi>x</mi><mn>2</mn></msup>\
+             <mrow><mi>sin</mi>{APPLY_FUNCTION}<mrow><mo>(</mo><mi>y</mi><mo>)</mo></mrow></mrow>\
+             </mfrac></math>"
+        )
+    );
+
+    // every expression is a single element, so it can go into an msup or mfrac directly
+    let expr = Lisp::parse("(^ (+ a (* 2 -1/2 b)) (- n))").unwrap();
+    let mathml = PresentationMathml::format(&expr);
+    assert!(Document::parse(&mathml).is_ok());
+    assert!(mathml.contains("<msup><mrow><mo>(</mo><mrow><mrow><mn>2</mn><mo>&#xB7;</mo>"));
+    assert!(mathml.contains("<mo>)</mo></mrow><mrow><mo>-</mo><mi>n</mi></mrow></msup>"));
+}
@@ -0,0 +1,197 @@
+use {crate::text::SourceToken, std::collections::VecDeque};
+
+// where a token or an error is in the source, lines and columns count from 1 and columns are
+// in characters, start and end are byte offsets
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+// tokens with their spans, parsers take them from the front
+#[derive(Debug, Default)]
+pub struct TokenStream {
+    tokens: VecDeque<(SourceToken, Span)>,
+    // the span of the last token taken, errors are usually about it
+    last: Span,
+    // empty span just past the end of the source
+    end: Span,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseError<K> {
+    pub kind: K,
+    pub span: Span,
+}
+
+impl Span {
+    pub fn new(source: &str, start: usize, end: usize) -> Self {
+        let before = &source[..start];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+        Span {
+            start,
+            end,
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+
+    // from the start of self to the end of other
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end: other.end.max(self.end),
+            ..self
+        }
+    }
+
+    pub fn error<K>(self, kind: K) -> ParseError<K> {
+        ParseError { kind, span: self }
+    }
+
+    // a span in one line of a larger source, moved to where that line starts at byte start
+    pub fn shift(self, start: usize, line: usize) -> Span {
+        Span {
+            start: self.start + start,
+            end: self.end + start,
+            line: self.line + line - 1,
+            column: self.column,
+        }
+    }
+}
+
+impl TokenStream {
+    pub fn new(source: &str) -> Self {
+        let end = Span::new(source, source.len(), source.len());
+
+        TokenStream {
+            tokens: VecDeque::new(),
+            last: end,
+            end,
+        }
+    }
+
+    pub fn push_back(&mut self, token: SourceToken, span: Span) {
+        self.tokens.push_back((token, span));
+    }
+
+    pub fn pop_front(&mut self) -> Option<SourceToken> {
+        match self.tokens.pop_front() {
+            Some((token, span)) => {
+                self.last = span;
+                Some(token)
+            }
+            None => {
+                self.last = self.end;
+                None
+            }
+        }
+    }
+
+    pub fn pop_back(&mut self) -> Option<SourceToken> {
+        self.tokens.pop_back().map(|(token, _)| token)
+    }
+
+    pub fn front(&self) -> Option<&SourceToken> {
+        self.get(0)
+    }
+
+    pub fn back(&self) -> Option<&SourceToken> {
+        self.tokens.back().map(|(token, _)| token)
+    }
+
+    pub fn get(&self, i: usize) -> Option<&SourceToken> {
+        self.tokens.get(i).map(|(token, _)| token)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    // span of the last token taken
+    pub fn span(&self) -> Span {
+        self.last
+    }
+
+    // error about the last token taken
+    pub fn error<K>(&self, kind: K) -> ParseError<K> {
+        self.last.error(kind)
+    }
+}
+
+impl IntoIterator for TokenStream {
+    type Item = (SourceToken, Span);
+    type IntoIter = std::collections::vec_deque::IntoIter<(SourceToken, Span)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.tokens.into_iter()
+    }
+}
+
+impl<K: std::fmt::Display> ParseError<K> {
+    // rustc style, with the source line and a caret under the span, origin names the source
+    pub fn diagnostic(&self, source: &str, origin: &str) -> String {
+        let Span {
+            start,
+            end,
+            line,
+            column,
+        } = self.span;
+
+        let text = source.lines().nth(line - 1).unwrap_or_default();
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        // spans over several lines are underlined up to the end of the first one
+        let width = source[start..end.min(line_start + text.len()).max(start)]
+            .chars()
+            .count()
+            .max(1);
+        let gutter = " ".repeat(line.to_string().len());
+
+        format!(
+            "error: {}\n{gutter}--> {origin}:{line}:{column}\n{gutter} |\n{line} | {text}\n{gutter} | {}{}",
+            self.kind,
+            " ".repeat(column - 1),
+            "^".repeat(width)
+        )
+    }
+}
+
+impl<K: std::fmt::Display> std::fmt::Display for ParseError<K> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} at {}:{}",
+            self.kind, self.span.line, self.span.column
+        )
+    }
+}
+
+#[test]
+fn test_spans() {
+    let source = "ab\n  cd é f";
+
+    assert_eq!(
+        Span::new(source, 5, 7),
+        Span {
+            start: 5,
+            end: 7,
+            line: 2,
+            column: 3
+        }
+    );
+    assert_eq!(Span::new(source, 11, 12).column, 8);
+
+    let error = Span::new(source, 5, 7).error("bad");
+    assert_eq!(
+        error.diagnostic(source, "test.lisp"),
+        "error: bad\n --> test.lisp:2:3\n  |\n2 |   cd é f\n  |   ^^"
+    );
+    assert_eq!(
+        Span::new(source, 12, 12)
+            .error("end")
+            .diagnostic(source, "x"),
+        "error: end\n --> x:2:9\n  |\n2 |   cd é f\n  |         ^"
+    );
+}